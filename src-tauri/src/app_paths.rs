@@ -0,0 +1,183 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The directory name used below the platform directories, the bundle identifier like Tauri itself uses.
+const APP_DIRECTORY: &str = "chase.cargo.sftp-editor";
+
+/// The command line flag that keeps all state beside the executable, e.g. for installs on a USB stick.
+const PORTABLE_FLAG: &str = "--portable";
+
+/// The environment variable that enables portable mode like `--portable` if it is set to anything but `0`.
+const PORTABLE_VARIABLE: &str = "SFTP_EDITOR_PORTABLE";
+
+/// The files that older versions stored beside the executable and where they are kept now.
+const LEGACY_FILES: &[(&str, Location)] = &[
+	("connections.db", Location::Data),
+	("known_hosts", Location::Data),
+	("app_settings.json", Location::Config),
+];
+
+/// The directories persistent state is stored in, resolved once per process.
+static DIRECTORIES: OnceLock<Directories> = OnceLock::new();
+
+/// Which of the directories a file belongs in.
+#[derive(Clone, Copy)]
+enum Location {
+	/// State the application writes, e.g. the connection database.
+	Data,
+	/// Settings the user chose.
+	Config,
+}
+
+/// Where persistent state is stored.
+struct Directories {
+	data: PathBuf,
+	config: PathBuf,
+	/// Local copies of remote files, created on first use by `cache_directory`.
+	cache: PathBuf,
+}
+
+impl Directories {
+	fn get(&self, location: Location) -> &Path {
+		match location {
+			Location::Data => &self.data,
+			Location::Config => &self.config,
+		}
+	}
+}
+
+/// Returns the directory of the executable, or the working directory if it can not be determined.
+fn executable_directory() -> PathBuf {
+	match std::env::current_exe() {
+		Ok(exe_path) => exe_path.parent().map(Path::to_path_buf).unwrap_or_default(),
+		Err(e) => {
+			eprintln!("Failed to get current exe path: {}", e);
+			PathBuf::new()
+		}
+	}
+}
+
+/// Checks whether portable mode was requested on the command line or through the environment.
+fn is_portable() -> bool {
+	std::env::args().any(|argument| argument == PORTABLE_FLAG)
+		|| std::env::var(PORTABLE_VARIABLE).map(|value| !value.is_empty() && value != "0").unwrap_or(false)
+}
+
+/// Resolves the directories, creates them and moves the files older versions stored beside the executable.
+///
+/// Errors are printed instead of returned, state that can not be stored in the user directories falls back to
+/// the directory of the executable like before.
+fn resolve() -> Directories {
+	let executable = executable_directory();
+	let beside_executable = || Directories { data: executable.clone(), config: executable.clone(), cache: executable.join("cache") };
+	if is_portable() {
+		return beside_executable();
+	}
+
+	// On Linux these follow XDG_DATA_HOME and XDG_CONFIG_HOME, on Windows both are the roaming app data
+	let data = tauri::api::path::data_dir().map(|directory| directory.join(APP_DIRECTORY));
+	let config = tauri::api::path::config_dir().map(|directory| directory.join(APP_DIRECTORY));
+	let (data, config) = match (data, config) {
+		(Some(data), Some(config)) => (data, config),
+		_ => {
+			eprintln!("Failed to determine the user data directories, storing state beside the executable");
+			return beside_executable();
+		}
+	};
+	for directory in [&data, &config] {
+		if let Err(e) = fs::create_dir_all(directory) {
+			eprintln!("Failed to create {}: {}, storing state beside the executable", directory.display(), e);
+			return beside_executable();
+		}
+	}
+	let cache = tauri::api::path::cache_dir().map(|directory| directory.join(APP_DIRECTORY)).unwrap_or_else(|| data.join("cache"));
+
+	let directories = Directories { data, config, cache };
+	for (name, location) in LEGACY_FILES {
+		let target = directories.get(*location).join(name);
+		if let Err(e) = migrate_file(&executable.join(name), &target) {
+			eprintln!("Failed to move {} to {}: {}", name, target.display(), e);
+		}
+	}
+	directories
+}
+
+/// Moves a file from beside the executable to its new location, unless a file already exists there.
+///
+/// The file is copied if it can not be moved, e.g. across file systems, and the original is left in place if the
+/// install directory is read-only.
+fn migrate_file(legacy: &Path, target: &Path) -> io::Result<()> {
+	if !legacy.is_file() || target.exists() {
+		return Ok(());
+	}
+	if fs::rename(legacy, target).is_ok() {
+		return Ok(());
+	}
+	fs::copy(legacy, target)?;
+	let _ = fs::remove_file(legacy);
+	Ok(())
+}
+
+/// Resolves the directories and moves state stored by older versions, called once at startup before anything is read.
+///
+/// # Returns
+///
+/// The directory the connection database is stored in.
+pub fn initialize() -> PathBuf {
+	DIRECTORIES.get_or_init(resolve).data.clone()
+}
+
+/// Returns the path of a file the application writes, e.g. `connections.db`.
+pub fn data_file(name: &str) -> PathBuf {
+	DIRECTORIES.get_or_init(resolve).data.join(name)
+}
+
+/// Returns the path of a settings file, e.g. `app_settings.json`.
+pub fn config_file(name: &str) -> PathBuf {
+	DIRECTORIES.get_or_init(resolve).config.join(name)
+}
+
+/// Returns a directory below the cache directory, e.g. for the local copies of edited files.
+///
+/// The directory and any missing parents are created so only the current user can access them, as the cache holds
+/// copies of remote files.
+pub fn cache_directory(name: &str) -> io::Result<PathBuf> {
+	let cache = &DIRECTORIES.get_or_init(resolve).cache;
+	create_private_directory(cache)?;
+	// The cache directory may have been created by an older version with the default permissions
+	#[cfg(unix)]
+	fs::set_permissions(cache, std::os::unix::fs::PermissionsExt::from_mode(0o700))?;
+
+	let directory = cache.join(name);
+	create_private_directory(&directory)?;
+	Ok(directory)
+}
+
+/// Creates a directory and any missing parents, on Unix only the current user can access the created directories.
+pub fn create_private_directory(path: &Path) -> io::Result<()> {
+	let mut builder = fs::DirBuilder::new();
+	builder.recursive(true);
+	#[cfg(unix)]
+	std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+	builder.create(path)
+}
+
+/// Returns options that create or truncate a file for writing, on Unix a new file can only be read and written by
+/// the current user.
+pub fn private_file_options() -> fs::OpenOptions {
+	let mut options = fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+	options
+}
+
+/// Creates or truncates a file, see `private_file_options`. An existing file is restricted to the current user as well.
+pub fn create_private_file(path: &Path) -> io::Result<fs::File> {
+	let file = private_file_options().open(path)?;
+	#[cfg(unix)]
+	file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+	Ok(file)
+}
@@ -1,11 +1,19 @@
-use std::env;
 use std::fs::File;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use crate::app_paths;
+use crate::error::BackendError;
+
 #[derive(Serialize, Deserialize)]
 pub struct AppSettings {
     general_settings: GeneralSettings,
+	// Settings files written by older versions have no network or transfer settings yet
+    #[serde(default = "default_network_settings")]
+    network_settings: NetworkSettings,
+    #[serde(default = "default_transfer_settings")]
+    transfer_settings: TransferSettings,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -31,6 +39,52 @@ pub struct Panel {
     visible: bool,
 }
 
+/// Timeouts of the connections to servers, in seconds, `0` waits as long as the operating system does.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct NetworkSettings {
+    connect_timeout: u64,
+    read_timeout: u64,
+}
+
+impl NetworkSettings {
+    /// How long to wait for the TCP connection to a server to be established.
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.connect_timeout)).filter(|timeout| !timeout.is_zero())
+    }
+
+    /// How long to wait for a server to answer before a blocking operation fails with a `Timeout` error.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.read_timeout)).filter(|timeout| !timeout.is_zero())
+    }
+}
+
+const DEFAULT_NETWORK_SETTINGS: NetworkSettings = NetworkSettings {
+    connect_timeout: 15,
+    read_timeout: 60,
+};
+
+fn default_network_settings() -> NetworkSettings {
+    DEFAULT_NETWORK_SETTINGS
+}
+
+/// How the transfer queue runs its transfers.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct TransferSettings {
+    /// How many transfers of the same connection run at once, each on its own session.
+    pub parallel_transfers: usize,
+    /// How often a failed transfer is retried before it is marked as failed.
+    pub max_retries: u32,
+}
+
+const DEFAULT_TRANSFER_SETTINGS: TransferSettings = TransferSettings {
+    parallel_transfers: 2,
+    max_retries: 3,
+};
+
+fn default_transfer_settings() -> TransferSettings {
+    DEFAULT_TRANSFER_SETTINGS
+}
+
 const DEFAULT_SETTINGS: AppSettings = AppSettings {
     general_settings: GeneralSettings {
         dark_mode: true,
@@ -62,6 +116,8 @@ const DEFAULT_SETTINGS: AppSettings = AppSettings {
             },
         },
     },
+    network_settings: DEFAULT_NETWORK_SETTINGS,
+    transfer_settings: DEFAULT_TRANSFER_SETTINGS,
 };
 
 /// Get the application settings.
@@ -75,9 +131,9 @@ const DEFAULT_SETTINGS: AppSettings = AppSettings {
 /// ```
 ///
 /// # Return
-/// The application settings as an `AppSettings` struct.
+/// The application settings as an `AppSettings` struct, or a `BackendError` if the file cannot be read or parsed.
 #[tauri::command()]
-pub fn get_settings() -> AppSettings {
+pub fn get_settings() -> Result<AppSettings, BackendError> {
 	// Importing Read from std::io
     use std::io::Read;
 
@@ -85,20 +141,23 @@ pub fn get_settings() -> AppSettings {
     let file = get_settings_path();
 
 	// Try to open the file. If the file does not exist, create a default settings file 
-	// and then open the file, if it fails for any reason, the program will panic and print the respective error message
-    let mut file = File::open(&file).unwrap_or_else(|_| {
-        save_settings(DEFAULT_SETTINGS);
-        File::open(&file).expect("Failed to open file")
-    });
+	// and then open the file, if it fails for any reason, the error is returned to the caller
+    let mut file = match File::open(&file) {
+        Ok(file) => file,
+        Err(_) => {
+            save_settings(DEFAULT_SETTINGS)?;
+            File::open(&file).map_err(|e| BackendError::io("Failed to open settings file", &e))?
+        }
+    };
 
 	// String to store the contents of the file
     let mut serialized = String::new();
 
 	// Read the contents of the file into the string variable 'serialized'
-    file.read_to_string(&mut serialized).expect("Failed to read file");
+    file.read_to_string(&mut serialized).map_err(|e| BackendError::io("Failed to read settings file", &e))?;
 
 	// Deserialize the contents of the file from json format into the corresponding rust data structures and return
-    return serde_json::from_str(&serialized).expect("Failed to deserialize");
+    serde_json::from_str(&serialized).map_err(|e| BackendError::invalid_input(format!("Failed to deserialize settings: {}", e)))
 }
 
 
@@ -108,9 +167,9 @@ pub fn get_settings() -> AppSettings {
 ///
 /// * `settings` - The application settings to be saved.
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function may panic in the following situations:
+/// This function returns an error in the following situations:
 ///
 /// * Failed to serialize the settings into a JSON string.
 /// * Failed to create the file.
@@ -128,7 +187,7 @@ pub fn get_settings() -> AppSettings {
 /// save_settings(settings);
 /// ```
 #[tauri::command()]
-pub fn save_settings(settings: AppSettings) {
+pub fn save_settings(settings: AppSettings) -> Result<(), BackendError> {
 	// Importing Write from std::io
     use std::io::Write;
 
@@ -136,79 +195,33 @@ pub fn save_settings(settings: AppSettings) {
     let file = get_settings_path();
 
 	// Serialize application settings in a pretty way (with indentations and line breaks) to a JSON string
-    let serialized = serde_json::to_string_pretty(&settings).expect("Failed to serialize");
+    let serialized = serde_json::to_string_pretty(&settings).map_err(|e| BackendError::other(format!("Failed to serialize settings: {}", e)))?;
 
 	// Create a file (overwrite if it exists) at the given path and store the reference to the file in variable 'file'
-    let mut file = File::create(file).expect("Failed to create file");
+    let mut file = File::create(file).map_err(|e| BackendError::io("Failed to create settings file", &e))?;
 
 	// Write all bytes in serialized to the file in one go
-    file.write_all(serialized.as_bytes()).expect("Failed to write to file");
+    file.write_all(serialized.as_bytes()).map_err(|e| BackendError::io("Failed to write settings file", &e))
 }
 
 /// Retrieves the path to the application settings file.
 ///
+/// The file is stored in the config directory of the user, or beside the executable in portable mode.
+/// See `app_paths` for how the directory is chosen.
+///
 /// # Returns
 ///
 /// The path to the application settings file as a `String`.
-///
-/// # Errors
-///
-/// This function may fail if it is unable to determine the current executable path or if it is
-/// unable to retrieve the parent directory of the executable path.
-///
-/// # Examples
-///
-/// ```
-/// use std::env;
-///
-/// fn get_settings_path() -> String {
-///     // Try to get the current executable path
-///     let exe_path = match env::current_exe() {
-///         Ok(exe_path) => exe_path,
-///         Err(e) => {
-///             // Print the exception and return the default file path if it failed
-///             eprintln!("Failed to get current exe path: {}", e);
-///             return "app_settings.json".to_string();
-///         }
-///     };
-///
-///     // Try to get the parent directory of the current executable path
-///     let exe_directory = match exe_path.parent() {
-///         Some(dir) => dir,
-///         None => {
-///             // Print the exception and return the default file path if it failed
-///             eprintln!("Failed to get directory of the exe");
-///             return "app_settings.json".to_string();
-///         }
-///     };
-///
-///     // Create new path by joining the directory path with the settings file name,
-///     // convert to string and return
-///     return exe_directory.join("app_settings.json").to_str()
-///         .expect("Failed to convert path to string").to_string();
-/// }
-/// ```
 fn get_settings_path() -> String {
-	// Try to get the current executable path
-    let exe_path = match env::current_exe() {
-        Ok(exe_path) => exe_path,
-        Err(e) => {
-			// Print the exception and return the default file path if it failed
-            eprintln!("Failed to get current exe path: {}", e);
-            return "app_settings.json".to_string();
-        }
-    };
+    app_paths::config_file("app_settings.json").to_string_lossy().to_string()
+}
 
-	// Try to get the parent directory of the current executable path
-    let exe_directory = match exe_path.parent() {
-        Some(dir) => dir,
-        None => {
-			// Print the exception and return the default file path if it failed
-            eprintln!("Failed to get directory of the exe");
-            return "app_settings.json".to_string();
-        }
-    };
+/// Returns the network settings, falling back to the defaults if the settings file can not be read.
+pub fn network_settings() -> NetworkSettings {
+    get_settings().map(|settings| settings.network_settings).unwrap_or(DEFAULT_NETWORK_SETTINGS)
+}
 
-	// Create new path by joining the directory path with the settings file name, convert to string and return
-    return exe_directory.join("app_settings.json").to_str().unwrap().to_string();
-}
\ No newline at end of file
+/// Returns the transfer queue settings, falling back to the defaults if the settings file can not be read.
+pub fn transfer_settings() -> TransferSettings {
+    get_settings().map(|settings| settings.transfer_settings).unwrap_or(DEFAULT_TRANSFER_SETTINGS)
+}
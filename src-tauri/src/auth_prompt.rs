@@ -0,0 +1,139 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::connection_manager::Connection;
+use crate::error::BackendError;
+
+/// How long to wait for the user to answer a prompt before authentication fails.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The application handle, set once during setup.
+///
+/// Connections are opened from places that have no access to the application handle, e.g. the session pool,
+/// so prompts reach the frontend through this handle instead.
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+thread_local! {
+	/// Whether connections opened on this thread may ask the user, see `allow_prompts`.
+	static PROMPTS_ALLOWED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A single question of an authentication prompt.
+#[derive(Clone, serde::Serialize)]
+pub struct AuthPromptField {
+	pub text: String,
+	/// Whether the answer may be shown while typing, `false` for passwords and one-time codes.
+	pub echo: bool,
+}
+
+/// Emitted as `auth-prompt` when the server asks questions during authentication, e.g. for a 2FA code.
+///
+/// The frontend answers with `answer_auth_prompt` using the same id.
+#[derive(Clone, serde::Serialize)]
+pub struct AuthPrompt {
+	id: u64,
+	connection_id: i32,
+	host: String,
+	username: String,
+	instructions: String,
+	prompts: Vec<AuthPromptField>,
+}
+
+/// Keeps track of the prompts that are waiting for an answer from the user.
+#[derive(Default)]
+pub struct AuthPromptManager {
+	next_id: AtomicU64,
+	/// `None` is sent when the user cancels the prompt.
+	pending: Mutex<HashMap<u64, Sender<Option<Vec<String>>>>>,
+}
+
+impl AuthPromptManager {
+	/// Creates a prompt manager without any pending prompts.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+/// Stores the application handle so prompts can be emitted, called once during setup.
+pub fn init(app: AppHandle) {
+	let _ = APP.set(app);
+}
+
+/// Runs a task that may ask the user to answer authentication prompts for the connections it opens.
+///
+/// A prompt blocks until the answer arrives through `answer_auth_prompt`, so prompts are only allowed where the
+/// caller knows that does not block the command handling, e.g. on the thread pool of `run_blocking` or on a
+/// transfer thread. Anywhere else, e.g. in synchronous commands, the authentication fails instead.
+pub fn allow_prompts<R>(task: impl FnOnce() -> R) -> R {
+	let previous = PROMPTS_ALLOWED.with(|allowed| allowed.replace(true));
+	let result = task();
+	PROMPTS_ALLOWED.with(|allowed| allowed.set(previous));
+	result
+}
+
+/// Asks the user to answer the questions of the server and waits for the answer.
+///
+/// # Arguments
+///
+/// * `options` - The connection that is being authenticated.
+/// * `instructions` - Informational text sent by the server.
+/// * `prompts` - The questions to answer.
+///
+/// # Returns
+///
+/// One answer per question, or an error if the user cancelled or did not answer in time.
+pub fn ask(options: &Connection, instructions: &str, prompts: Vec<AuthPromptField>) -> Result<Vec<String>, BackendError> {
+	if !PROMPTS_ALLOWED.with(Cell::get) {
+		return Err(BackendError::auth_failed("The server asks for additional authentication, open the session with open_session first"));
+	}
+	let app = APP.get().ok_or_else(|| BackendError::other("Authentication prompts are not available"))?;
+	let manager = app.state::<AuthPromptManager>();
+
+	let id = manager.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+	let (sender, receiver) = mpsc::channel();
+	manager.pending.lock().map_err(|e| BackendError::other(format!("Prompt state is poisoned: {:?}", e)))?.insert(id, sender);
+
+	let prompt = AuthPrompt {
+		id,
+		connection_id: options.id,
+		host: options.host.clone(),
+		username: options.username.clone(),
+		instructions: instructions.to_string(),
+		prompts,
+	};
+	let result = match app.emit_all("auth-prompt", prompt) {
+		Ok(()) => match receiver.recv_timeout(PROMPT_TIMEOUT) {
+			Ok(Some(answers)) => Ok(answers),
+			Ok(None) => Err(BackendError::cancelled("Authentication was cancelled")),
+			Err(RecvTimeoutError::Timeout) => Err(BackendError::timeout("Authentication prompt timed out")),
+			Err(RecvTimeoutError::Disconnected) => Err(BackendError::cancelled("Authentication prompt was dropped")),
+		},
+		Err(e) => Err(BackendError::other(format!("Error emitting authentication prompt: {:?}", e))),
+	};
+
+	if let Ok(mut pending) = manager.pending.lock() {
+		pending.remove(&id);
+	}
+	result
+}
+
+/// Answers a prompt received through an `auth-prompt` event.
+///
+/// # Arguments
+///
+/// * `id` - The id of the prompt.
+/// * `answers` - One answer per question, or `None` to cancel the authentication.
+#[tauri::command]
+pub fn answer_auth_prompt(id: u64, answers: Option<Vec<String>>, prompts: State<AuthPromptManager>) -> Result<(), BackendError> {
+	let pending = prompts.pending.lock().map_err(|e| BackendError::other(format!("Prompt state is poisoned: {:?}", e)))?;
+	match pending.get(&id) {
+		Some(sender) => sender.send(answers).map_err(|_| BackendError::other(format!("Prompt {} is no longer waiting", id))),
+		None => Err(BackendError::not_found(format!("No prompt with id: {}", id))),
+	}
+}
@@ -0,0 +1,466 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::app_paths;
+use crate::connection_manager::{get_connection_by_id, get_connections, insert_connection, update_connection, Connection};
+use crate::credential_store;
+use crate::error::BackendError;
+use crate::importer::{self, ImportAction, ImportCandidate, ImportPreview};
+
+/// The bundle format written by this version, bundles written by newer versions are rejected.
+const BUNDLE_VERSION: u32 = 1;
+
+/// Known plaintext encrypted with the passphrase key, used to reject a wrong passphrase before importing anything.
+const BUNDLE_VERIFIER: &str = "sftp-editor-bundle";
+
+/// The JSON document connections are exported to.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Bundle {
+	version: u32,
+	exported_at: String,
+	/// Set if the secrets in the bundle are encrypted with a passphrase, otherwise they are left out.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	encryption: Option<BundleEncryption>,
+	connections: Vec<BundleConnection>,
+}
+
+/// How the secrets of a bundle are encrypted.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BundleEncryption {
+	/// The base64 encoded salt the key is derived from the passphrase with, using Argon2id.
+	salt: String,
+	/// `BUNDLE_VERIFIER` encrypted with the key.
+	verifier: String,
+}
+
+/// A connection as stored in a bundle, timestamps and the default flag only make sense on the exporting machine.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BundleConnection {
+	/// The id on the exporting machine, used to match connections by id and to refer to jump hosts.
+	id: i32,
+	name: String,
+	host: String,
+	port: i32,
+	username: String,
+	#[serde(default)]
+	password: String,
+	#[serde(default)]
+	private_key: String,
+	#[serde(default)]
+	remote_path: String,
+	#[serde(default)]
+	local_path: String,
+	protocol: i8,
+	#[serde(default)]
+	auth_method: i8,
+	#[serde(default)]
+	private_key_path: String,
+	/// The id of another connection in the same bundle.
+	#[serde(default)]
+	jump_host_id: Option<i32>,
+}
+
+/// What to do with a bundle connection that matches an existing connection.
+#[derive(Clone, Copy, PartialEq, serde::Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictMode {
+	/// Keep the existing connection.
+	Skip,
+	/// Replace the existing connection with the one from the bundle.
+	Overwrite,
+	/// Import the connection next to the existing one under a new name.
+	Duplicate,
+}
+
+/// How bundle connections are matched against existing connections.
+#[derive(Clone, Copy, PartialEq, serde::Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchBy {
+	/// Matches the id, for bundles exported from the same database, e.g. a backup.
+	Id,
+	/// Matches the name, for bundles shared between machines.
+	Name,
+}
+
+/// A bundle connection and what the import does with it.
+struct PlannedImport {
+	candidate: ImportCandidate,
+	action: ImportAction,
+	/// The id of the bundle connection.
+	bundle_id: i32,
+	/// The id of the bundle connection to tunnel through.
+	bundle_jump_host_id: Option<i32>,
+	/// The existing connection that is kept or overwritten.
+	target: Option<Connection>,
+}
+
+/// Returns the name with the lowest ` (n)` suffix that is not taken yet.
+fn unique_name(name: &str, taken: &HashSet<String>) -> String {
+	(2..).map(|number| format!("{} ({})", name, number)).find(|candidate| !taken.contains(candidate)).unwrap_or_default()
+}
+
+/// Reads a bundle and decrypts its secrets.
+///
+/// # Arguments
+///
+/// * `path` - The bundle file.
+/// * `passphrase` - The passphrase the secrets are encrypted with, required for encrypted bundles.
+fn read_bundle(path: &str, passphrase: Option<String>) -> Result<Vec<BundleConnection>, BackendError> {
+	let content = fs::read_to_string(path).map_err(|e| BackendError::io(&format!("Error reading {}", path), &e))?;
+	let bundle: Bundle = serde_json::from_str(&content).map_err(|e| BackendError::invalid_input(format!("Error parsing connection bundle: {}", e)))?;
+	if bundle.version > BUNDLE_VERSION {
+		return Err(BackendError::invalid_input(format!("The connection bundle has version {}, this version can only import up to version {}", bundle.version, BUNDLE_VERSION)));
+	}
+
+	let mut connections = bundle.connections;
+	if let Some(encryption) = bundle.encryption {
+		let passphrase = passphrase.filter(|passphrase| !passphrase.is_empty()).ok_or_else(|| BackendError::invalid_input("The connection bundle is encrypted, a passphrase is required"))?;
+		let salt = STANDARD.decode(&encryption.salt).map_err(|e| BackendError::invalid_input(format!("Failed to decode salt: {}", e)))?;
+		let key = credential_store::derive_key(&passphrase, &salt)?;
+		match credential_store::decrypt_with(&key, &encryption.verifier) {
+			Ok(value) if value == BUNDLE_VERIFIER => (),
+			_ => return Err(BackendError::auth_failed("Wrong passphrase")),
+		}
+		for connection in connections.iter_mut() {
+			connection.password = credential_store::decrypt_with(&key, &connection.password)?;
+			connection.private_key = credential_store::decrypt_with(&key, &connection.private_key)?;
+		}
+	}
+	Ok(connections)
+}
+
+/// Decides what to do with every bundle connection, without changing anything.
+fn plan(connections: Vec<BundleConnection>, conflict: ConflictMode, match_by: MatchBy) -> Result<Vec<PlannedImport>, BackendError> {
+	let existing = get_connections(None)?;
+	let names: HashMap<i32, String> = connections.iter().map(|connection| (connection.id, connection.name.clone())).collect();
+	let mut taken: HashSet<String> = existing.iter().map(|connection| connection.name.clone()).collect();
+
+	let mut planned = Vec::new();
+	for entry in connections {
+		let mut connection = importer::new_connection(&entry.name, &entry.host, entry.port, &entry.username);
+		connection.password = entry.password;
+		connection.private_key = entry.private_key;
+		connection.remote_path = entry.remote_path;
+		connection.local_path = entry.local_path;
+		connection.protocol = entry.protocol;
+		connection.auth_method = entry.auth_method;
+		connection.private_key_path = entry.private_key_path;
+
+		let current = existing.iter().find(|current| match match_by {
+			MatchBy::Id => current.id == entry.id,
+			MatchBy::Name => current.name == entry.name,
+		});
+		let (action, target) = match (current, conflict) {
+			(None, _) => (ImportAction::Add, None),
+			(Some(current), ConflictMode::Skip) => (ImportAction::Skip, Some(current.clone())),
+			(Some(current), ConflictMode::Overwrite) => (ImportAction::Update, Some(current.clone())),
+			(Some(_), ConflictMode::Duplicate) => {
+				connection.name = unique_name(&entry.name, &taken);
+				(ImportAction::Add, None)
+			}
+		};
+		taken.insert(connection.name.clone());
+
+		planned.push(PlannedImport {
+			candidate: ImportCandidate {
+				connection,
+				jump_host: entry.jump_host_id.and_then(|id| names.get(&id).cloned()),
+				warning: None,
+			},
+			action,
+			bundle_id: entry.id,
+			bundle_jump_host_id: entry.jump_host_id,
+			target,
+		});
+	}
+	Ok(planned)
+}
+
+/// Adds and overwrites the planned connections and links them to their jump hosts.
+fn apply(planned: &[PlannedImport]) -> Result<(), BackendError> {
+	// Maps the ids of the bundle to the ids of the connections they ended up as
+	let mut ids: HashMap<i32, i32> = HashMap::new();
+	for entry in planned {
+		let mut connection = entry.candidate.connection.clone();
+		match (entry.action, &entry.target) {
+			(ImportAction::Add, _) => {
+				ids.insert(entry.bundle_id, insert_connection(connection)?);
+			}
+			(ImportAction::Update, Some(target)) => {
+				// Empty secrets keep the stored ones, e.g. for bundles exported without secrets
+				connection.id = target.id;
+				connection.default = target.default;
+				connection.jump_host_id = target.jump_host_id;
+				update_connection(target.id, connection, None, None)?;
+				ids.insert(entry.bundle_id, target.id);
+			}
+			(_, Some(target)) => {
+				ids.insert(entry.bundle_id, target.id);
+			}
+			(_, None) => (),
+		}
+	}
+
+	// Link the jump hosts now that every connection has an id, jump hosts outside of the bundle are dropped
+	for entry in planned.iter().filter(|entry| matches!(entry.action, ImportAction::Add | ImportAction::Update)) {
+		let id = match ids.get(&entry.bundle_id) {
+			Some(id) => *id,
+			None => continue,
+		};
+		let jump_host_id = entry.bundle_jump_host_id.and_then(|jump_host_id| ids.get(&jump_host_id).copied());
+		let mut connection = get_connection_by_id(id, None)?;
+		if connection.jump_host_id != jump_host_id {
+			connection.jump_host_id = jump_host_id;
+			update_connection(id, connection, None, None)?;
+		}
+	}
+	Ok(())
+}
+
+/// Exports connections to a versioned JSON bundle that can be imported on another machine.
+///
+/// # Arguments
+///
+/// * `path` - The file to write the bundle to.
+/// * `ids` - The connections to export, all connections are exported if not given.
+/// * `include_secrets` - Whether to export passwords and private keys, requires unlocked credentials and a passphrase.
+/// * `passphrase` - The passphrase to encrypt the secrets with, secrets are never written as plaintext.
+///
+/// # Returns
+///
+/// The number of exported connections.
+#[tauri::command]
+pub fn export_connections(path: String, ids: Option<Vec<i32>>, include_secrets: bool, passphrase: Option<String>) -> Result<usize, BackendError> {
+	let mut connections = get_connections(Some(include_secrets))?;
+	if let Some(ids) = ids {
+		connections.retain(|connection| ids.contains(&connection.id));
+	}
+	let exported: HashSet<i32> = connections.iter().map(|connection| connection.id).collect();
+
+	// The key is independent of the master password, so the bundle can be opened anywhere
+	let mut encryption = None;
+	let mut key = None;
+	if include_secrets {
+		let passphrase = passphrase.filter(|passphrase| !passphrase.is_empty()).ok_or_else(|| BackendError::invalid_input("A passphrase is required to export passwords and private keys"))?;
+		let salt = credential_store::generate_salt();
+		let derived = credential_store::derive_key(&passphrase, &salt)?;
+		encryption = Some(BundleEncryption {
+			salt: STANDARD.encode(salt),
+			verifier: credential_store::encrypt_with(&derived, BUNDLE_VERIFIER)?,
+		});
+		key = Some(derived);
+	}
+
+	let mut entries = Vec::new();
+	for connection in connections {
+		let (password, private_key) = match &key {
+			Some(key) => (credential_store::encrypt_with(key, &connection.password)?, credential_store::encrypt_with(key, &connection.private_key)?),
+			// Without a key the secrets were not requested, plaintext secrets are never exported
+			None => (String::new(), String::new()),
+		};
+		entries.push(BundleConnection {
+			id: connection.id,
+			name: connection.name,
+			host: connection.host,
+			port: connection.port,
+			username: connection.username,
+			password,
+			private_key,
+			remote_path: connection.remote_path,
+			local_path: connection.local_path,
+			protocol: connection.protocol,
+			auth_method: connection.auth_method,
+			private_key_path: connection.private_key_path,
+			// A jump host that is not exported can not be linked on import
+			jump_host_id: connection.jump_host_id.filter(|id| exported.contains(id)),
+		});
+	}
+
+	let count = entries.len();
+	let bundle = Bundle {
+		version: BUNDLE_VERSION,
+		exported_at: chrono::Local::now().to_rfc3339(),
+		encryption,
+		connections: entries,
+	};
+	let content = serde_json::to_string_pretty(&bundle).map_err(|e| BackendError::other(format!("Error serializing connection bundle: {}", e)))?;
+	// Even without secrets a bundle reveals hosts and usernames, so only the current user may read it
+	app_paths::create_private_file(path.as_ref())
+		.and_then(|mut file| file.write_all(content.as_bytes()))
+		.map_err(|e| BackendError::io(&format!("Error writing {}", path), &e))?;
+	Ok(count)
+}
+
+/// Imports the connections of a bundle written by `export_connections`.
+///
+/// # Arguments
+///
+/// * `path` - The bundle file.
+/// * `passphrase` - The passphrase the secrets are encrypted with, required for encrypted bundles.
+/// * `conflict` - What to do with connections that already exist: `skip`, `overwrite` or `duplicate`.
+/// * `match_by` - Whether existing connections are matched by `id` or by `name`, defaults to `name`.
+/// * `commit` - `false` only returns the preview, `true` imports the connections.
+///
+/// # Returns
+///
+/// What is, or would be, done with every connection of the bundle.
+#[tauri::command]
+pub fn import_connections(path: String, passphrase: Option<String>, conflict: ConflictMode, match_by: Option<MatchBy>, commit: bool) -> Result<Vec<ImportPreview>, BackendError> {
+	let connections = read_bundle(&path, passphrase)?;
+	let planned = plan(connections, conflict, match_by.unwrap_or(MatchBy::Name))?;
+	if commit {
+		apply(&planned)?;
+	}
+	Ok(planned.iter().map(|entry| importer::describe(&entry.candidate, entry.action)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::connection_manager::tests::{connection, TestDatabase};
+
+	#[test]
+	fn secrets_are_only_exported_with_a_passphrase() {
+		let _database = TestDatabase::new("bundle-passphrase");
+		insert_connection(connection("server")).unwrap();
+		let path = std::env::temp_dir().join(format!("bundle-test-{}.json", std::process::id())).to_string_lossy().to_string();
+
+		assert!(export_connections(path.clone(), None, true, None).is_err());
+		assert!(export_connections(path.clone(), None, true, Some(String::new())).is_err());
+
+		export_connections(path.clone(), None, false, None).unwrap();
+		let content = fs::read_to_string(&path).unwrap();
+		#[cfg(unix)]
+		assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+		let _ = fs::remove_file(&path);
+		let bundle: Bundle = serde_json::from_str(&content).unwrap();
+		assert!(bundle.encryption.is_none());
+		assert_eq!(bundle.connections[0].password, "");
+	}
+
+	#[test]
+	fn encrypted_secrets_round_trip() {
+		let _database = TestDatabase::new("bundle-round-trip");
+		insert_connection(connection("server")).unwrap();
+		let path = std::env::temp_dir().join(format!("bundle-round-trip-{}.json", std::process::id())).to_string_lossy().to_string();
+
+		export_connections(path.clone(), None, true, Some("passphrase".to_string())).unwrap();
+		let wrong = read_bundle(&path, Some("wrong".to_string()));
+		let connections = read_bundle(&path, Some("passphrase".to_string()));
+		let _ = fs::remove_file(&path);
+
+		assert!(matches!(wrong, Err(BackendError::AuthFailed { .. })));
+		assert_eq!(connections.unwrap()[0].password, "password");
+	}
+
+	/// A connection of a bundle exported without secrets.
+	fn bundled(id: i32, name: &str, jump_host_id: Option<i32>) -> BundleConnection {
+		BundleConnection {
+			id,
+			name: name.to_string(),
+			host: "bundle.example.com".to_string(),
+			port: 2222,
+			username: "bundle".to_string(),
+			password: String::new(),
+			private_key: String::new(),
+			remote_path: String::new(),
+			local_path: String::new(),
+			protocol: 0,
+			auth_method: 0,
+			private_key_path: String::new(),
+			jump_host_id,
+		}
+	}
+
+	/// Plans and applies an import, returning what was done with every bundle connection.
+	fn import(connections: Vec<BundleConnection>, conflict: ConflictMode, match_by: MatchBy) -> Vec<ImportAction> {
+		let planned = plan(connections, conflict, match_by).unwrap();
+		apply(&planned).unwrap();
+		planned.iter().map(|entry| entry.action).collect()
+	}
+
+	fn stored(name: &str) -> Connection {
+		get_connections(Some(true)).unwrap().into_iter().find(|connection| connection.name == name).unwrap()
+	}
+
+	#[test]
+	fn conflicts_are_skipped() {
+		let _database = TestDatabase::new("bundle-skip");
+		insert_connection(connection("server")).unwrap();
+
+		assert_eq!(import(vec![bundled(1, "server", None)], ConflictMode::Skip, MatchBy::Name), [ImportAction::Skip]);
+		assert_eq!(get_connections(None).unwrap().len(), 1);
+		assert_eq!(stored("server").host, "example.com");
+	}
+
+	#[test]
+	fn conflicts_are_overwritten() {
+		let _database = TestDatabase::new("bundle-overwrite");
+		let id = insert_connection(connection("server")).unwrap();
+
+		assert_eq!(import(vec![bundled(1, "server", None)], ConflictMode::Overwrite, MatchBy::Name), [ImportAction::Update]);
+		let server = stored("server");
+		assert_eq!(get_connections(None).unwrap().len(), 1);
+		assert_eq!(server.id, id);
+		assert_eq!(server.host, "bundle.example.com");
+		assert_eq!(server.port, 2222);
+		// The bundle has no secrets, so the stored password is kept
+		assert_eq!(server.password, "password");
+	}
+
+	#[test]
+	fn conflicts_are_duplicated_under_a_new_name() {
+		let _database = TestDatabase::new("bundle-duplicate");
+		insert_connection(connection("server")).unwrap();
+		insert_connection(connection("server (2)")).unwrap();
+
+		let actions = import(vec![bundled(1, "server", None), bundled(2, "server", None)], ConflictMode::Duplicate, MatchBy::Name);
+		assert_eq!(actions, [ImportAction::Add, ImportAction::Add]);
+		assert_eq!(stored("server").host, "example.com");
+		assert_eq!(stored("server (3)").host, "bundle.example.com");
+		assert_eq!(stored("server (4)").host, "bundle.example.com");
+	}
+
+	#[test]
+	fn connections_are_matched_by_id_or_name() {
+		let _database = TestDatabase::new("bundle-match");
+		let id = insert_connection(connection("server")).unwrap();
+
+		// The same id under another name is the same connection when matching by id
+		assert_eq!(import(vec![bundled(id, "renamed", None)], ConflictMode::Overwrite, MatchBy::Id), [ImportAction::Update]);
+		assert_eq!(get_connection_by_id(id, None).unwrap().name, "renamed");
+
+		// But a new one when matching by name
+		assert_eq!(import(vec![bundled(id, "server", None)], ConflictMode::Overwrite, MatchBy::Name), [ImportAction::Add]);
+		assert_eq!(get_connections(None).unwrap().len(), 2);
+	}
+
+	#[test]
+	fn jump_hosts_are_relinked() {
+		let _database = TestDatabase::new("bundle-jump-hosts");
+		let jump = insert_connection(connection("jump")).unwrap();
+		let old_jump = insert_connection(connection("old jump")).unwrap();
+		let mut tunneled = connection("tunneled");
+		tunneled.jump_host_id = Some(old_jump);
+		insert_connection(tunneled).unwrap();
+
+		let actions = import(
+			vec![bundled(10, "jump", None), bundled(11, "server", Some(10)), bundled(12, "tunneled", Some(99))],
+			ConflictMode::Skip,
+			MatchBy::Name,
+		);
+		assert_eq!(actions, [ImportAction::Skip, ImportAction::Add, ImportAction::Skip]);
+		// A jump host that was skipped links to the existing connection
+		assert_eq!(stored("server").jump_host_id, Some(jump));
+		// Skipped connections are left alone
+		assert_eq!(stored("tunneled").jump_host_id, Some(old_jump));
+
+		// A jump host outside of the bundle is dropped from overwritten connections
+		assert_eq!(import(vec![bundled(12, "tunneled", Some(99))], ConflictMode::Overwrite, MatchBy::Name), [ImportAction::Update]);
+		assert_eq!(stored("tunneled").jump_host_id, None);
+	}
+}
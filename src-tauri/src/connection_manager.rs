@@ -1,6 +1,6 @@
-use std::env;
-
-use sqlite;
+use crate::app_paths;
+use crate::credential_store;
+use crate::error::BackendError;
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
 pub struct Connection {
@@ -18,12 +18,22 @@ pub struct Connection {
 	pub created_at: String,
 	pub updated_at: String,
 	pub last_connected_at: String,
+	/// How to authenticate, see `AuthMethod`.
+	#[serde(default)]
+	pub auth_method: i8,
+	/// Path of a private key file on this machine, used with `AuthMethod::KeyFile`.
+	#[serde(default)]
+	pub private_key_path: String,
+	/// The id of another connection to tunnel through, which can have a jump host of its own.
+	#[serde(default)]
+	pub jump_host_id: Option<i32>,
 }
 
-#[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, Debug)]
 pub enum Protocol {
 	Sftp = 0,
 	Ftp = 1,
+	Ftps = 2,
 }
 
 impl From<Protocol> for i32 {
@@ -31,6 +41,7 @@ impl From<Protocol> for i32 {
 		match protocol {
 			Protocol::Sftp => 0,
 			Protocol::Ftp => 1,
+			Protocol::Ftps => 2,
 		}
 	}
 }
@@ -40,27 +51,129 @@ impl From<i32> for Protocol {
 		match protocol {
 			0 => Protocol::Sftp,
 			1 => Protocol::Ftp,
+			2 => Protocol::Ftps,
 			_ => Protocol::Sftp,
 		}
 	}
 }
 
+impl TryFrom<i8> for Protocol {
+	type Error = BackendError;
+
+	/// Converts the protocol number stored on a `Connection`, unknown numbers are rejected instead of falling back to SFTP
+	fn try_from(protocol: i8) -> Result<Protocol, BackendError> {
+		match protocol {
+			0 => Ok(Protocol::Sftp),
+			1 => Ok(Protocol::Ftp),
+			2 => Ok(Protocol::Ftps),
+			_ => Err(BackendError::invalid_input(format!("Unknown protocol: {}", protocol))),
+		}
+	}
+}
+
+/// How an SSH connection authenticates, stored in the `auth_method` column.
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, Debug)]
+pub enum AuthMethod {
+	/// The password of the user.
+	Password = 0,
+	/// The private key stored in the connection, the password is used as its passphrase.
+	PrivateKey = 1,
+	/// The identities of a running SSH agent, e.g. ssh-agent or Pageant.
+	Agent = 2,
+	/// A private key file on this machine, the password is used as its passphrase.
+	KeyFile = 3,
+}
+
+impl TryFrom<i8> for AuthMethod {
+	type Error = BackendError;
+
+	/// Converts the auth method number stored on a `Connection`, unknown numbers are rejected
+	fn try_from(auth_method: i8) -> Result<AuthMethod, BackendError> {
+		match auth_method {
+			0 => Ok(AuthMethod::Password),
+			1 => Ok(AuthMethod::PrivateKey),
+			2 => Ok(AuthMethod::Agent),
+			3 => Ok(AuthMethod::KeyFile),
+			_ => Err(BackendError::invalid_input(format!("Unknown auth method: {}", auth_method))),
+		}
+	}
+}
+
+/// A schema change of `connections.db`, applied once to databases whose `user_version` is below its position.
+type Migration = fn(&sqlite::Connection) -> Result<(), BackendError>;
+
+/// The migrations of `connections.db` in the order they are applied.
+///
+/// The schema version stored in `PRAGMA user_version` is the number of applied migrations, so new migrations
+/// are only ever appended and existing ones are never changed or reordered.
+const MIGRATIONS: &[Migration] = &[
+	create_connections_table, // 1: the table as it was created by the first versions
+	add_auth_method,          // 2: how SSH connections authenticate
+	add_private_key_path,     // 3: private key files for the key file auth method
+	add_jump_host_id,         // 4: the connection to tunnel through
+	create_transfer_history,  // 5: finished transfers of the transfer queue
+];
 
-/// Initializes the SQLite database and creates a 'connections' table if it doesn't already exist.
+/// Initializes the SQLite database and brings its schema up to date.
+///
+/// Every migration that was not applied yet runs in its own transaction together with the update of the
+/// schema version, so an interrupted start never leaves a half migrated database behind.
 ///
 /// # Arguments
 /// * None
 ///
 /// # Returns
 /// * `Ok(())` if the initialization is successful
-/// * `Err(String)` with the error message if there's an error
-pub fn initialize() -> Result<(), String> {
+/// * `Err(BackendError)` if there's an error, e.g. when the database was written by a newer version
+pub fn initialize() -> Result<(), BackendError> {
 	// Open a SQLite database with a dynamically calculated path
-	let lite = sqlite::open(get_database_path()).unwrap();
+	let lite = sqlite::open(get_database_path()).map_err(|e| BackendError::database(format!("Failed to open database: {}", e)))?;
+
+	let version = schema_version(&lite)?;
+	if version > MIGRATIONS.len() {
+		return Err(BackendError::database(format!("The database has schema version {}, this version only supports up to version {}", version, MIGRATIONS.len())));
+	}
+
+	for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+		lite.execute("BEGIN TRANSACTION").map_err(|e| BackendError::database(format!("Failed to begin transaction: {}", e)))?;
+		// The pragma can not be bound as a parameter, the version is a number we computed ourselves
+		let result = migration(&lite).and_then(|_| {
+			lite.execute(format!("PRAGMA user_version = {}", index + 1)).map_err(|e| BackendError::database(format!("Failed to set schema version: {}", e)))
+		});
+		match result {
+			Ok(()) => lite.execute("COMMIT").map_err(|e| BackendError::database(format!("Failed to commit migration {}: {}", index + 1, e)))?,
+			Err(e) => {
+				let _ = lite.execute("ROLLBACK");
+				return Err(BackendError::database(format!("Migration {} failed: {}", index + 1, e)));
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Reads the schema version of the database, 0 for new databases and databases written before migrations existed.
+fn schema_version(lite: &sqlite::Connection) -> Result<usize, BackendError> {
+	let mut statement = lite.prepare("PRAGMA user_version").map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+	statement.next().map_err(|e| BackendError::database(format!("Failed to read schema version: {}", e)))?;
+	Ok(statement.read::<i64, usize>(0).map_err(|e| BackendError::database(format!("Failed to read schema version: {}", e)))? as usize)
+}
 
-	// Execute a CREATE TABLE SQL statement to ensure a table named 'connections' exists
-	// This table contains all necessary fields for storing connection information
-	// Here, we create a table named 'connections' in the SQLite database, if it doesn't already exist.
+/// Checks whether a column exists in the `connections` table.
+///
+/// Databases without a schema version may already have columns that were added before migrations existed,
+/// so migrations that add columns check first.
+fn has_column(lite: &sqlite::Connection, column: &str) -> Result<bool, BackendError> {
+	let mut statement = lite.prepare("PRAGMA table_info(`connections`)").map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+	while statement.next().map_err(|e| BackendError::database(format!("Failed to read table info: {}", e)))? == sqlite::State::Row {
+		if statement.read::<String, &str>("name").map_err(|e| BackendError::database(format!("Failed to get column name: {}", e)))? == column {
+			return Ok(true);
+		}
+	}
+	Ok(false)
+}
+
+/// Migration 1: creates the `connections` table if it doesn't already exist.
+fn create_connections_table(lite: &sqlite::Connection) -> Result<(), BackendError> {
 	// This table has the following columns:
 	// id: An autoincrementing integer that serves as the primary key.
 	// name: A text field that stores a human-friendly name for the connection. This field is NOT NULL.
@@ -72,11 +185,11 @@ pub fn initialize() -> Result<(), String> {
 	// remote_path: A text field that stores the remote path. This field is NOT NULL.
 	// local_path: A text field that stores the local path. This field is NOT NULL.
 	// default: A boolean field that checks if this is a default connection or not. This field is NOT NULL.
-	// protocol: A tiny int field that stores the protocol for the connection. 0 means SFTP, 1 means FTP. This field is NOT NULL and defaults to 0.
+	// protocol: A tiny int field that stores the protocol for the connection. 0 means SFTP, 1 means FTP, 2 means FTP with explicit TLS. This field is NOT NULL and defaults to 0.
 	// created_at: A timestamp field that tracks the creation time of the connection. Defaults to the current timestamp.
 	// updated_at: A timestamp field that tracks the last update time. Defaults to the current timestamp.
-	// last_connected_at: A timestamp field that tracks the last connection attempt. Defaults to the current timestamp.  
-	match lite.execute(
+	// last_connected_at: A timestamp field that tracks the last connection attempt. Defaults to the current timestamp.
+	lite.execute(
 		"CREATE TABLE IF NOT EXISTS `connections` (
         'id' INTEGER PRIMARY KEY AUTOINCREMENT,
         'name' TEXT NOT NULL,
@@ -91,12 +204,87 @@ pub fn initialize() -> Result<(), String> {
         'protocol' TINYINT NOT NULL DEFAULT 0,
         'created_at' TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
         'updated_at' TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-        'last_connected_at' TIMESTAMP DEFAULT CURRENT_TIMESTAMP     
+        'last_connected_at' TIMESTAMP DEFAULT CURRENT_TIMESTAMP
             )",
-	) {
-		Ok(_) => Ok(()),   // If the SQL statement executes successfully, return Ok
-		Err(e) => Err(format!("Code: {:?}, Message: {:?}", e.code, e.message))  // If there's an error, format and return the error
+	).map_err(|e| BackendError::database(format!("Code: {:?}, Message: {:?}", e.code, e.message)))
+}
+
+/// Migration 2: adds `auth_method`, how SSH connections authenticate. 0 means password, 1 private key, 2 SSH agent, 3 key file.
+fn add_auth_method(lite: &sqlite::Connection) -> Result<(), BackendError> {
+	if has_column(lite, "auth_method")? {
+		return Ok(());
+	}
+	lite.execute("ALTER TABLE `connections` ADD COLUMN 'auth_method' TINYINT NOT NULL DEFAULT 0")
+		.map_err(|e| BackendError::database(format!("Code: {:?}, Message: {:?}", e.code, e.message)))?;
+	// Connections with a private key authenticated with it before the column existed
+	lite.execute("UPDATE `connections` SET 'auth_method' = 1 WHERE `private_key` != ''")
+		.map_err(|e| BackendError::database(format!("Code: {:?}, Message: {:?}", e.code, e.message)))
+}
+
+/// Migration 3: adds `private_key_path`, the path of a private key file for the key file auth method.
+fn add_private_key_path(lite: &sqlite::Connection) -> Result<(), BackendError> {
+	if has_column(lite, "private_key_path")? {
+		return Ok(());
+	}
+	lite.execute("ALTER TABLE `connections` ADD COLUMN 'private_key_path' TEXT NOT NULL DEFAULT ''")
+		.map_err(|e| BackendError::database(format!("Code: {:?}, Message: {:?}", e.code, e.message)))
+}
+
+/// Migration 4: adds `jump_host_id`, the id of the connection to tunnel through. NULL for direct connections.
+fn add_jump_host_id(lite: &sqlite::Connection) -> Result<(), BackendError> {
+	if has_column(lite, "jump_host_id")? {
+		return Ok(());
 	}
+	lite.execute("ALTER TABLE `connections` ADD COLUMN 'jump_host_id' INTEGER DEFAULT NULL")
+		.map_err(|e| BackendError::database(format!("Code: {:?}, Message: {:?}", e.code, e.message)))
+}
+
+/// Migration 5: creates the `transfer_history` table, the transfers the transfer queue finished, failed or cancelled.
+fn create_transfer_history(lite: &sqlite::Connection) -> Result<(), BackendError> {
+	// direction: `download` or `upload`.
+	// state: `done`, `failed` or `cancelled`.
+	// bytes: The number of bytes that were transferred.
+	// attempts: How often the transfer was started, retries included.
+	// error: The message of the last error, NULL if the transfer did not fail.
+	lite.execute(
+		"CREATE TABLE IF NOT EXISTS `transfer_history` (
+        'id' INTEGER PRIMARY KEY AUTOINCREMENT,
+        'connection_id' INTEGER NOT NULL,
+        'direction' TEXT NOT NULL,
+        'source' TEXT NOT NULL,
+        'target' TEXT NOT NULL,
+        'state' TEXT NOT NULL,
+        'bytes' INTEGER NOT NULL DEFAULT 0,
+        'attempts' INTEGER NOT NULL DEFAULT 0,
+        'error' TEXT DEFAULT NULL,
+        'finished_at' TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+	).map_err(|e| BackendError::database(format!("Code: {:?}, Message: {:?}", e.code, e.message)))
+}
+
+/// Maps the current row of a `SELECT * FROM connections` statement to a `Connection`, secrets stay encrypted.
+///
+/// Columns are read by name, so the order of the columns in the table does not matter.
+fn read_connection(statement: &sqlite::Statement) -> Result<Connection, BackendError> {
+	Ok(Connection {
+		id: statement.read::<i64, &str>("id").map_err(|e| BackendError::database(format!("Failed to get id from database: {:?}", e.to_string())))? as i32,  // ID of the connection
+		name: statement.read::<String, &str>("name").map_err(|e| BackendError::database(format!("Failed to get name from database: {:?}", e.to_string())))?,  // Name of the connection
+		host: statement.read::<String, &str>("host").map_err(|e| BackendError::database(format!("Failed to get host from database: {:?}", e.to_string())))?,  // Host IP or URL of the connection
+		port: statement.read::<i64, &str>("port").map_err(|e| BackendError::database(format!("Failed to get port from database: {:?}", e.to_string())))? as i32,  // Port of the connection
+		username: statement.read::<String, &str>("username").map_err(|e| BackendError::database(format!("Failed to get username from database: {:?}", e.to_string())))?,  // Username for the connection
+		password: statement.read::<String, &str>("password").map_err(|e| BackendError::database(format!("Failed to get password from database: {:?}", e.to_string())))?,  // Encrypted password for the connection
+		private_key: statement.read::<String, &str>("private_key").map_err(|e| BackendError::database(format!("Failed to get private_key from database: {:?}", e.to_string())))?,  // Encrypted SSH private key for the connection
+		remote_path: statement.read::<String, &str>("remote_path").map_err(|e| BackendError::database(format!("Failed to get remote_path from database: {:?}", e.to_string())))?,  // The path for the connection in the remote machine
+		local_path: statement.read::<String, &str>("local_path").map_err(|e| BackendError::database(format!("Failed to get local_path from database: {:?}", e.to_string())))?,  // Local path that this connection mapped to
+		default: statement.read::<i64, &str>("default").map_err(|e| BackendError::database(format!("Failed to get default from database: {:?}", e.to_string())))? != 0,  // Boolean flag if the connection is default or not
+		protocol: statement.read::<i64, &str>("protocol").map_err(|e| BackendError::database(format!("Failed to get protocol from database: {:?}", e.to_string())))? as i8,  // The protocol of the connection (SFTP = 0, FTP = 1, FTPS = 2)
+		created_at: statement.read::<String, &str>("created_at").map_err(|e| BackendError::database(format!("Failed to get created_at from database: {:?}", e.to_string())))?,  // Connection creation time
+		updated_at: statement.read::<String, &str>("updated_at").map_err(|e| BackendError::database(format!("Failed to get updated_at from database: {:?}", e.to_string())))?,  // Connection update time
+		last_connected_at: statement.read::<String, &str>("last_connected_at").map_err(|e| BackendError::database(format!("Failed to get last_connected_at from database: {:?}", e.to_string())))?,  // Last time the connection was attempted
+		auth_method: statement.read::<i64, &str>("auth_method").map_err(|e| BackendError::database(format!("Failed to get auth_method from database: {:?}", e.to_string())))? as i8,  // How the connection authenticates
+		private_key_path: statement.read::<String, &str>("private_key_path").map_err(|e| BackendError::database(format!("Failed to get private_key_path from database: {:?}", e.to_string())))?,  // Path of the private key file
+		jump_host_id: statement.read::<Option<i64>, &str>("jump_host_id").map_err(|e| BackendError::database(format!("Failed to get jump_host_id from database: {:?}", e.to_string())))?.map(|id| id as i32),  // The connection to tunnel through
+	})
 }
 
 /// Adds a new connection to the SQLite database.
@@ -105,6 +293,8 @@ pub fn initialize() -> Result<(), String> {
 ///
 /// * `connection` - The `Connection` structure containing all the necessary values for the new connection.
 ///
+/// The password and private key are encrypted before they are stored, which fails while the credentials are locked.
+///
 /// # Example
 ///
 /// ```rust
@@ -116,121 +306,98 @@ pub fn initialize() -> Result<(), String> {
 /// }
 /// ```
 #[tauri::command]
-pub fn add_connection(connection: Connection) {
+pub fn add_connection(connection: Connection) -> Result<(), BackendError> {
+	insert_connection(connection).map(|_| ())
+}
+
+/// Adds a new connection to the SQLite database, like `add_connection`, and returns the id of the new row.
+pub(crate) fn insert_connection(connection: Connection) -> Result<i32, BackendError> {
+	// Encrypt the secrets first, nothing is stored if the credentials are locked
+	let password = credential_store::encrypt(&connection.password)?;
+	let private_key = credential_store::encrypt(&connection.private_key)?;
+
 	// Open SQLite database, path is acquired through get_database_path() function
-	let lite = sqlite::open(get_database_path()).unwrap();
+	let lite = sqlite::open(get_database_path())?;
 
 	// Execute an SQL command to insert a new row to 'connections' inside the database
 	// All necessary values for the new row are taken from the Connection structure passed as a parameter to add_connection method
-	match lite.execute(
-		&format!(
-			"INSERT INTO `connections` ('name', 'host', 'port', 'username', 'password', 'private_key', 'remote_path', 'local_path', 'default', 'protocol')
-                    VALUES ('{}', '{}', {}, '{}', '{}', '{}', '{}', '{}', {}, '{}')",
+	execute(
+		&lite,
+		"INSERT INTO `connections` ('name', 'host', 'port', 'username', 'password', 'private_key', 'remote_path', 'local_path', 'default', 'protocol', 'auth_method', 'private_key_path', 'jump_host_id')
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+		&[
 			// These fetched by the structure fields of Connection
-			connection.name,                      // Name of the connection
-			connection.host,                      // Host IP or URL of the connection
-			connection.port,                      // The port number of the connection
-			connection.username,                  // Username for authentication for the connection
-			connection.password,                  // Password for authentication for the connection
-			connection.private_key,               // SSH private key for the connection (if applicable)
-			connection.remote_path,               // The path for the connection in the remote machine
-			connection.local_path,                // Local path that this connection mapped to
-			connection.default,                   // Boolean flag - is this connection default or not
-			connection.protocol                   // The protocol of the connection (FTP or SFTP)
-		),
-	) {
-		// If the query was executed successfully - do nothing
-		Ok(_) => (),
-		// If there's an error, print it to the console
-		Err(e) => println!("{:?}", e),
-	}
+			connection.name.into(),                      // Name of the connection
+			connection.host.into(),                      // Host IP or URL of the connection
+			(connection.port as i64).into(),             // The port number of the connection
+			connection.username.into(),                  // Username for authentication for the connection
+			password.into(),                             // Encrypted password for authentication for the connection
+			private_key.into(),                          // Encrypted SSH private key for the connection (if applicable)
+			connection.remote_path.into(),               // The path for the connection in the remote machine
+			connection.local_path.into(),                // Local path that this connection mapped to
+			(connection.default as i64).into(),          // Boolean flag - is this connection default or not
+			(connection.protocol as i64).into(),         // The protocol of the connection (FTP or SFTP)
+			(connection.auth_method as i64).into(),      // How the connection authenticates
+			connection.private_key_path.into(),          // Path of the private key file, if the key file auth method is used
+			connection.jump_host_id.map(i64::from).into(), // The connection to tunnel through, if any
+		],
+	)?;
+
+	// The id of the new row, read on the same database connection that inserted it
+	let mut statement = lite.prepare("SELECT last_insert_rowid()")?;
+	statement.next()?;
+	Ok(statement.read::<i64, usize>(0).map_err(|e| BackendError::database(format!("Failed to get id of the new connection: {:?}", e.to_string())))? as i32)
 }
 
 /// Retrieves a list of connections from the database.
 ///
-/// Returns a `Result` containing a `Vec<Connection>` if successfully got the data or a `String` with an error message if failed.
+/// Passwords and private keys are returned empty unless `include_secrets` is `true`.
+///
+/// Returns a `Result` containing a `Vec<Connection>` if successfully got the data or a `BackendError` if failed.
 #[tauri::command]
-pub fn get_connections() -> Result<Vec<Connection>, String> {
+pub fn get_connections(include_secrets: Option<bool>) -> Result<Vec<Connection>, BackendError> {
 	// Store all connections
 	let mut connections = Vec::new();
 	// Open SQLite database, path is acquired through get_database_path() function
-	let lite = sqlite::open(get_database_path()).map_err(|e| e.to_string())?;
+	let lite = sqlite::open(get_database_path())?;
 	// Execute a SELECT statement to retrieve all data from the connections table
-	let mut statement = lite
-		.prepare("SELECT * FROM connections ORDER BY last_connected_at DESC")
-		.map_err(|e| e.to_string())?;
+	let mut statement = lite.prepare("SELECT * FROM connections ORDER BY last_connected_at DESC")?;
 
 	// Read every row of data in the connections table
-	while let sqlite::State::Row = statement.next().unwrap() {
+	while let sqlite::State::Row = statement.next()? {
 		// Map the data to a Connection struct
-		let connection = Connection {
-			// Reading unique identifier (id) from the database and converting it to i32
-			id: statement.read::<i64, usize>(0).map_err(|e| format!("Failed to get id from database: {:?}", e.to_string()))? as i32,
-			// Reading connection name from the database
-			name: statement.read::<String, usize>(1).map_err(|e| format!("Failed to get name from database: {:?}", e.to_string()))?,
-			// Reading host IP or URL of the remote connection from the database
-			host: statement.read::<String, usize>(2).map_err(|e| format!("Failed to get host from database: {:?}", e.to_string()))?,
-			// Reading port number for the remote connection from the database and converting it to i32
-			port: statement.read::<i64, usize>(3).map_err(|e| format!("Failed to get port from database: {:?}", e.to_string()))? as i32,
-			// Reading username for the remote connection from the database
-			username: statement.read::<String, usize>(4).map_err(|e| format!("Failed to get username from database: {:?}", e.to_string()))?,
-			// Reading password for the remote connection from the database
-			password: statement.read::<String, usize>(5).map_err(|e| format!("Failed to get password from database: {:?}", e.to_string()))?,
-			// Reading SSH private key for the connection from the database, if it is available
-			private_key: statement.read::<String, usize>(6).map_err(|e| format!("Failed to get private_key from database: {:?}", e.to_string()))?,
-			// Reading the remote path for the connection from the database
-			remote_path: statement.read::<String, usize>(7).map_err(|e| format!("Failed to get remote_path from database: {:?}", e.to_string()))?,
-			// Reading the local path the connection is mapped to from the database
-			local_path: statement.read::<String, usize>(8).map_err(|e| format!("Failed to get local_path from database: {:?}", e.to_string()))?,
-			// Reading the flag that signals if this is the default connection and converting it to a boolean
-			default: statement.read::<i64, usize>(9).map_err(|e| format!("Failed to get default from database: {:?}", e.to_string()))? != 0,
-			// Reading the protocol of the connection (protocol number: FTP = 0, SFTP = 1) from the database and converting it to i8
-			protocol: statement.read::<i64, usize>(10).map_err(|e| format!("Failed to get protocol from database: {:?}", e.to_string()))? as i8,
-			// Reading the creation time of the connection from the database
-			created_at: statement.read::<String, usize>(11).map_err(|e| format!("Failed to get created_at from database: {:?}", e.to_string()))?,
-			// Reading the last update time of the connection from the database
-			updated_at: statement.read::<String, usize>(12).map_err(|e| format!("Failed to get updated_at from database: {:?}", e.to_string()))?,
-			// Reading the last connection attempt time for this connection from the database
-			last_connected_at: statement.read::<String, usize>(13).map_err(|e| format!("Failed to get last_connected_at from database: {:?}", e.to_string()))?,
-		};
+		let mut connection = read_connection(&statement)?;
+		credential_store::reveal_secrets(&mut connection, include_secrets.unwrap_or(false))?; // Decrypt or clear the secrets
 		connections.push(connection); // Add the connection into the collection
 	}
 
-	return Ok(connections); // Return the collection of connections
+	Ok(connections) // Return the collection of connections
 }
 
 #[tauri::command]
 // Function to retrieve a connection from the SQLite database based on the id
-pub fn get_connection_by_id(id: i32) -> Result<Connection, String> {
+// Passwords and private keys are returned empty unless `include_secrets` is `true`
+pub fn get_connection_by_id(id: i32, include_secrets: Option<bool>) -> Result<Connection, BackendError> {
+	let mut connection = load_connection(id)?.ok_or_else(|| BackendError::not_found(format!("No connection found with id: {}", id)))?;
+	credential_store::reveal_secrets(&mut connection, include_secrets.unwrap_or(false))?;
+	Ok(connection)
+}
+
+// Function to read a connection as it is stored, with encrypted secrets, or `None` if there is no connection with the id
+pub(crate) fn load_connection(id: i32) -> Result<Option<Connection>, BackendError> {
 	// Open SQLite connection using the get_database_path() function
-	let sqlite = sqlite::open(get_database_path()).map_err(|e| e.to_string())?;
-	// Create a SQL query by formatting a string with the provided id
-	let query = format!("SELECT * FROM connections WHERE id = {} limit 1", id);
-	// Prepare the SQL statement
-	let mut statement = sqlite.prepare(&query).map_err(|e| e.to_string())?;
-
-	// If there's no row to fetch for the provided id, return an error
-	if statement.next().map_err(|e| e.to_string())? != sqlite::State::Row {
-		return Err(format!("No connection found with id: {}", id));
+	let sqlite = sqlite::open(get_database_path())?;
+	// Prepare the SQL statement and bind the provided id to it
+	let mut statement = sqlite.prepare("SELECT * FROM connections WHERE id = ? limit 1")?;
+	statement.bind((1, id as i64)).map_err(|e| BackendError::database(format!("Failed to bind id: {}", e)))?;
+
+	// If there's no row to fetch for the provided id, there is no connection
+	if statement.next()? != sqlite::State::Row {
+		return Ok(None);
 	}
 
 	// Build a Connection structure by reading row's columns and mapping them to the struct's fields
-	Ok(Connection {
-		id: statement.read::<i64, usize>(0).map_err(|e| format!("Failed to get id from database: {:?}", e.to_string()))? as i32,  // ID of the connection
-		name: statement.read::<String, usize>(1).map_err(|e| format!("Failed to get name from database: {:?}", e.to_string()))?,  // Name of the connection
-		host: statement.read::<String, usize>(2).map_err(|e| format!("Failed to get host from database: {:?}", e.to_string()))?,  // Host of the connection
-		port: statement.read::<i64, usize>(3).map_err(|e| format!("Failed to get port from database: {:?}", e.to_string()))? as i32,  // Port of the connection
-		username: statement.read::<String, usize>(4).map_err(|e| format!("Failed to get username from database: {:?}", e.to_string()))?,  // Username for the connection
-		password: statement.read::<String, usize>(5).map_err(|e| format!("Failed to get password from database: {:?}", e.to_string()))?,  // Password for the connection
-		private_key: statement.read::<String, usize>(6).map_err(|e| format!("Failed to get private_key from database: {:?}", e.to_string()))?,  // SSH private key for the connection
-		remote_path: statement.read::<String, usize>(7).map_err(|e| format!("Failed to get remote_path from database: {:?}", e.to_string()))?,  // The path for the connection in the remote machine
-		local_path: statement.read::<String, usize>(8).map_err(|e| format!("Failed to get local_path from database: {:?}", e.to_string()))?,  // Local path that this connection mapped to
-		default: statement.read::<i64, usize>(9).map_err(|e| format!("Failed to get default from database: {:?}", e.to_string()))? != 0,  // Boolean flag if the connection is default or not
-		protocol: statement.read::<i64, usize>(10).map_err(|e| format!("Failed to get protocol from database: {:?}", e.to_string()))? as i8,  // The protocol of the connection (FTP or SFTP)
-		created_at: statement.read::<String, usize>(11).map_err(|e| format!("Failed to get created_at from database: {:?}", e.to_string()))?,  // Connection creation time
-		updated_at: statement.read::<String, usize>(12).map_err(|e| format!("Failed to get updated_at from database: {:?}", e.to_string()))?,  // Connection update time
-		last_connected_at: statement.read::<String, usize>(13).map_err(|e| format!("Failed to get last_connected_at from database: {:?}", e.to_string()))?,  // Last time the connection was attempted
-	})
+	read_connection(&statement).map(Some)
 }
 
 /// Update an existing connection in the SQLite database based on the id.
@@ -239,6 +406,12 @@ pub fn get_connection_by_id(id: i32) -> Result<Connection, String> {
 ///
 /// * `id` - The id of the connection to update.
 /// * `connection` - The updated connection details.
+/// * `clear_password` - Remove the stored password instead of keeping it when `connection.password` is empty.
+/// * `clear_private_key` - Remove the stored private key instead of keeping it when `connection.private_key` is empty.
+///
+/// Connections are returned without their secrets, so an empty password or private key keeps the stored one unless
+/// it is cleared. Changing the auth method drops the stored secrets that no longer apply: the password of a key is its
+/// passphrase, and only the private key auth method uses a stored private key.
 ///
 /// # Example
 ///
@@ -257,39 +430,63 @@ pub fn get_connection_by_id(id: i32) -> Result<Connection, String> {
 ///     local_path: String::from("/path/to/local"),
 ///     default: false,
 ///     protocol: 0,
+///     auth_method: 1,
+///     private_key_path: String::new(),
+///     jump_host_id: None,
 /// };
-/// update_connection(id, connection);
+/// update_connection(id, connection, None, None);
 /// ```
 #[tauri::command]
-pub fn update_connection(id: i32, connection: Connection) {
-	println!("Updating '{:?}' {:?}", id, connection);  // Debug print - show which connection(id) is updated
+pub fn update_connection(id: i32, connection: Connection, clear_password: Option<bool>, clear_private_key: Option<bool>) -> Result<(), BackendError> {
+	// Keep the stored secrets that were not sent and still apply, encrypt the ones that were
+	let stored = load_connection(id)?;
+	let same_auth_method = stored.as_ref().is_some_and(|stored| stored.auth_method == connection.auth_method);
+	let uses_private_key = matches!(AuthMethod::try_from(connection.auth_method), Ok(AuthMethod::PrivateKey));
+	let password = updated_secret(
+		stored.as_ref().map(|stored| stored.password.as_str()),
+		&connection.password,
+		clear_password.unwrap_or(false) || !same_auth_method,
+	)?;
+	let private_key = updated_secret(
+		stored.as_ref().map(|stored| stored.private_key.as_str()),
+		&connection.private_key,
+		clear_private_key.unwrap_or(false) || !uses_private_key,
+	)?;
 
 	// Open SQLite database, path is acquired through get_database_path() function
-	let lite = sqlite::open(get_database_path()).unwrap();
+	let lite = sqlite::open(get_database_path())?;
 
 	// Prepare an SQL command to update certain row (connection) in 'connections' inside the database
 	// This command updates all fields of the row(connection) except id, which is used to identify the row to be updated
-	let sql = &format!(
-		"UPDATE `connections` SET 'name' = '{}', 'host' = '{}', 'port' = {}, 'username' = '{}', 'password' = '{}', 'private_key' = '{}', 'remote_path' = '{}', 'local_path' = '{}', 'default' = {}, 'protocol' = {}, 'updated_at'='{}' WHERE id = {}",
-		connection.name,            // Set the name of the connection
-		connection.host,            // Set the host IP or URL of the remote connection
-		connection.port,            // Set the port number of the remote connection
-		connection.username,        // Set the username for the remote connection
-		connection.password,        // Set the password for the remote connection
-		connection.private_key,     // Set the SSH private key for the connection
-		connection.remote_path,     // Set the path for the connection in the remote machine
-		connection.local_path,      // Set the local path that this connection mapped to
-		connection.default,         // Set the boolean flag if the connection is default or not
-		connection.protocol,        // Set the protocol of the connection (FTP or SFTP)
-		chrono::Local::now().to_string(),  // Update the 'updated_at' field with the current time
-		id   // This is the id of the row to be updated
-	);
-	println!("{}", &sql);  // Debug print - show the final SQL command string
-
-	// Execute the prepared SQL command, print error to the console in case of failure
-	match lite.execute(&sql) {
-		Ok(_) => (),
-		Err(e) => println!("{:?}", e),
+	let sql = "UPDATE `connections` SET 'name' = ?, 'host' = ?, 'port' = ?, 'username' = ?, 'password' = ?, 'private_key' = ?, 'remote_path' = ?, 'local_path' = ?, 'default' = ?, 'protocol' = ?, 'auth_method' = ?, 'private_key_path' = ?, 'jump_host_id' = ?, 'updated_at' = ? WHERE id = ?";
+	let parameters = [
+		connection.name.into(),                    // Set the name of the connection
+		connection.host.into(),                    // Set the host IP or URL of the remote connection
+		(connection.port as i64).into(),           // Set the port number of the remote connection
+		connection.username.into(),                // Set the username for the remote connection
+		password.into(),                           // Set the encrypted password for the remote connection
+		private_key.into(),                        // Set the encrypted SSH private key for the connection
+		connection.remote_path.into(),             // Set the path for the connection in the remote machine
+		connection.local_path.into(),              // Set the local path that this connection mapped to
+		(connection.default as i64).into(),        // Set the boolean flag if the connection is default or not
+		(connection.protocol as i64).into(),       // Set the protocol of the connection (FTP or SFTP)
+		(connection.auth_method as i64).into(),    // Set how the connection authenticates
+		connection.private_key_path.into(),        // Set the path of the private key file
+		connection.jump_host_id.map(i64::from).into(), // Set the connection to tunnel through
+		chrono::Local::now().to_string().into(),   // Update the 'updated_at' field with the current time
+		(id as i64).into(),                        // This is the id of the row to be updated
+	];
+
+	// Execute the prepared SQL command
+	execute(&lite, sql, &parameters)
+}
+
+/// Picks the secret `update_connection` stores: the sent value encrypted, or the stored value if nothing was sent and
+/// it is not cleared.
+fn updated_secret(stored: Option<&str>, sent: &str, clear: bool) -> Result<String, BackendError> {
+	match stored {
+		Some(stored) if sent.is_empty() && !clear => Ok(stored.to_string()),
+		_ => credential_store::encrypt(sent),
 	}
 }
 
@@ -299,28 +496,24 @@ pub fn update_connection(id: i32, connection: Connection) {
 ///
 /// * `id` - The ID of the connection to update.
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function will panic if the SQLite database cannot be opened or if there is an error during SQL execution.
+/// Returns a `Database` error if the SQLite database cannot be opened or if there is an error during SQL execution.
 #[tauri::command]
-pub fn update_join(id: i32) {
+pub fn update_join(id: i32) -> Result<(), BackendError> {
 	// Open a SQLite database with a dynamically calculated path
-	let lite = sqlite::open(get_database_path()).unwrap();
+	let lite = sqlite::open(get_database_path())?;
 
 	// Prepare an SQL command to update the 'last_connected_at' field of the connection with the given ID
 	// 'last_connected_at' is updated with the current timestamp
-	match lite.execute(
-		&format!(
-			"UPDATE `connections` SET 'last_connected_at' = '{}' WHERE id = {}",
-			chrono::Local::now().to_string(),  // Current timestamp
-			id   // ID of the connection to update
-		),
-	) {
-		// If the query was successfully executed - do nothing
-		Ok(_) => (),
-		// In case of an error during SQL execution, print the error details to the console
-		Err(e) => println!("{:?}", e),
-	}
+	execute(
+		&lite,
+		"UPDATE `connections` SET 'last_connected_at' = ? WHERE id = ?",
+		&[
+			chrono::Local::now().to_string().into(),  // Current timestamp
+			(id as i64).into(),   // ID of the connection to update
+		],
+	)
 }
 
 /// Delete a connection from the SQLite database based on the provided id.
@@ -336,19 +529,17 @@ pub fn update_join(id: i32) {
 /// delete_connection(1);
 /// ```
 #[tauri::command]
-pub fn delete_connection(id: i32) {
+pub fn delete_connection(id: i32) -> Result<(), BackendError> {
 	// Open a connection to the SQLite database.
-	let lite = sqlite::open(get_database_path()).unwrap();
-	match lite.execute(
-		&format!(
-			// SQL query to delete the connection based on the id.
-			"DELETE FROM connections WHERE id = '{}'",
-			id
-		),
-	) {
-		Ok(_) => (),
-		Err(e) => println!("{:?}", e),  // Print any error that occurs.
-	}
+	let lite = sqlite::open(get_database_path())?;
+	// Connections that tunneled through this one connect directly from now on.
+	execute(&lite, "UPDATE `connections` SET 'jump_host_id' = NULL WHERE jump_host_id = ?", &[(id as i64).into()])?;
+	execute(
+		&lite,
+		// SQL query to delete the connection based on the id.
+		"DELETE FROM connections WHERE id = ?",
+		&[(id as i64).into()],
+	)
 }
 
 /// Sets the specified connection as the default.
@@ -374,65 +565,210 @@ pub fn delete_connection(id: i32) {
 /// }
 ///
 /// // Set the specified connection as default based on the ID.
-/// match lite.execute(&format!("UPDATE `connections` SET 'default'
+/// match execute(&lite, "UPDATE `connections` SET 'default' = 1 WHERE id = ?", &[(id as i64).into()]) {
 #[tauri::command]
-pub fn set_default(id: i32) {
+pub fn set_default(id: i32) -> Result<(), BackendError> {
 	// Open a connection to the SQLite database.
-	let lite = sqlite::open(get_database_path()).unwrap();
-	match lite.execute(
-		&"UPDATE `connections` SET 'default' = 0".to_string(),
-		// SQL query to remove default status from all connections.
-	) {
-		Ok(_) => (),
-		Err(e) => println!("{:?}", e),  // Print any error that occurs.
-	}
-	match lite.execute(
-		&format!(
-			// SQL query to set the connection as default based on the id.
-			"UPDATE `connections` SET 'default' = 1 WHERE id = {}",
-			id
-		),
-	) {
-		Ok(_) => (),
-		Err(e) => println!("{:?}", e),  // Print any error that occurs.
-	}
+	let lite = sqlite::open(get_database_path())?;
+	// SQL query to remove default status from all connections.
+	lite.execute("UPDATE `connections` SET 'default' = 0")?;
+	execute(
+		&lite,
+		// SQL query to set the connection as default based on the id.
+		"UPDATE `connections` SET 'default' = 1 WHERE id = ?",
+		&[(id as i64).into()],
+	)
 }
 
-/// Retrieves the path to the SQLite database.
+/// Executes a statement that does not return rows, binding `parameters` to its `?` placeholders in order.
 ///
-/// The function first gets the path to the current executable using `env::current_exe()`.
-/// If successful, it then retrieves the directory of the executable using `exe_path.parent()`.
-/// Finally, the function joins the directory path with the filename "connections.db" and returns
-/// the resulting path as a string.
+/// Values are never formatted into the SQL, so quotes or semicolons in e.g. a connection name or password
+/// can neither break the statement nor inject SQL.
 ///
-/// # Errors
+/// # Arguments
+///
+/// * `lite` - The open database connection.
+/// * `sql` - The SQL statement with `?` placeholders.
+/// * `parameters` - The values for the placeholders, the first value is bound to the first placeholder.
+pub(crate) fn execute(lite: &sqlite::Connection, sql: &str, parameters: &[sqlite::Value]) -> Result<(), BackendError> {
+	let mut statement = lite.prepare(sql).map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+
+	// Placeholders are numbered starting at 1
+	for (index, value) in parameters.iter().enumerate() {
+		statement.bind((index + 1, value)).map_err(|e| BackendError::database(format!("Failed to bind parameter {}: {}", index + 1, e)))?;
+	}
+
+	// Step through the statement until it is done
+	while statement.next().map_err(|e| BackendError::database(format!("Failed to execute statement: {}", e)))? != sqlite::State::Done {}
+	Ok(())
+}
+
+/// Retrieves the path to the SQLite database.
 ///
-/// If any error occurs while getting the current executable path or the directory of the executable,
-/// an error message is printed to the standard error stream and the default path "connections.db" is returned.
+/// The database is stored in the data directory of the user, or beside the executable in portable mode.
+/// See `app_paths` for how the directory is chosen.
 ///
 /// # Returns
 ///
-/// - The path to the SQLite database if the executable path and directory are successfully retrieved.
-/// - The default path "connections.db" if any error occurs.
-fn get_database_path() -> String {
-	// Get the path to the current executable.
-	let exe_path = match env::current_exe() {
-		Ok(exe_path) => exe_path,
-		Err(e) => {
-			eprintln!("Failed to get current exe path: {}", e);
-			return "connections.db".to_string();
+/// - The path to the SQLite database.
+pub(crate) fn get_database_path() -> String {
+	#[cfg(test)]
+	if let Some(path) = tests::DATABASE.with(|path| path.borrow().clone()) {
+		return path;
+	}
+	app_paths::data_file("connections.db").to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::cell::RefCell;
+	use std::path::PathBuf;
+	use std::sync::{Mutex, MutexGuard};
+
+	use super::*;
+
+	thread_local! {
+		/// The database `get_database_path` returns on this thread, set by `TestDatabase`.
+		pub(super) static DATABASE: RefCell<Option<String>> = const { RefCell::new(None) };
+	}
+
+	/// The credential store key is global, so only one test database can be in use at a time.
+	static LOCK: Mutex<()> = Mutex::new(());
+
+	/// A new, initialized database for the current test, deleted again when it is dropped.
+	pub(crate) struct TestDatabase {
+		path: PathBuf,
+		_lock: MutexGuard<'static, ()>,
+	}
+
+	impl TestDatabase {
+		pub(crate) fn new(name: &str) -> Self {
+			let lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+			let path = std::env::temp_dir().join(format!("sftp-editor-test-{}-{}.db", name, std::process::id()));
+			let _ = std::fs::remove_file(&path);
+			DATABASE.with(|database| *database.borrow_mut() = Some(path.to_string_lossy().to_string()));
+			initialize().unwrap();
+			credential_store::initialize().unwrap();
+			TestDatabase { path, _lock: lock }
+		}
+	}
+
+	impl Drop for TestDatabase {
+		fn drop(&mut self) {
+			DATABASE.with(|database| *database.borrow_mut() = None);
+			let _ = std::fs::remove_file(&self.path);
 		}
-	};
-
-	// Get the directory the executable is in.
-	let exe_directory = match exe_path.parent() {
-		Some(dir) => dir,
-		None => {
-			eprintln!("Failed to get directory of the exe");
-			return "connections.db".to_string();
+	}
+
+	pub(crate) fn connection(name: &str) -> Connection {
+		Connection {
+			id: 0,
+			name: name.to_string(),
+			host: "example.com".to_string(),
+			port: 22,
+			username: "user".to_string(),
+			password: "password".to_string(),
+			private_key: String::new(),
+			remote_path: "/home/user".to_string(),
+			local_path: "/tmp".to_string(),
+			default: false,
+			protocol: 0,
+			created_at: String::new(),
+			updated_at: String::new(),
+			last_connected_at: String::new(),
+			auth_method: AuthMethod::Password as i8,
+			private_key_path: String::new(),
+			jump_host_id: None,
 		}
-	};
+	}
+
+	fn assert_stored(expected: &Connection, actual: &Connection) {
+		assert_eq!(expected.name, actual.name);
+		assert_eq!(expected.host, actual.host);
+		assert_eq!(expected.port, actual.port);
+		assert_eq!(expected.username, actual.username);
+		assert_eq!(expected.password, actual.password);
+		assert_eq!(expected.private_key, actual.private_key);
+		assert_eq!(expected.remote_path, actual.remote_path);
+		assert_eq!(expected.local_path, actual.local_path);
+		assert_eq!(expected.protocol, actual.protocol);
+		assert_eq!(expected.auth_method, actual.auth_method);
+		assert_eq!(expected.private_key_path, actual.private_key_path);
+		assert_eq!(expected.jump_host_id, actual.jump_host_id);
+	}
+
+	fn by_name(name: &str) -> Connection {
+		get_connections(Some(true)).unwrap().into_iter().find(|connection| connection.name == name).expect("connection not found by name")
+	}
 
-	// Return the path to the SQLite database.
-	return exe_directory.join("connections.db").to_str().unwrap().to_string();
+	#[test]
+	fn special_characters_round_trip() {
+		let _database = TestDatabase::new("round-trip");
+
+		let mut inserted = connection("O'Brien's \"server\"; DROP TABLE connections; --");
+		inserted.host = "höst.例え.jp".to_string();
+		inserted.username = "ユーザー'; --".to_string();
+		inserted.password = "pa'ss\";wörd 🔑".to_string();
+		inserted.remote_path = "/home/'quoted'/ünïcode; rm -rf".to_string();
+		inserted.local_path = "C:\\Users\\Zoë\\'files'".to_string();
+		let id = insert_connection(inserted.clone()).unwrap();
+		assert_stored(&inserted, &get_connection_by_id(id, Some(true)).unwrap());
+		assert_stored(&inserted, &by_name(&inserted.name));
+
+		let mut updated = inserted.clone();
+		updated.name = "Ω \"new\"; name' --".to_string();
+		updated.username = "😀; SELECT * FROM connections".to_string();
+		updated.password = "new'pass;\"".to_string();
+		updated.auth_method = AuthMethod::PrivateKey as i8;
+		updated.private_key = "-----BEGIN KEY-----\n'key'; --\n-----END KEY-----".to_string();
+		update_connection(id, updated.clone(), None, None).unwrap();
+		assert_stored(&updated, &get_connection_by_id(id, Some(true)).unwrap());
+		assert_stored(&updated, &by_name(&updated.name));
+		assert_eq!(get_connections(None).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn empty_secrets_are_kept_unless_cleared() {
+		let _database = TestDatabase::new("clear-secrets");
+		let mut stored = connection("keep");
+		stored.auth_method = AuthMethod::PrivateKey as i8;
+		stored.private_key = "key".to_string();
+		let id = insert_connection(stored.clone()).unwrap();
+
+		// Connections come back without secrets, saving them as they are keeps the stored ones
+		let returned = get_connection_by_id(id, None).unwrap();
+		update_connection(id, returned.clone(), None, None).unwrap();
+		assert_stored(&stored, &get_connection_by_id(id, Some(true)).unwrap());
+
+		update_connection(id, returned.clone(), Some(true), None).unwrap();
+		let cleared = get_connection_by_id(id, Some(true)).unwrap();
+		assert_eq!(cleared.password, "");
+		assert_eq!(cleared.private_key, "key");
+
+		update_connection(id, returned, None, Some(true)).unwrap();
+		assert_eq!(get_connection_by_id(id, Some(true)).unwrap().private_key, "");
+	}
+
+	#[test]
+	fn changing_the_auth_method_drops_secrets_that_no_longer_apply() {
+		let _database = TestDatabase::new("auth-method");
+		let id = insert_connection(connection("switch")).unwrap();
+
+		// The login password must not become the passphrase of the key file
+		let mut key_file = get_connection_by_id(id, None).unwrap();
+		key_file.auth_method = AuthMethod::KeyFile as i8;
+		key_file.private_key_path = "/home/user/.ssh/id_ed25519".to_string();
+		update_connection(id, key_file, None, None).unwrap();
+		assert_eq!(get_connection_by_id(id, Some(true)).unwrap().password, "");
+
+		let mut private_key = get_connection_by_id(id, None).unwrap();
+		private_key.auth_method = AuthMethod::PrivateKey as i8;
+		private_key.private_key = "key".to_string();
+		update_connection(id, private_key, None, None).unwrap();
+
+		let mut agent = get_connection_by_id(id, None).unwrap();
+		agent.auth_method = AuthMethod::Agent as i8;
+		update_connection(id, agent, None, None).unwrap();
+		assert_eq!(get_connection_by_id(id, Some(true)).unwrap().private_key, "");
+	}
 }
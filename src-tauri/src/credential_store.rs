@@ -0,0 +1,501 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::connection_manager::{get_database_path, load_connection, Connection};
+use crate::error::BackendError;
+use crate::logger;
+use crate::session_manager::same_server;
+
+/// Prefix of encrypted values, values without it are plaintext rows that still have to be migrated.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Known plaintext encrypted with the key, used to check a master password without storing it.
+const VERIFIER: &str = "sftp-editor";
+
+/// Length of the AES-GCM nonce that is stored in front of every ciphertext.
+const NONCE_LENGTH: usize = 12;
+
+/// The service name of the OS keyring entries, the bundle identifier like the data directory uses.
+const KEYRING_SERVICE: &str = "chase.cargo.sftp-editor";
+
+/// The key derived from the master password, `None` while the credentials are locked.
+///
+/// This is a static rather than managed state because connections are also opened from threads
+/// that have no access to the application handle.
+static KEY: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+
+/// Whether the current key of a store without master password is derived from a secret in the OS keyring.
+static IN_KEYRING: AtomicBool = AtomicBool::new(false);
+
+/// Whether a master password is set and whether the credentials are currently unlocked.
+#[derive(Clone, serde::Serialize)]
+pub struct CredentialStatus {
+	protected: bool,
+	unlocked: bool,
+	/// Whether the key of a store without master password is kept in the OS keyring. If not, the key is derived from
+	/// an empty password and the secrets are only obfuscated, as the salt is stored beside them.
+	keyring: bool,
+}
+
+/// The row of the `credential_store` table.
+struct StoreInfo {
+	salt: Vec<u8>,
+	verifier: String,
+	protected: bool,
+}
+
+/// Creates the `credential_store` table and unlocks the credentials if no master password is set.
+///
+/// On the first start a store without master password is created and all plaintext passwords and private keys
+/// are encrypted in place. If a master password is set, the credentials stay locked until `unlock_credentials`.
+///
+/// Without master password the key is derived from a random secret in the OS keyring, see `new_unprotected_password`.
+///
+/// # Returns
+/// * `Ok(())` if the initialization is successful
+/// * `Err(BackendError)` if there's an error
+pub fn initialize() -> Result<(), BackendError> {
+	let lite = sqlite::open(get_database_path()).map_err(|e| BackendError::database(format!("Failed to open database: {}", e)))?;
+
+	// The table only ever holds a single row
+	// salt: The base64 encoded salt the key is derived with.
+	// verifier: A known value encrypted with the key, to check the master password.
+	// protected: Whether the user set a master password, otherwise the key is derived from an empty password.
+	lite.execute(
+		"CREATE TABLE IF NOT EXISTS `credential_store` (
+        'id' INTEGER PRIMARY KEY CHECK (id = 1),
+        'salt' TEXT NOT NULL,
+        'verifier' TEXT NOT NULL,
+        'protected' BOOLEAN NOT NULL
+            )",
+	).map_err(|e| BackendError::database(format!("Code: {:?}, Message: {:?}", e.code, e.message)))?;
+
+	match read_store(&lite)? {
+		Some(store) if store.protected => Ok(()),
+		Some(store) => unlock_unprotected(&lite, &store),
+		None => {
+			let salt = generate_salt();
+			let password = new_unprotected_password(&salt);
+			let key = write_store(&lite, &salt, &password, false)?;
+			migrate(&lite, &key)?;
+			IN_KEYRING.store(!password.is_empty(), Ordering::SeqCst);
+			set_key(Some(key))
+		}
+	}
+}
+
+/// Returns the OS keyring entry that holds the secret of a store without master password.
+///
+/// There is one entry per salt, so several databases, e.g. of portable installs, do not overwrite each other's secret.
+fn keyring_entry(salt: &[u8]) -> Option<keyring::Entry> {
+	// Tests must not leave entries in the keyring of the developer
+	if cfg!(test) {
+		return None;
+	}
+	let salt: String = salt.iter().map(|byte| format!("{:02x}", byte)).collect();
+	keyring::Entry::new(KEYRING_SERVICE, &format!("credential-store-{}", salt)).ok()
+}
+
+/// Creates a random secret for a store without master password and saves it in the OS keyring.
+///
+/// # Returns
+///
+/// The secret, or an empty password if no keyring is available, e.g. on Linux without a Secret Service.
+fn new_unprotected_password(salt: &[u8]) -> String {
+	let mut secret = [0u8; 32];
+	OsRng.fill_bytes(&mut secret);
+	let secret = STANDARD.encode(secret);
+	match keyring_entry(salt).map(|entry| entry.set_password(&secret)) {
+		Some(Ok(())) => secret,
+		_ => String::new(),
+	}
+}
+
+/// Removes the keyring secret of a salt that is no longer used, if there is one.
+fn forget_unprotected_password(salt: &[u8]) {
+	if let Some(entry) = keyring_entry(salt) {
+		let _ = entry.delete_password();
+	}
+}
+
+/// Unlocks a store without master password and moves its key into the OS keyring if it is not there yet.
+///
+/// Stores of older versions, and stores created while no keyring was available, derive their key from an empty
+/// password. A store that can not be unlocked, e.g. because the keyring is not available at the moment, stays
+/// locked instead of failing the start.
+fn unlock_unprotected(lite: &sqlite::Connection, store: &StoreInfo) -> Result<(), BackendError> {
+	if let Some(Ok(secret)) = keyring_entry(&store.salt).map(|entry| entry.get_password()) {
+		if let Ok(key) = check_password(lite, &secret) {
+			migrate(lite, &key)?;
+			IN_KEYRING.store(true, Ordering::SeqCst);
+			return set_key(Some(key));
+		}
+	}
+
+	let key = match check_password(lite, "") {
+		Ok(key) => key,
+		Err(_) => {
+			let _ = logger::log("The key of the stored credentials could not be read from the OS keyring, they stay locked", "", logger::LOG_WARN);
+			return Ok(());
+		}
+	};
+	migrate(lite, &key)?;
+
+	let salt = generate_salt();
+	let password = new_unprotected_password(&salt);
+	if password.is_empty() {
+		IN_KEYRING.store(false, Ordering::SeqCst);
+		return set_key(Some(key));
+	}
+	match rekey(lite, &key, &salt, &password, false) {
+		Ok(new_key) => {
+			IN_KEYRING.store(true, Ordering::SeqCst);
+			set_key(Some(new_key))
+		}
+		Err(e) => {
+			forget_unprotected_password(&salt);
+			Err(e)
+		}
+	}
+}
+
+/// Derives the encryption key from a master password with Argon2id.
+pub(crate) fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32], BackendError> {
+	let mut key = [0u8; 32];
+	Argon2::default().hash_password_into(password.as_bytes(), salt, &mut key).map_err(|e| BackendError::other(format!("Failed to derive key: {}", e)))?;
+	Ok(key)
+}
+
+/// Generates a random salt to derive a key with.
+pub(crate) fn generate_salt() -> [u8; 16] {
+	let mut salt = [0u8; 16];
+	OsRng.fill_bytes(&mut salt);
+	salt
+}
+
+/// Encrypts a value with AES-256-GCM, empty values stay empty so it remains visible that no secret is set.
+pub(crate) fn encrypt_with(key: &[u8; 32], plaintext: &str) -> Result<String, BackendError> {
+	if plaintext.is_empty() {
+		return Ok(String::new());
+	}
+	let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+	let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+	let ciphertext = cipher.encrypt(&nonce, plaintext.as_bytes()).map_err(|e| BackendError::other(format!("Failed to encrypt secret: {}", e)))?;
+
+	// Store the nonce in front of the ciphertext, it is needed for decryption
+	let mut data = nonce.to_vec();
+	data.extend_from_slice(&ciphertext);
+	Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(data)))
+}
+
+/// Decrypts a value encrypted by `encrypt_with`, values that are not encrypted yet are returned as they are.
+pub(crate) fn decrypt_with(key: &[u8; 32], value: &str) -> Result<String, BackendError> {
+	let encoded = match value.strip_prefix(ENCRYPTED_PREFIX) {
+		Some(encoded) => encoded,
+		None => return Ok(value.to_string()),
+	};
+	let data = STANDARD.decode(encoded).map_err(|e| BackendError::invalid_input(format!("Failed to decode secret: {}", e)))?;
+	if data.len() < NONCE_LENGTH {
+		return Err(BackendError::invalid_input("Failed to decrypt secret: the value is too short"));
+	}
+	let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+	let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+	let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| BackendError::invalid_input("Failed to decrypt secret: wrong key or corrupted value"))?;
+	String::from_utf8(plaintext).map_err(|e| BackendError::invalid_input(format!("Failed to decrypt secret: {}", e)))
+}
+
+/// Returns the current key, or an error if the credentials are locked.
+fn current_key() -> Result<[u8; 32], BackendError> {
+	KEY.lock().map_err(|e| BackendError::other(format!("Credential state is poisoned: {:?}", e)))?
+		.ok_or_else(|| BackendError::credentials_locked("Credentials are locked, unlock them with the master password first"))
+}
+
+/// Replaces the current key.
+fn set_key(key: Option<[u8; 32]>) -> Result<(), BackendError> {
+	*KEY.lock().map_err(|e| BackendError::other(format!("Credential state is poisoned: {:?}", e)))? = key;
+	Ok(())
+}
+
+/// Encrypts a secret with the current key.
+///
+/// # Returns
+///
+/// The encrypted value, or an error if the credentials are locked.
+pub fn encrypt(plaintext: &str) -> Result<String, BackendError> {
+	encrypt_with(&current_key()?, plaintext)
+}
+
+/// Decrypts a secret with the current key.
+///
+/// # Returns
+///
+/// The plaintext value, or an error if the credentials are locked or the value can not be decrypted.
+pub fn decrypt(value: &str) -> Result<String, BackendError> {
+	decrypt_with(&current_key()?, value)
+}
+
+/// Decrypts the secrets of a connection read from the database, or clears them if they were not requested.
+///
+/// # Arguments
+///
+/// * `connection` - The connection as it was read from the database.
+/// * `include_secrets` - Whether to decrypt the password and private key instead of clearing them.
+pub fn reveal_secrets(connection: &mut Connection, include_secrets: bool) -> Result<(), BackendError> {
+	if include_secrets {
+		connection.password = decrypt(&connection.password)?;
+		connection.private_key = decrypt(&connection.private_key)?;
+	} else {
+		connection.password.clear();
+		connection.private_key.clear();
+	}
+	Ok(())
+}
+
+/// Fills in the stored password and private key of a saved connection before connecting.
+///
+/// Connections returned to the frontend have their secrets cleared, so a connection without any secret is
+/// looked up by its id. The secrets are only filled in if the connection still points at the server they were
+/// stored for, otherwise passing the id of another connection with a different host would send its secrets there.
+/// Connections that are not saved yet, e.g. while testing a new one, are left as they are.
+pub fn fill_secrets(options: &mut Connection) -> Result<(), BackendError> {
+	if !options.password.is_empty() || !options.private_key.is_empty() {
+		return Ok(());
+	}
+	match load_connection(options.id)? {
+		Some(stored) if same_server(&stored, options) => {
+			options.password = decrypt(&stored.password)?;
+			options.private_key = decrypt(&stored.private_key)?;
+		}
+		_ => (),
+	}
+	Ok(())
+}
+
+/// Reads the row of the `credential_store` table, if it exists.
+fn read_store(lite: &sqlite::Connection) -> Result<Option<StoreInfo>, BackendError> {
+	let mut statement = lite.prepare("SELECT `salt`, `verifier`, `protected` FROM `credential_store` WHERE id = 1")
+		.map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+	if statement.next().map_err(|e| BackendError::database(format!("Failed to read credential store: {}", e)))? != sqlite::State::Row {
+		return Ok(None);
+	}
+	let salt = statement.read::<String, usize>(0).map_err(|e| BackendError::database(format!("Failed to get salt from database: {}", e)))?;
+	Ok(Some(StoreInfo {
+		salt: STANDARD.decode(salt).map_err(|e| BackendError::invalid_input(format!("Failed to decode salt: {}", e)))?,
+		verifier: statement.read::<String, usize>(1).map_err(|e| BackendError::database(format!("Failed to get verifier from database: {}", e)))?,
+		protected: statement.read::<i64, usize>(2).map_err(|e| BackendError::database(format!("Failed to get protected from database: {}", e)))? != 0,
+	}))
+}
+
+/// Writes a new salt and verifier for the password the key is derived from.
+///
+/// # Arguments
+///
+/// * `lite` - The open database connection.
+/// * `salt` - The new salt.
+/// * `password` - The master password, or the secret of a store without master password.
+/// * `protected` - Whether `password` is a master password the user has to enter.
+///
+/// # Returns
+///
+/// The key derived from the password.
+fn write_store(lite: &sqlite::Connection, salt: &[u8], password: &str, protected: bool) -> Result<[u8; 32], BackendError> {
+	let key = derive_key(password, salt)?;
+
+	let mut statement = lite.prepare("INSERT OR REPLACE INTO `credential_store` ('id', 'salt', 'verifier', 'protected') VALUES (1, ?, ?, ?)")
+		.map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+	statement.bind((1, STANDARD.encode(salt).as_str())).map_err(|e| BackendError::database(format!("Failed to bind salt: {}", e)))?;
+	statement.bind((2, encrypt_with(&key, VERIFIER)?.as_str())).map_err(|e| BackendError::database(format!("Failed to bind verifier: {}", e)))?;
+	statement.bind((3, protected as i64)).map_err(|e| BackendError::database(format!("Failed to bind protected: {}", e)))?;
+	while statement.next().map_err(|e| BackendError::database(format!("Failed to write credential store: {}", e)))? != sqlite::State::Done {}
+	Ok(key)
+}
+
+/// Derives the key from a master password and checks it against the stored verifier.
+fn check_password(lite: &sqlite::Connection, password: &str) -> Result<[u8; 32], BackendError> {
+	let store = read_store(lite)?.ok_or_else(|| BackendError::database("The credential store is not initialized"))?;
+	let key = derive_key(password, &store.salt)?;
+	match decrypt_with(&key, &store.verifier) {
+		Ok(value) if value == VERIFIER => Ok(key),
+		_ => Err(BackendError::auth_failed("Wrong master password")),
+	}
+}
+
+/// Writes a new store and re-encrypts all stored secrets with its key, either completely or not at all.
+///
+/// # Returns
+///
+/// The new key.
+fn rekey(lite: &sqlite::Connection, old_key: &[u8; 32], salt: &[u8], password: &str, protected: bool) -> Result<[u8; 32], BackendError> {
+	lite.execute("BEGIN TRANSACTION").map_err(|e| BackendError::database(format!("Failed to begin transaction: {}", e)))?;
+	let result = write_store(lite, salt, password, protected).and_then(|new_key| reencrypt(lite, old_key, &new_key).map(|_| new_key));
+	match result {
+		Ok(new_key) => {
+			lite.execute("COMMIT").map_err(|e| BackendError::database(format!("Failed to commit transaction: {}", e)))?;
+			Ok(new_key)
+		}
+		Err(e) => {
+			let _ = lite.execute("ROLLBACK");
+			Err(e)
+		}
+	}
+}
+
+/// Unlocks the credentials and encrypts any plaintext rows that are left.
+fn unlock(lite: &sqlite::Connection, password: &str) -> Result<(), BackendError> {
+	let key = check_password(lite, password)?;
+	migrate(lite, &key)?;
+	set_key(Some(key))
+}
+
+/// Re-encrypts the password and private key of every connection.
+///
+/// Plaintext values are decrypted as they are, so this also migrates rows written before encryption was added.
+///
+/// # Arguments
+///
+/// * `lite` - The open database connection.
+/// * `old_key` - The key the values are currently encrypted with.
+/// * `new_key` - The key to encrypt the values with.
+fn reencrypt(lite: &sqlite::Connection, old_key: &[u8; 32], new_key: &[u8; 32]) -> Result<(), BackendError> {
+	// Read all rows first, the table can not be updated while the statement is iterating over it
+	let mut rows = Vec::new();
+	let mut statement = lite.prepare("SELECT `id`, `password`, `private_key` FROM `connections`")
+		.map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+	while statement.next().map_err(|e| BackendError::database(format!("Failed to read connections: {}", e)))? == sqlite::State::Row {
+		rows.push((
+			statement.read::<i64, usize>(0).map_err(|e| BackendError::database(format!("Failed to get id from database: {}", e)))?,
+			statement.read::<String, usize>(1).map_err(|e| BackendError::database(format!("Failed to get password from database: {}", e)))?,
+			statement.read::<String, usize>(2).map_err(|e| BackendError::database(format!("Failed to get private_key from database: {}", e)))?,
+		));
+	}
+
+	for (id, password, private_key) in rows {
+		let mut update = lite.prepare("UPDATE `connections` SET 'password' = ?, 'private_key' = ? WHERE id = ?")
+			.map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+		update.bind((1, encrypt_with(new_key, &decrypt_with(old_key, &password)?)?.as_str())).map_err(|e| BackendError::database(format!("Failed to bind password: {}", e)))?;
+		update.bind((2, encrypt_with(new_key, &decrypt_with(old_key, &private_key)?)?.as_str())).map_err(|e| BackendError::database(format!("Failed to bind private_key: {}", e)))?;
+		update.bind((3, id)).map_err(|e| BackendError::database(format!("Failed to bind id: {}", e)))?;
+		while update.next().map_err(|e| BackendError::database(format!("Failed to update connection: {}", e)))? != sqlite::State::Done {}
+	}
+	Ok(())
+}
+
+/// Encrypts the secrets of rows that are still stored in plaintext, already encrypted rows are left untouched.
+fn migrate(lite: &sqlite::Connection, key: &[u8; 32]) -> Result<(), BackendError> {
+	let mut statement = lite.prepare("SELECT COUNT(*) FROM `connections` WHERE (`password` != '' AND `password` NOT LIKE 'enc:v1:%') OR (`private_key` != '' AND `private_key` NOT LIKE 'enc:v1:%')")
+		.map_err(|e| BackendError::database(format!("Failed to prepare statement: {}", e)))?;
+	statement.next().map_err(|e| BackendError::database(format!("Failed to count plaintext connections: {}", e)))?;
+	if statement.read::<i64, usize>(0).map_err(|e| BackendError::database(format!("Failed to count plaintext connections: {}", e)))? == 0 {
+		return Ok(());
+	}
+	reencrypt(lite, key, key)
+}
+
+/// Retrieves whether a master password is set and whether the credentials are unlocked.
+#[tauri::command]
+pub fn get_credential_status() -> Result<CredentialStatus, BackendError> {
+	let lite = sqlite::open(get_database_path()).map_err(|e| BackendError::database(format!("Failed to open database: {}", e)))?;
+	let protected = read_store(&lite)?.map(|store| store.protected).unwrap_or(false);
+	Ok(CredentialStatus {
+		protected,
+		unlocked: current_key().is_ok(),
+		keyring: !protected && IN_KEYRING.load(Ordering::SeqCst),
+	})
+}
+
+/// Unlocks the stored passwords and private keys with the master password, called at startup if a master password is set.
+///
+/// # Arguments
+///
+/// * `master_password` - The master password.
+#[tauri::command]
+pub fn unlock_credentials(master_password: String) -> Result<(), BackendError> {
+	let lite = sqlite::open(get_database_path()).map_err(|e| BackendError::database(format!("Failed to open database: {}", e)))?;
+	unlock(&lite, &master_password)
+}
+
+/// Forgets the key, connections using stored secrets fail until the credentials are unlocked again.
+#[tauri::command]
+pub fn lock_credentials() -> Result<(), BackendError> {
+	set_key(None)
+}
+
+/// Sets, changes or removes the master password and re-encrypts all stored secrets with the new key.
+///
+/// # Arguments
+///
+/// * `current_password` - The current master password, ignored if none is set as the credentials are unlocked then.
+/// * `new_password` - The new master password, empty to remove the master password.
+#[tauri::command]
+pub fn set_master_password(current_password: String, new_password: String) -> Result<(), BackendError> {
+	let lite = sqlite::open(get_database_path()).map_err(|e| BackendError::database(format!("Failed to open database: {}", e)))?;
+	let store = read_store(&lite)?.ok_or_else(|| BackendError::database("The credential store is not initialized"))?;
+	let old_key = if store.protected { check_password(&lite, &current_password)? } else { current_key()? };
+
+	// Without master password the key is derived from a new secret in the OS keyring
+	let salt = generate_salt();
+	let protected = !new_password.is_empty();
+	let password = if protected { new_password } else { new_unprotected_password(&salt) };
+	let new_key = match rekey(&lite, &old_key, &salt, &password, protected) {
+		Ok(new_key) => new_key,
+		Err(e) => {
+			forget_unprotected_password(&salt);
+			return Err(e);
+		}
+	};
+	forget_unprotected_password(&store.salt);
+	IN_KEYRING.store(!protected && !password.is_empty(), Ordering::SeqCst);
+	set_key(Some(new_key))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::connection_manager::insert_connection;
+	use crate::connection_manager::tests::{connection, TestDatabase};
+
+	/// The stored connection as the frontend sends it back, with the secrets cleared.
+	fn returned(id: i32) -> Connection {
+		let mut options = connection("stored");
+		options.id = id;
+		options.password.clear();
+		options
+	}
+
+	#[test]
+	fn secrets_are_filled_for_the_stored_server() {
+		let _database = TestDatabase::new("fill-secrets");
+		let id = insert_connection(connection("stored")).unwrap();
+
+		let mut options = returned(id);
+		fill_secrets(&mut options).unwrap();
+		assert_eq!(options.password, "password");
+	}
+
+	#[test]
+	fn secrets_are_not_sent_to_another_server() {
+		let _database = TestDatabase::new("leak-secrets");
+		let id = insert_connection(connection("stored")).unwrap();
+
+		let changes: [fn(&mut Connection); 5] = [
+			|options| options.host = "attacker.example".to_string(),
+			|options| options.port = 2222,
+			|options| options.username = "other".to_string(),
+			|options| options.protocol = 1,
+			|options| options.jump_host_id = Some(7),
+		];
+		for change in changes {
+			let mut options = returned(id);
+			change(&mut options);
+			fill_secrets(&mut options).unwrap();
+			assert_eq!(options.password, "");
+		}
+	}
+}
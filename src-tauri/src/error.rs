@@ -0,0 +1,168 @@
+use std::fmt;
+use std::io;
+
+/// An error returned by the backend.
+///
+/// Commands return this instead of a formatted string, it is serialized as e.g.
+/// `{ "kind": "NotFound", "message": "No such file: /tmp/a" }` so the frontend can react to the kind
+/// and still show the message to the user.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "kind")]
+pub enum BackendError {
+	/// The server rejected the credentials, or the user cancelled the authentication.
+	AuthFailed { message: String },
+	/// The server is not in any known_hosts file yet, the user may choose to trust the key.
+	UnknownHostKey { message: String, host: String, port: u16, key_type: String, fingerprint: String },
+	/// The server is known with a different key, this may be a man-in-the-middle attack.
+	HostKeyMismatch { message: String, host: String, port: u16, key_type: String, fingerprint: String },
+	/// A file, directory or saved connection does not exist.
+	NotFound { message: String },
+	/// The server or the local system refused access.
+	PermissionDenied { message: String },
+	/// The server did not answer in time.
+	Timeout { message: String },
+	/// The operation was cancelled by the user.
+	Cancelled { message: String },
+	/// The server answered with an error or something unexpected, e.g. an SFTP or FTP failure.
+	Protocol { message: String },
+	/// Reading or writing the connection database failed.
+	Database { message: String },
+	/// The stored credentials are locked and have to be unlocked with the master password first.
+	CredentialsLocked { message: String },
+	/// The arguments of a command or the contents of a file are not valid.
+	InvalidInput { message: String },
+	/// A local file or network operation failed.
+	Io { message: String },
+	/// Anything without a more specific kind.
+	Other { message: String },
+}
+
+impl BackendError {
+	pub fn auth_failed(message: impl Into<String>) -> Self {
+		BackendError::AuthFailed { message: message.into() }
+	}
+
+	pub fn not_found(message: impl Into<String>) -> Self {
+		BackendError::NotFound { message: message.into() }
+	}
+
+	pub fn permission_denied(message: impl Into<String>) -> Self {
+		BackendError::PermissionDenied { message: message.into() }
+	}
+
+	pub fn timeout(message: impl Into<String>) -> Self {
+		BackendError::Timeout { message: message.into() }
+	}
+
+	pub fn cancelled(message: impl Into<String>) -> Self {
+		BackendError::Cancelled { message: message.into() }
+	}
+
+	pub fn protocol(message: impl Into<String>) -> Self {
+		BackendError::Protocol { message: message.into() }
+	}
+
+	pub fn database(message: impl Into<String>) -> Self {
+		BackendError::Database { message: message.into() }
+	}
+
+	pub fn credentials_locked(message: impl Into<String>) -> Self {
+		BackendError::CredentialsLocked { message: message.into() }
+	}
+
+	pub fn invalid_input(message: impl Into<String>) -> Self {
+		BackendError::InvalidInput { message: message.into() }
+	}
+
+	pub fn other(message: impl Into<String>) -> Self {
+		BackendError::Other { message: message.into() }
+	}
+
+	/// Classifies an error of libssh2, e.g. a missing remote file is `NotFound`.
+	///
+	/// # Arguments
+	///
+	/// * `context` - What was being done, put in front of the message of libssh2.
+	/// * `error` - The error of libssh2.
+	pub fn ssh(context: &str, error: &ssh2::Error) -> Self {
+		let message = format!("{}: {}", context, error.message());
+		match error.code() {
+			// LIBSSH2_ERROR_AUTHENTICATION_FAILED and LIBSSH2_ERROR_PUBLICKEY_UNVERIFIED
+			ssh2::ErrorCode::Session(-18) | ssh2::ErrorCode::Session(-19) => BackendError::auth_failed(message),
+			// LIBSSH2_ERROR_TIMEOUT and LIBSSH2_ERROR_SOCKET_TIMEOUT
+			ssh2::ErrorCode::Session(-9) | ssh2::ErrorCode::Session(-30) => BackendError::timeout(message),
+			// LIBSSH2_FX_NO_SUCH_FILE and LIBSSH2_FX_NO_SUCH_PATH
+			ssh2::ErrorCode::SFTP(2) | ssh2::ErrorCode::SFTP(10) => BackendError::not_found(message),
+			// LIBSSH2_FX_PERMISSION_DENIED and LIBSSH2_FX_WRITE_PROTECT
+			ssh2::ErrorCode::SFTP(3) | ssh2::ErrorCode::SFTP(12) => BackendError::permission_denied(message),
+			_ => BackendError::protocol(message),
+		}
+	}
+
+	/// Classifies a local I/O or socket error, e.g. a timed out read is `Timeout`.
+	///
+	/// # Arguments
+	///
+	/// * `context` - What was being done, put in front of the message of the error.
+	/// * `error` - The I/O error.
+	pub fn io(context: &str, error: &io::Error) -> Self {
+		let message = format!("{}: {}", context, error);
+		match error.kind() {
+			io::ErrorKind::NotFound => BackendError::not_found(message),
+			io::ErrorKind::PermissionDenied => BackendError::permission_denied(message),
+			io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => BackendError::timeout(message),
+			_ => BackendError::Io { message },
+		}
+	}
+
+	/// Returns the message without the kind.
+	pub fn message(&self) -> &str {
+		match self {
+			BackendError::AuthFailed { message }
+			| BackendError::UnknownHostKey { message, .. }
+			| BackendError::HostKeyMismatch { message, .. }
+			| BackendError::NotFound { message }
+			| BackendError::PermissionDenied { message }
+			| BackendError::Timeout { message }
+			| BackendError::Cancelled { message }
+			| BackendError::Protocol { message }
+			| BackendError::Database { message }
+			| BackendError::CredentialsLocked { message }
+			| BackendError::InvalidInput { message }
+			| BackendError::Io { message }
+			| BackendError::Other { message } => message,
+		}
+	}
+}
+
+impl fmt::Display for BackendError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.message())
+	}
+}
+
+impl std::error::Error for BackendError {}
+
+impl From<io::Error> for BackendError {
+	fn from(error: io::Error) -> Self {
+		BackendError::io("I/O error", &error)
+	}
+}
+
+impl From<ssh2::Error> for BackendError {
+	fn from(error: ssh2::Error) -> Self {
+		BackendError::ssh("SSH error", &error)
+	}
+}
+
+impl From<tauri::Error> for BackendError {
+	fn from(error: tauri::Error) -> Self {
+		BackendError::other(error.to_string())
+	}
+}
+
+impl From<sqlite::Error> for BackendError {
+	fn from(error: sqlite::Error) -> Self {
+		BackendError::database(format!("Database error: {}", error))
+	}
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::{AppHandle, Manager, State};
+
+use crate::app_paths;
+use crate::auth_prompt;
+use crate::connection_manager::Connection;
+use crate::error::BackendError;
+use crate::operations::{run_blocking, Operation};
+use crate::remote_fs::with_remote_fs;
+use crate::session_manager::SessionManager;
+
+/// Editors often write a file in several steps, changes are uploaded once the file has been quiet this long.
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// A remote file that is being edited through a local copy.
+struct EditSession {
+	options: Connection,
+	remote_path: String,
+	/// Modification time of the remote file when it was last downloaded or uploaded.
+	remote_modified: u64,
+	/// Size of the remote file when it was last downloaded or uploaded.
+	remote_size: u64,
+	/// Modification time of the local copy that was last synchronized, to ignore events without changes.
+	local_modified: Option<SystemTime>,
+	/// Dropping the watcher stops the upload thread of the session.
+	_watcher: RecommendedWatcher,
+}
+
+/// Emitted as `edit-conflict` when the remote file changed since it was downloaded.
+#[derive(Clone, serde::Serialize)]
+pub struct EditConflict {
+	local_path: String,
+	remote_path: String,
+	expected_modified: u64,
+	expected_size: u64,
+	remote_modified: u64,
+	remote_size: u64,
+}
+
+/// Emitted as `edit-uploaded` after a local save was uploaded, or as `edit-error` if the upload failed.
+#[derive(Clone, serde::Serialize)]
+pub struct EditStatus {
+	local_path: String,
+	remote_path: String,
+	error: Option<BackendError>,
+}
+
+/// Keeps track of the files that are open for editing, keyed by the path of their local copy.
+#[derive(Default)]
+pub struct EditorManager {
+	sessions: Mutex<HashMap<PathBuf, EditSession>>,
+}
+
+impl EditorManager {
+	/// Creates an editor manager without any open files.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+/// Returns the local cache path of a remote file, every connection has its own cache directory.
+///
+/// # Arguments
+///
+/// * `options` - The connection the file belongs to.
+/// * `remote_path` - The path of the remote file.
+fn cache_path(options: &Connection, remote_path: &str) -> Result<PathBuf, BackendError> {
+	let mut path = app_paths::cache_directory("edits").map_err(|e| BackendError::io("Error creating cache directory", &e))?.join(options.id.to_string());
+	// Mirror the remote directory structure, skipping anything that could escape the cache directory
+	for component in remote_path.split('/').filter(|c| !c.is_empty() && *c != "." && *c != "..") {
+		path.push(component);
+	}
+	Ok(path)
+}
+
+/// Opens a file with the default application of the operating system.
+fn open_with_default_application(path: &Path) -> Result<(), BackendError> {
+	#[cfg(target_os = "windows")]
+	let result = std::process::Command::new("cmd").args(["/C", "start", ""]).arg(path).spawn();
+	#[cfg(target_os = "macos")]
+	let result = std::process::Command::new("open").arg(path).spawn();
+	#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+	let result = std::process::Command::new("xdg-open").arg(path).spawn();
+
+	result.map(|_| ()).map_err(|e| BackendError::io(&format!("Error opening {}", path.display()), &e))
+}
+
+/// Downloads a remote file into its local cache path.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to reach the session manager.
+/// * `options` - The connection the file belongs to.
+/// * `remote_path` - The path of the remote file.
+/// * `local_path` - The cache path to download to.
+/// * `operation` - The operation of the command, so the download can be cancelled.
+///
+/// # Returns
+///
+/// The modification time and size of the remote file.
+fn download_to_cache(app: &AppHandle, options: &Connection, remote_path: &str, local_path: &Path, operation: &Operation) -> Result<(u64, u64), BackendError> {
+	if let Some(parent) = local_path.parent() {
+		app_paths::create_private_directory(parent).map_err(|e| BackendError::io("Error creating cache directory", &e))?;
+	}
+	let sessions = app.state::<SessionManager>();
+	with_remote_fs(options, &sessions, |fs| {
+		operation.attach(fs.socket());
+		let stat = fs.stat(remote_path)?;
+		let local_file = app_paths::create_private_file(local_path).map_err(|e| BackendError::io("Error creating local file", &e))?;
+		fs.read(remote_path, &mut operation.guard(local_file))?;
+		Ok((stat.modified, stat.size))
+	})
+}
+
+/// Uploads the local copy of an edited file, unless the remote file changed since it was downloaded.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to reach the managed state and emit events.
+/// * `local_path` - The path of the local copy.
+/// * `force` - Upload even if the remote file changed.
+/// * `operation` - The operation of the command that asked for the upload, `None` for uploads after a save.
+///
+/// # Returns
+///
+/// An error if the file is not being edited or the upload failed, a conflict is only reported as event.
+fn synchronize(app: &AppHandle, local_path: &Path, force: bool, operation: Option<&Operation>) -> Result<(), BackendError> {
+	let editors = app.state::<EditorManager>();
+
+	// Copy what is needed out of the session so the lock is not held during the upload
+	let (options, remote_path, expected_modified, expected_size) = {
+		let mut sessions = editors.sessions.lock().map_err(|e| BackendError::other(format!("Editor state is poisoned: {:?}", e)))?;
+		let session = sessions.get_mut(local_path).ok_or_else(|| BackendError::not_found(format!("{} is not being edited", local_path.display())))?;
+
+		// Ignore events that did not change the file, e.g. our own download
+		let local_modified = fs::metadata(local_path).and_then(|metadata| metadata.modified()).ok();
+		if !force && local_modified == session.local_modified {
+			return Ok(());
+		}
+		session.local_modified = local_modified;
+		(session.options.clone(), session.remote_path.clone(), session.remote_modified, session.remote_size)
+	};
+
+	let status = |error: Option<BackendError>| EditStatus {
+		local_path: local_path.to_string_lossy().to_string(),
+		remote_path: remote_path.clone(),
+		error,
+	};
+
+	let sessions = app.state::<SessionManager>();
+	let result = with_remote_fs(&options, &sessions, |fs| {
+		if let Some(operation) = operation {
+			operation.attach(fs.socket());
+		}
+
+		// Refuse to overwrite changes somebody else made on the server
+		let remote = fs.stat(&remote_path)?;
+		if !force && (remote.modified != expected_modified || remote.size != expected_size) {
+			return Ok(Some(EditConflict {
+				local_path: local_path.to_string_lossy().to_string(),
+				remote_path: remote_path.clone(),
+				expected_modified,
+				expected_size,
+				remote_modified: remote.modified,
+				remote_size: remote.size,
+			}));
+		}
+
+		let mut local_file = fs::File::open(local_path).map_err(|e| BackendError::io("Error opening local file", &e))?;
+		fs.write(&remote_path, &mut local_file)?;
+
+		// Remember the new remote state so the next save is compared against our own upload
+		let uploaded = fs.stat(&remote_path)?;
+		if let Ok(mut editors) = editors.sessions.lock() {
+			if let Some(session) = editors.get_mut(local_path) {
+				session.remote_modified = uploaded.modified;
+				session.remote_size = uploaded.size;
+			}
+		}
+		Ok(None)
+	});
+
+	match result {
+		Ok(Some(conflict)) => {
+			let _ = app.emit_all("edit-conflict", conflict);
+			Ok(())
+		}
+		Ok(None) => {
+			let _ = app.emit_all("edit-uploaded", status(None));
+			Ok(())
+		}
+		Err(e) => {
+			let _ = app.emit_all("edit-error", status(Some(e.clone())));
+			Err(e)
+		}
+	}
+}
+
+/// Waits for changes of the local copy and uploads it once the editor is done writing.
+///
+/// The thread ends when the watcher, and with it the sending half of `events`, is dropped.
+fn watch_local_copy(app: AppHandle, local_path: PathBuf, events: Receiver<()>) {
+	while events.recv().is_ok() {
+		// Wait until the file has been quiet for a moment
+		loop {
+			match events.recv_timeout(SAVE_DEBOUNCE) {
+				Ok(()) => continue,
+				Err(RecvTimeoutError::Timeout) => break,
+				Err(RecvTimeoutError::Disconnected) => return,
+			}
+		}
+		// Failures are reported through the edit-error event
+		let _ = synchronize(&app, &local_path, false, None);
+	}
+}
+
+/// Downloads a remote file to the local cache of its connection and opens it for editing.
+///
+/// Every save of the local copy is uploaded back to the server. If the remote file changed since it was
+/// downloaded, an `edit-conflict` event is emitted instead and nothing is uploaded until the conflict is
+/// resolved with `resolve_edit_conflict`.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to run the download on the blocking thread pool.
+/// * `remote_path` - The remote file to edit.
+/// * `options` - The connection the file belongs to.
+/// * `open` - Whether to open the local copy in the default application, defaults to `true`.
+/// * `operation_id` - An id to cancel the download with, see `cancel_operation`.
+///
+/// # Returns
+///
+/// The path of the local copy.
+#[tauri::command]
+pub async fn edit_file(app: AppHandle, remote_path: String, options: Connection, open: Option<bool>, operation_id: Option<String>) -> Result<String, BackendError> {
+	run_blocking(app, operation_id, move |app, operation| start_editing(app, remote_path, options, open.unwrap_or(true), operation)).await
+}
+
+/// Downloads a remote file, watches its local copy and opens it, see `edit_file`.
+fn start_editing(app: &AppHandle, remote_path: String, options: Connection, open: bool, operation: &Operation) -> Result<String, BackendError> {
+	let editors = app.state::<EditorManager>();
+	let local_path = cache_path(&options, &remote_path)?;
+
+	// Stop watching a previous copy of the same file before overwriting it
+	if let Ok(mut sessions) = editors.sessions.lock() {
+		sessions.remove(&local_path);
+	}
+	let (remote_modified, remote_size) = download_to_cache(app, &options, &remote_path, &local_path, operation)?;
+
+	// Watch the directory rather than the file, many editors save by replacing the file
+	let (sender, receiver) = channel();
+	let watched = local_path.clone();
+	let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+		if let Ok(event) = event {
+			if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) && event.paths.iter().any(|path| path == &watched) {
+				let _ = sender.send(());
+			}
+		}
+	}).map_err(|e| BackendError::other(format!("Error creating file watcher: {:?}", e)))?;
+	let directory = local_path.parent().unwrap_or(Path::new("."));
+	watcher.watch(directory, RecursiveMode::NonRecursive).map_err(|e| BackendError::other(format!("Error watching {}: {:?}", directory.display(), e)))?;
+
+	editors.sessions.lock().map_err(|e| BackendError::other(format!("Editor state is poisoned: {:?}", e)))?.insert(local_path.clone(), EditSession {
+		options,
+		remote_path,
+		remote_modified,
+		remote_size,
+		local_modified: fs::metadata(&local_path).and_then(|metadata| metadata.modified()).ok(),
+		_watcher: watcher,
+	});
+
+	let thread_app = app.clone();
+	let thread_path = local_path.clone();
+	std::thread::spawn(move || auth_prompt::allow_prompts(|| watch_local_copy(thread_app, thread_path, receiver)));
+
+	if open {
+		open_with_default_application(&local_path)?;
+	}
+	Ok(local_path.to_string_lossy().to_string())
+}
+
+/// Resolves an edit conflict reported through an `edit-conflict` event.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to run the transfer on the blocking thread pool.
+/// * `local_path` - The path of the local copy, as reported in the event.
+/// * `overwrite` - `true` uploads the local copy anyway, `false` replaces it with the current remote file.
+/// * `operation_id` - An id to cancel the transfer with, see `cancel_operation`.
+#[tauri::command]
+pub async fn resolve_edit_conflict(app: AppHandle, local_path: String, overwrite: bool, operation_id: Option<String>) -> Result<(), BackendError> {
+	run_blocking(app, operation_id, move |app, operation| resolve(app, &PathBuf::from(local_path), overwrite, operation)).await
+}
+
+/// Uploads the local copy or replaces it with the remote file, see `resolve_edit_conflict`.
+fn resolve(app: &AppHandle, local_path: &Path, overwrite: bool, operation: &Operation) -> Result<(), BackendError> {
+	if overwrite {
+		return synchronize(app, local_path, true, Some(operation));
+	}
+
+	let editors = app.state::<EditorManager>();
+
+	// Take the remote version, the watcher ignores the download as the new local mtime is recorded first
+	let (options, remote_path) = match editors.sessions.lock().map_err(|e| BackendError::other(format!("Editor state is poisoned: {:?}", e)))?.get(local_path) {
+		Some(session) => (session.options.clone(), session.remote_path.clone()),
+		None => return Err(BackendError::not_found(format!("{} is not being edited", local_path.display()))),
+	};
+	let (remote_modified, remote_size) = download_to_cache(app, &options, &remote_path, local_path, operation)?;
+	if let Some(session) = editors.sessions.lock().map_err(|e| BackendError::other(format!("Editor state is poisoned: {:?}", e)))?.get_mut(local_path) {
+		session.remote_modified = remote_modified;
+		session.remote_size = remote_size;
+		session.local_modified = fs::metadata(local_path).and_then(|metadata| metadata.modified()).ok();
+	}
+	Ok(())
+}
+
+/// Stops watching the local copy of an edited file, the local copy itself is kept.
+///
+/// # Returns
+///
+/// `true` if the file was being edited, otherwise `false`.
+#[tauri::command]
+pub fn stop_editing(local_path: String, editors: State<EditorManager>) -> bool {
+	match editors.sessions.lock() {
+		Ok(mut sessions) => sessions.remove(Path::new(&local_path)).is_some(),
+		Err(_) => false,
+	}
+}
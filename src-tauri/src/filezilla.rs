@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use base64::Engine;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::connection_manager::Protocol;
+use crate::error::BackendError;
+use crate::importer::{self, ImportCandidate, ImportPreview};
+
+/// Returns the default location of the FileZilla site manager.
+fn default_path() -> Result<PathBuf, BackendError> {
+	// FileZilla keeps its settings in the roaming profile on Windows and in ~/.config everywhere else, including macOS
+	let directory = if cfg!(target_os = "windows") {
+		tauri::api::path::config_dir().map(|config| config.join("FileZilla"))
+	} else {
+		tauri::api::path::home_dir().map(|home| home.join(".config").join("filezilla"))
+	};
+	directory.map(|directory| directory.join("sitemanager.xml")).ok_or_else(|| BackendError::not_found("Could not determine the FileZilla settings directory"))
+}
+
+/// Reads the next space separated number of an encoded remote directory.
+fn take_number(rest: &mut &str) -> Option<usize> {
+	let trimmed = rest.trim_start_matches(' ');
+	let end = trimmed.find(' ').unwrap_or(trimmed.len());
+	let number = trimmed[..end].parse().ok()?;
+	*rest = &trimmed[end..];
+	Some(number)
+}
+
+/// Reads a segment of the given number of characters that follows a length, segments may contain spaces.
+fn take_segment<'a>(rest: &mut &'a str, length: usize) -> Option<&'a str> {
+	let trimmed = rest.strip_prefix(' ')?;
+	let end = trimmed.char_indices().nth(length).map(|(index, _)| index).unwrap_or(trimmed.len());
+	*rest = &trimmed[end..];
+	Some(&trimmed[..end])
+}
+
+/// Decodes a `RemoteDir` value, e.g. `1 0 4 home 4 user` is `/home/user`.
+///
+/// The value starts with the server type and the length of a prefix used by VMS servers, followed by the length
+/// and name of every directory segment.
+fn decode_remote_dir(value: &str) -> String {
+	let mut rest = value;
+	let (server_type, prefix) = match (take_number(&mut rest), take_number(&mut rest)) {
+		(Some(server_type), Some(prefix)) => (server_type, prefix),
+		_ => return String::new(),
+	};
+	if prefix > 0 && take_segment(&mut rest, prefix).is_none() {
+		return String::new();
+	}
+
+	let mut segments = Vec::new();
+	while let Some(length) = take_number(&mut rest) {
+		match take_segment(&mut rest, length) {
+			Some(segment) => segments.push(segment),
+			None => break,
+		}
+	}
+	// Server type 3 are DOS style paths like C:\data, everything else is treated as a Unix path
+	if server_type == 3 {
+		segments.join("\\")
+	} else {
+		format!("/{}", segments.join("/"))
+	}
+}
+
+/// Decodes the password of a site, passwords protected with a FileZilla master password cannot be read.
+fn decode_password(fields: &HashMap<String, String>) -> String {
+	let password = fields.get("Pass").cloned().unwrap_or_default();
+	match fields.get("Pass@encoding").map(String::as_str) {
+		Some("base64") => base64::engine::general_purpose::STANDARD
+			.decode(password.trim())
+			.map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+			.unwrap_or_default(),
+		Some("crypt") => String::new(),
+		_ => password,
+	}
+}
+
+/// Converts the fields of a `Server` element into a candidate.
+///
+/// # Arguments
+///
+/// * `folders` - The site manager folders the site is in, they become part of the connection name.
+/// * `fields` - The child elements of the `Server` element by name.
+///
+/// # Returns
+///
+/// `None` for sites without a host and for protocols that are not supported, e.g. implicit FTPS.
+fn candidate(folders: &[String], fields: &HashMap<String, String>) -> Option<ImportCandidate> {
+	let field = |key: &str| fields.get(key).map(|value| value.trim().to_string()).unwrap_or_default();
+	let host = field("Host");
+	if host.is_empty() {
+		return None;
+	}
+
+	// 0 uses explicit TLS only if the server offers it, which is imported as plain FTP
+	let protocol = match field("Protocol").as_str() {
+		"" | "0" | "6" => Protocol::Ftp,
+		"1" => Protocol::Sftp,
+		"4" => Protocol::Ftps,
+		_ => return None,
+	};
+	let port = field("Port").parse().unwrap_or(match protocol {
+		Protocol::Sftp => 22,
+		Protocol::Ftp | Protocol::Ftps => 21,
+	});
+
+	// Older versions store the name as text of the Server element instead of a Name element
+	let mut name = field("Name");
+	if name.is_empty() {
+		name = field("Server");
+	}
+	if name.is_empty() {
+		name = host.clone();
+	}
+	let path: Vec<&str> = folders.iter().map(String::as_str).filter(|folder| !folder.is_empty()).chain([name.as_str()]).collect();
+
+	let mut connection = importer::new_connection(&path.join("/"), &host, port, &field("User"));
+	connection.protocol = i32::from(protocol) as i8;
+	connection.remote_path = decode_remote_dir(&field("RemoteDir"));
+	connection.local_path = field("LocalDir");
+	let mut warning = None;
+	match field("Logontype").as_str() {
+		// Anonymous logins use the same credentials FileZilla sends
+		"0" => {
+			connection.username = "anonymous".to_string();
+			connection.password = "anonymous@example.com".to_string();
+		}
+		"5" if protocol == Protocol::Sftp => warning = importer::use_key_file(&mut connection, &field("Keyfile")),
+		_ => connection.password = decode_password(fields),
+	}
+	Some(ImportCandidate { connection, jump_host: None, warning })
+}
+
+/// Reads the sites of a FileZilla site manager file.
+fn read_candidates(content: &str) -> Result<Vec<ImportCandidate>, BackendError> {
+	let mut reader = Reader::from_str(content);
+	reader.trim_text(true);
+
+	// Names of the open elements and folders, and the fields of the Server element being read
+	let mut elements: Vec<String> = Vec::new();
+	let mut folders: Vec<String> = Vec::new();
+	let mut server: Option<HashMap<String, String>> = None;
+	let mut candidates = Vec::new();
+	loop {
+		let event = reader.read_event().map_err(|e| BackendError::invalid_input(format!("Error parsing site manager at position {}: {}", reader.buffer_position(), e)))?;
+		match event {
+			Event::Start(element) => {
+				let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+				match name.as_str() {
+					"Folder" => folders.push(String::new()),
+					"Server" => server = Some(HashMap::new()),
+					"Pass" => {
+						if let (Some(fields), Ok(Some(encoding))) = (server.as_mut(), element.try_get_attribute("encoding")) {
+							let encoding = encoding.unescape_value().map_err(|e| BackendError::invalid_input(format!("Error parsing site manager: {}", e)))?;
+							fields.insert("Pass@encoding".to_string(), encoding.to_string());
+						}
+					}
+					_ => (),
+				}
+				elements.push(name);
+			}
+			Event::Text(text) => {
+				let text = text.unescape().map_err(|e| BackendError::invalid_input(format!("Error parsing site manager: {}", e)))?.to_string();
+				match (elements.last().map(String::as_str), server.as_mut()) {
+					// The name of a folder is the text of the Folder element itself
+					(Some("Folder"), _) => {
+						if let Some(folder) = folders.last_mut() {
+							*folder = text;
+						}
+					}
+					(Some(element), Some(fields)) => {
+						fields.insert(element.to_string(), text);
+					}
+					_ => (),
+				}
+			}
+			Event::End(_) => match elements.pop().as_deref() {
+				Some("Folder") => {
+					folders.pop();
+				}
+				Some("Server") => {
+					if let Some(candidate) = server.take().and_then(|fields| candidate(&folders, &fields)) {
+						candidates.push(candidate);
+					}
+				}
+				_ => (),
+			},
+			Event::Eof => break,
+			_ => (),
+		}
+	}
+	Ok(candidates)
+}
+
+/// Imports the sites of a FileZilla site manager as connections.
+///
+/// Folders become part of the connection name, e.g. `Work/Web server`. Passwords stored as base64 are imported,
+/// passwords protected with a FileZilla master password are not. Existing connections are matched by name and updated.
+///
+/// # Arguments
+///
+/// * `path` - The `sitemanager.xml` to read, defaults to the one of the current user.
+/// * `commit` - `false` only returns the preview, `true` adds and updates the connections.
+/// * `sites` - The names of the sites to import, including their folders, all sites are imported if not given.
+///
+/// # Returns
+///
+/// What is, or would be, added, updated or left unchanged for every site.
+#[tauri::command]
+pub fn import_filezilla(path: Option<String>, commit: bool, sites: Option<Vec<String>>) -> Result<Vec<ImportPreview>, BackendError> {
+	let path = match path {
+		Some(path) => PathBuf::from(path),
+		None => default_path()?,
+	};
+	let content = fs::read_to_string(&path).map_err(|e| BackendError::io(&format!("Error reading {}", path.display()), &e))?;
+	let mut candidates = read_candidates(&content)?;
+	if let Some(sites) = sites {
+		candidates.retain(|candidate| sites.contains(&candidate.connection.name));
+	}
+
+	if commit {
+		importer::apply(candidates)
+	} else {
+		importer::preview(&candidates)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::connection_manager::AuthMethod;
+
+	const SITE_MANAGER: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes" ?>
+<FileZilla3 version="3.66.0" platform="windows">
+	<Servers>
+		<Folder expanded="1">Work
+			<Folder>Web
+				<Server>
+					<Host>example.com</Host>
+					<Port>2222</Port>
+					<Protocol>1</Protocol>
+					<Logontype>1</Logontype>
+					<User>admin</User>
+					<Pass encoding="base64">czNjcmV0IQ==</Pass>
+					<Name>Production</Name>
+					<RemoteDir>1 0 3 var 7 www dir</RemoteDir>
+				</Server>
+			</Folder>
+			<Server>
+				<Host>ftp.example.com</Host>
+				<Protocol>4</Protocol>
+				<Logontype>0</Logontype>
+				<Name>Explicit</Name>
+			</Server>
+		</Folder>
+		<Server>
+			<Host>ftp.example.com</Host>
+			<Protocol>3</Protocol>
+			<Name>Implicit</Name>
+		</Server>
+		<Server>
+			<Host>example.com</Host>
+			<Protocol>1</Protocol>
+			<Logontype>5</Logontype>
+			<Keyfile>C:\keys\id.ppk</Keyfile>
+			<Name>PuTTY</Name>
+		</Server>
+		<Server>
+			<Host>example.com</Host>
+			<Pass encoding="crypt">encrypted</Pass>
+			<Name>Protected</Name>
+		</Server>
+	</Servers>
+</FileZilla3>"#;
+
+	fn find<'a>(candidates: &'a [ImportCandidate], name: &str) -> &'a ImportCandidate {
+		candidates.iter().find(|candidate| candidate.connection.name == name).unwrap()
+	}
+
+	#[test]
+	fn remote_directories_are_decoded() {
+		assert_eq!(decode_remote_dir("1 0 4 home 4 user"), "/home/user");
+		assert_eq!(decode_remote_dir("1 0 7 www dir"), "/www dir");
+		assert_eq!(decode_remote_dir("3 0 2 C: 4 data"), "C:\\data");
+		assert_eq!(decode_remote_dir("8 4 DISK 4 data"), "/data");
+		assert_eq!(decode_remote_dir(""), "");
+	}
+
+	#[test]
+	fn sites_are_read_with_their_folders() {
+		let candidates = read_candidates(SITE_MANAGER).unwrap();
+		let names: Vec<&str> = candidates.iter().map(|candidate| candidate.connection.name.as_str()).collect();
+		assert_eq!(names, ["Work/Web/Production", "Work/Explicit", "PuTTY", "Protected"]);
+
+		let production = &find(&candidates, "Work/Web/Production").connection;
+		assert_eq!(production.protocol, i32::from(Protocol::Sftp) as i8);
+		assert_eq!(production.port, 2222);
+		assert_eq!(production.username, "admin");
+		assert_eq!(production.password, "s3cret!");
+		assert_eq!(production.remote_path, "/var/www dir");
+
+		let explicit = &find(&candidates, "Work/Explicit").connection;
+		assert_eq!(explicit.protocol, i32::from(Protocol::Ftps) as i8);
+		assert_eq!(explicit.port, 21);
+		assert_eq!(explicit.username, "anonymous");
+
+		assert_eq!(find(&candidates, "Protected").connection.password, "");
+	}
+
+	#[test]
+	fn putty_keys_fall_back_to_the_agent() {
+		let candidates = read_candidates(SITE_MANAGER).unwrap();
+		let putty = find(&candidates, "PuTTY");
+		assert_eq!(putty.connection.auth_method, AuthMethod::Agent as i8);
+		assert_eq!(putty.connection.private_key_path, "");
+		assert!(putty.warning.is_some());
+	}
+}
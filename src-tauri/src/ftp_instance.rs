@@ -0,0 +1,704 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use native_tls::{TlsConnector, TlsStream};
+
+use crate::app_settings;
+use crate::connection_manager::{Connection, Protocol};
+use crate::credential_store;
+use crate::error::BackendError;
+use crate::jump_host;
+use crate::remote_fs::{join_remote, remote_filename, remote_parent, RemoteFs};
+use crate::sftp_manager::File;
+
+/// A socket of an FTP session, either plain or wrapped in TLS after `AUTH TLS`.
+enum FtpStream {
+	Plain(TcpStream),
+	Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Read for FtpStream {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match self {
+			FtpStream::Plain(stream) => stream.read(buf),
+			FtpStream::Tls(stream) => stream.read(buf),
+		}
+	}
+}
+
+impl Write for FtpStream {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			FtpStream::Plain(stream) => stream.write(buf),
+			FtpStream::Tls(stream) => stream.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			FtpStream::Plain(stream) => stream.flush(),
+			FtpStream::Tls(stream) => stream.flush(),
+		}
+	}
+}
+
+impl FtpStream {
+	/// Returns the underlying socket, below the TLS layer if there is one.
+	fn tcp(&self) -> &TcpStream {
+		match self {
+			FtpStream::Plain(stream) => stream,
+			FtpStream::Tls(stream) => stream.get_ref(),
+		}
+	}
+
+	/// Closes the stream, for TLS streams the close notify alert is sent first as many servers require it.
+	fn close(self) {
+		if let FtpStream::Tls(mut stream) = self {
+			let _ = stream.shutdown();
+		}
+	}
+}
+
+/// A reply received on the control connection.
+struct FtpReply {
+	code: u32,
+	lines: Vec<String>,
+}
+
+impl FtpReply {
+	/// Returns the text of the last line without the reply code.
+	fn message(&self) -> &str {
+		self.lines.last().map(|line| line.get(4..).unwrap_or("")).unwrap_or("")
+	}
+}
+
+/// An authenticated FTP session, optionally protected with explicit TLS (FTPS).
+///
+/// The client is written directly on `TcpStream` and `native-tls` instead of using an FTP crate, because the
+/// backend has to own its sockets: the timeouts of the network settings are set on them, cancelling an
+/// operation shuts the control socket down, and data connections get the same TLS setup as the control connection.
+///
+/// native-tls can not resume the TLS session of the control connection on the data connections. Servers that
+/// require this reject every listing and transfer, e.g. vsftpd with its default `require_ssl_reuse=YES` or ProFTPD
+/// without `TLSOptions NoSessionReuseRequired`. This is reported as a protocol error that names the setting.
+pub struct FTPInstance {
+	control: BufReader<FtpStream>,
+	host: String,
+	tls: Option<TlsConnector>,
+	/// How long reads and writes on the control and data connections may block.
+	read_timeout: Option<Duration>,
+}
+
+impl FTPInstance {
+	/// Connects to an FTP server using the given options and returns an `FTPInstance` if successful.
+	///
+	/// If the protocol of the connection is `Protocol::Ftps`, the control connection is upgraded with
+	/// `AUTH TLS` before logging in and all data connections are protected as well.
+	///
+	/// # Arguments
+	///
+	/// * `options` - The connection options specifying the host, port, username and password.
+	///
+	/// # Returns
+	///
+	/// * `Result<FTPInstance, BackendError>` - The `FTPInstance` if the connection was successful, otherwise an error message.
+	pub fn connect(options: &Connection) -> Result<FTPInstance, BackendError> {
+		// Only set up TLS if explicit TLS was requested
+		let tls = match Protocol::try_from(options.protocol)? {
+			Protocol::Ftps => Some(TlsConnector::new().map_err(|e| BackendError::protocol(format!("Error creating TLS connector: {:?}", e)))?),
+			_ => None,
+		};
+
+		// Establish the control connection and wait for the greeting of the server
+		let read_timeout = app_settings::network_settings().read_timeout();
+		let tcp = open_tcp(&options.host, options.port as u16, read_timeout)?;
+		let mut instance = FTPInstance {
+			control: BufReader::new(FtpStream::Plain(tcp)),
+			host: options.host.clone(),
+			tls: None,
+			read_timeout,
+		};
+		instance.expect_reply(&[220])?;
+
+		// Upgrade the control connection to TLS before sending any credentials
+		if let Some(connector) = tls {
+			instance.command("AUTH TLS", &[234])?;
+			let tcp = match instance.control.into_inner() {
+				FtpStream::Plain(tcp) => tcp,
+				FtpStream::Tls(_) => return Err(BackendError::protocol("Control connection is already encrypted")),
+			};
+			let stream = connector.connect(&options.host, tcp).map_err(|e| BackendError::protocol(format!("Error negotiating TLS: {:?}", e)))?;
+			instance.control = BufReader::new(FtpStream::Tls(Box::new(stream)));
+			instance.tls = Some(connector);
+
+			// Protect the data connections as well
+			instance.command("PBSZ 0", &[200])?;
+			instance.command("PROT P", &[200])?;
+		}
+
+		// Saved connections come without their password, look it up
+		let mut credentials = options.clone();
+		credential_store::fill_secrets(&mut credentials)?;
+
+		// Log in, some servers accept the user without asking for a password
+		let reply = instance.command(&format!("USER {}", argument(&options.username)?), &[230, 331])?;
+		if reply.code == 331 {
+			instance.command(&format!("PASS {}", argument(&credentials.password)?), &[230, 202])
+				.map_err(|e| BackendError::auth_failed(format!("Error authenticating with password: {}", e)))?;
+		}
+
+		// Transfer everything as binary so files are not altered
+		instance.command("TYPE I", &[200])?;
+
+		Ok(instance)
+	}
+
+	/// Checks whether the control connection is still usable by sending a `NOOP`.
+	pub fn is_alive(&mut self) -> bool {
+		self.command("NOOP", &[200]).is_ok()
+	}
+
+	/// Ends the session with a `QUIT`, errors are ignored as the session is being discarded anyway.
+	pub fn disconnect(&mut self) {
+		let _ = self.command("QUIT", &[221]);
+	}
+
+	/// Sends a command on the control connection and checks the reply code.
+	///
+	/// # Arguments
+	///
+	/// * `command` - The command to send, without the trailing CRLF.
+	/// * `expected` - The reply codes that count as success.
+	fn command(&mut self, command: &str, expected: &[u32]) -> Result<FtpReply, BackendError> {
+		let stream = self.control.get_mut();
+		stream.write_all(format!("{}\r\n", command).as_bytes())
+			.and_then(|_| stream.flush())
+			.map_err(|e| BackendError::io("Error sending FTP command", &e))?;
+		self.expect_reply(expected)
+	}
+
+	/// Reads the next reply from the control connection and checks its code.
+	fn expect_reply(&mut self, expected: &[u32]) -> Result<FtpReply, BackendError> {
+		let reply = self.read_reply()?;
+		if expected.contains(&reply.code) {
+			Ok(reply)
+		} else {
+			Err(reply_error(&reply))
+		}
+	}
+
+	/// Reads a (possibly multi-line) reply from the control connection.
+	fn read_reply(&mut self) -> Result<FtpReply, BackendError> {
+		let mut lines = Vec::new();
+		let mut line = String::new();
+
+		// The first line carries the code, a '-' after it means more lines follow
+		self.control.read_line(&mut line).map_err(|e| BackendError::io("Error reading FTP reply", &e))?;
+		let first = line.trim_end().to_string();
+		let code = first.get(..3).and_then(|code| code.parse::<u32>().ok())
+			.ok_or_else(|| BackendError::protocol(format!("Invalid FTP reply: {}", first)))?;
+		let multiline = first.as_bytes().get(3) == Some(&b'-');
+		lines.push(first);
+
+		// A multi-line reply ends with a line starting with the same code followed by a space
+		if multiline {
+			let terminator = format!("{} ", code);
+			loop {
+				line.clear();
+				if self.control.read_line(&mut line).map_err(|e| BackendError::io("Error reading FTP reply", &e))? == 0 {
+					return Err(BackendError::protocol("FTP connection closed unexpectedly"));
+				}
+				let current = line.trim_end().to_string();
+				let done = current.starts_with(&terminator);
+				lines.push(current);
+				if done {
+					break;
+				}
+			}
+		}
+
+		Ok(FtpReply { code, lines })
+	}
+
+	/// Opens a passive data connection, preferring `EPSV` and falling back to `PASV`.
+	///
+	/// The address announced by the server is ignored in favour of the control connection host,
+	/// which avoids problems with servers behind NAT that announce private addresses.
+	fn open_data_connection(&mut self) -> Result<TcpStream, BackendError> {
+		let port = match self.command("EPSV", &[229]) {
+			// 229 Entering Extended Passive Mode (|||port|)
+			Ok(reply) => {
+				let message = reply.message();
+				message.rsplit("|||").next()
+					.and_then(|rest| rest.split('|').next())
+					.and_then(|port| port.parse::<u16>().ok())
+					.ok_or_else(|| BackendError::protocol(format!("Invalid EPSV reply: {}", message)))?
+			}
+			Err(_) => {
+				let reply = self.command("PASV", &[227])?;
+				pasv_port(reply.message()).ok_or_else(|| BackendError::protocol(format!("Invalid PASV reply: {}", reply.message())))?
+			}
+		};
+		open_tcp(&self.host, port, self.read_timeout)
+	}
+
+	/// Runs a command that transfers data, calling `transfer` with the data connection.
+	///
+	/// # Arguments
+	///
+	/// * `command` - The command starting the transfer, e.g. `RETR file`.
+	/// * `transfer` - Reads from or writes to the data connection.
+	fn transfer<T, F>(&mut self, command: &str, transfer: F) -> Result<T, BackendError>
+	where
+		F: FnOnce(&mut FtpStream) -> Result<T, BackendError>,
+	{
+		let tcp = self.open_data_connection()?;
+		self.command(command, &[125, 150])?;
+
+		// With explicit TLS the data connection is encrypted as well
+		let mut data = match &self.tls {
+			Some(connector) => match connector.connect(&self.host, tcp) {
+				Ok(stream) => FtpStream::Tls(Box::new(stream)),
+				Err(e) => {
+					// The server explains on the control connection why it rejected the handshake
+					let reply = self.read_reply().ok();
+					return Err(reply.as_ref().and_then(session_reuse_error)
+						.unwrap_or_else(|| BackendError::protocol(format!("Error negotiating TLS on the data connection, the server may require TLS session reuse which is not supported: {:?}", e))));
+				}
+			},
+			None => FtpStream::Plain(tcp),
+		};
+
+		// Run the transfer and close the data connection so the server sees the end of the data
+		let result = transfer(&mut data);
+		data.close();
+
+		// The server confirms the transfer once the data connection is closed
+		self.expect_reply(&[226, 250])?;
+		result
+	}
+
+	/// Runs a listing command and returns its lines.
+	fn read_lines(&mut self, command: &str) -> Result<Vec<String>, BackendError> {
+		self.transfer(command, |data| {
+			let mut output = Vec::new();
+			data.read_to_end(&mut output).map_err(|e| BackendError::io("Error reading directory listing", &e))?;
+			Ok(String::from_utf8_lossy(&output).lines().map(|line| line.to_string()).filter(|line| !line.is_empty()).collect())
+		})
+	}
+}
+
+/// Checks a path or credential before it is sent as the argument of a command.
+///
+/// Commands end at the first CRLF, so a line break in e.g. a filename or password would let it send
+/// further commands of its own. Such values are rejected instead of sent, without repeating them in the error.
+fn argument(value: &str) -> Result<&str, BackendError> {
+	if value.contains(['\r', '\n', '\0']) {
+		return Err(BackendError::invalid_input("FTP paths and credentials can not contain line breaks or NUL characters"));
+	}
+	Ok(value)
+}
+
+/// Reads the port of a `227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)` reply.
+///
+/// Only the six numbers inside the parentheses are read, each must fit in a byte.
+fn pasv_port(message: &str) -> Option<u16> {
+	let start = message.find('(')? + 1;
+	let end = start + message[start..].find(')')?;
+	let numbers: Vec<u32> = message[start..end]
+		.split(',')
+		.map(|number| number.trim().parse::<u32>().ok().filter(|number| *number <= 255))
+		.collect::<Option<_>>()?;
+	match numbers[..] {
+		[_, _, _, _, high, low] => u16::try_from(high * 256 + low).ok(),
+		_ => None,
+	}
+}
+
+/// Recognizes the reply of a server that rejected a data connection because its TLS session was not reused.
+fn session_reuse_error(reply: &FtpReply) -> Option<BackendError> {
+	let message = reply.message().to_lowercase();
+	if reply.code == 522 || message.contains("reuse") {
+		Some(BackendError::protocol(format!(
+			"The server requires TLS session reuse on data connections, which is not supported. Disable require_ssl_reuse (vsftpd) or set TLSOptions NoSessionReuseRequired (ProFTPD). FTP error {}: {}",
+			reply.code,
+			reply.message()
+		)))
+	} else {
+		None
+	}
+}
+
+/// Opens a control or data connection whose reads and writes fail once `read_timeout` has passed.
+fn open_tcp(host: &str, port: u16, read_timeout: Option<Duration>) -> Result<TcpStream, BackendError> {
+	let tcp = jump_host::connect_tcp(host, port)?;
+	tcp.set_read_timeout(read_timeout)
+		.and_then(|_| tcp.set_write_timeout(read_timeout))
+		.map_err(|e| BackendError::io("Error configuring socket timeouts", &e))?;
+	Ok(tcp)
+}
+
+/// Converts an unexpected reply into an error, e.g. `530 Login incorrect` is `AuthFailed`.
+fn reply_error(reply: &FtpReply) -> BackendError {
+	let message = format!("FTP error {}: {}", reply.code, reply.message());
+	match reply.code {
+		// Not logged in
+		530 => BackendError::auth_failed(message),
+		// Service not available, the server closes the connection, e.g. after an idle timeout
+		421 => BackendError::timeout(message),
+		// File unavailable, servers use it for missing files and for denied access alike
+		550 if reply.message().to_lowercase().contains("permission") => BackendError::permission_denied(message),
+		450 | 550 => BackendError::not_found(message),
+		// File name not allowed
+		553 => BackendError::permission_denied(message),
+		_ => BackendError::protocol(message),
+	}
+}
+
+/// Parses an `MLSD`/`MLST` fact line (`type=file;size=12;modify=20240101120000; name`).
+///
+/// # Arguments
+///
+/// * `directory` - The directory the entry belongs to, used to build the absolute path.
+/// * `line` - The fact line.
+fn parse_mlsd_line(directory: &str, line: &str) -> Option<File> {
+	let (facts, name) = line.trim_start().split_once(' ')?;
+	let mut file = File {
+		path: String::new(),
+		filename: String::new(),
+		is_dir: false,
+		is_symlink: false,
+		size: 0,
+		modified: 0,
+		access: 0,
+		permissions: 0,
+		owner: 0,
+		group: 0,
+	};
+
+	for fact in facts.split(';').filter(|fact| !fact.is_empty()) {
+		let (key, value) = fact.split_once('=')?;
+		match key.to_ascii_lowercase().as_str() {
+			"type" => {
+				let value = value.to_ascii_lowercase();
+				file.is_dir = matches!(value.as_str(), "dir" | "cdir" | "pdir");
+				// Servers that do not follow links report them as 'OS.unix=symlink' or 'OS.unix=slink:target'
+				file.is_symlink = value.starts_with("os.unix=symlink") || value.starts_with("os.unix=slink");
+			}
+			"size" => file.size = value.parse().unwrap_or(0),
+			"modify" => file.modified = parse_mdtm(value),
+			"unix.mode" => file.permissions = u32::from_str_radix(value, 8).unwrap_or(0),
+			"unix.uid" | "unix.owner" => file.owner = value.parse().unwrap_or(0),
+			"unix.gid" | "unix.group" => file.group = value.parse().unwrap_or(0),
+			_ => (),
+		}
+	}
+
+	// MLST returns the full path as the name, MLSD only the filename
+	let filename = remote_filename(name).to_string();
+	file.path = join_remote(directory, &filename);
+	file.filename = filename;
+	Some(file)
+}
+
+/// Parses a Unix style `LIST` line (`drwxr-xr-x 2 user group 4096 Jan 01 12:00 name`).
+///
+/// Only the fields that can be read reliably are filled in, owners are reported by name and therefore left at 0.
+fn parse_list_line(directory: &str, line: &str) -> Option<File> {
+	// mode, links, owner, group, size, month, day, time/year and then the name
+	let parts: Vec<&str> = line.split_whitespace().collect();
+	if parts.len() < 9 {
+		return None;
+	}
+	let mode = parts[0];
+	let size = parts[4].parse::<u64>().ok()?;
+	let mut filename = parts[8..].join(" ");
+
+	// Symlinks are listed as 'name -> target'
+	if mode.starts_with('l') {
+		if let Some((name, _)) = filename.split_once(" -> ") {
+			filename = name.to_string();
+		}
+	}
+
+	// Convert the 'rwxr-xr-x' part into a numeric mode
+	let permissions = mode.chars().skip(1).take(9).fold(0u32, |mode, c| (mode << 1) | if c == '-' { 0 } else { 1 });
+
+	Some(File {
+		path: join_remote(directory, &filename),
+		filename,
+		is_dir: mode.starts_with('d'),
+		is_symlink: mode.starts_with('l'),
+		size,
+		modified: 0,
+		access: 0,
+		permissions,
+		owner: 0,
+		group: 0,
+	})
+}
+
+/// Converts an FTP timestamp (`YYYYMMDDHHMMSS[.sss]`, always UTC) to a unix timestamp.
+fn parse_mdtm(value: &str) -> u64 {
+	chrono::NaiveDateTime::parse_from_str(value.get(..14).unwrap_or(value), "%Y%m%d%H%M%S")
+		.map(|time| time.and_utc().timestamp().max(0) as u64)
+		.unwrap_or(0)
+}
+
+/// The FTP backend of the `RemoteFs` trait.
+impl RemoteFs for FTPInstance {
+	fn list(&mut self, path: &str, show_hidden: bool) -> Result<Vec<File>, BackendError> {
+		// Prefer the machine readable MLSD listing and fall back to parsing LIST output
+		let mut files: Vec<File> = match self.read_lines(&format!("MLSD {}", argument(path)?)) {
+			Ok(lines) => lines.iter().filter_map(|line| parse_mlsd_line(path, line)).collect(),
+			Err(_) => self.read_lines(&format!("LIST -a {}", argument(path)?))?.iter().filter_map(|line| parse_list_line(path, line)).collect(),
+		};
+
+		// Ignore '.' and '..' and, if requested, hidden files
+		files.retain(|file| file.filename != "." && file.filename != ".." && (show_hidden || !file.filename.starts_with('.')));
+		Ok(files)
+	}
+
+	fn stat(&mut self, path: &str) -> Result<File, BackendError> {
+		// MLST returns the facts on the second line of the reply
+		if let Ok(reply) = self.command(&format!("MLST {}", argument(path)?), &[250]) {
+			if let Some(file) = reply.lines.get(1).and_then(|line| parse_mlsd_line(remote_parent(path), line)) {
+				return Ok(file);
+			}
+		}
+
+		// Otherwise look the entry up in the listing of its parent directory
+		let parent = match remote_parent(path) {
+			"" => "/",
+			parent => parent,
+		};
+		let filename = remote_filename(path);
+		self.list(parent, true)?
+			.into_iter()
+			.find(|file| file.filename == filename)
+			.ok_or_else(|| BackendError::not_found(format!("No such file: {}", path)))
+	}
+
+	fn lstat(&mut self, path: &str) -> Result<File, BackendError> {
+		// FTP has no request that never follows links, servers that do not follow them report links in MLST and LIST
+		self.stat(path)
+	}
+
+	fn read(&mut self, path: &str, writer: &mut dyn Write) -> Result<u64, BackendError> {
+		self.transfer(&format!("RETR {}", argument(path)?), |data| {
+			io::copy(data, writer).map_err(|e| BackendError::io("Error reading remote file", &e))
+		})
+	}
+
+	fn write(&mut self, path: &str, reader: &mut dyn Read) -> Result<u64, BackendError> {
+		self.transfer(&format!("STOR {}", argument(path)?), |data| {
+			io::copy(reader, data).map_err(|e| BackendError::io("Error writing remote file", &e))
+		})
+	}
+
+	fn rename(&mut self, from: &str, to: &str) -> Result<(), BackendError> {
+		let (from, to) = (argument(from)?, argument(to)?);
+		self.command(&format!("RNFR {}", from), &[350])?;
+		self.command(&format!("RNTO {}", to), &[250]).map(|_| ())
+	}
+
+	fn remove(&mut self, path: &str) -> Result<(), BackendError> {
+		// There is no way to tell files and directories apart without a listing, so try both
+		let path = argument(path)?;
+		match self.command(&format!("DELE {}", path), &[250]) {
+			Ok(_) => Ok(()),
+			Err(_) => self.command(&format!("RMD {}", path), &[250]).map(|_| ()),
+		}
+	}
+
+	fn mkdir(&mut self, path: &str) -> Result<(), BackendError> {
+		self.command(&format!("MKD {}", argument(path)?), &[257]).map(|_| ())
+	}
+
+	fn socket(&self) -> Option<TcpStream> {
+		self.control.get_ref().tcp().try_clone().ok()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn argument_rejects_line_breaks_and_nul() {
+		assert!(argument("file.txt").is_ok());
+		assert!(argument("/home/user/ünïcødé; 'quoted' file").is_ok());
+		assert!(argument("file\r\nDELE other").is_err());
+		assert!(argument("file\nDELE other").is_err());
+		assert!(argument("file\rDELE other").is_err());
+		assert!(argument("file\0").is_err());
+	}
+
+	#[test]
+	fn pasv_ports_are_read_from_the_parentheses() {
+		assert_eq!(pasv_port("Entering Passive Mode (127,0,0,1,82,8)."), Some(21000));
+		assert_eq!(pasv_port("Entering Passive Mode (127,0,0,1,82,8) 2024 ready"), Some(21000));
+		assert_eq!(pasv_port("Entering Passive Mode (127,0,0,1,256,8)"), None);
+		assert_eq!(pasv_port("Entering Passive Mode (127,0,0,1,82)"), None);
+		assert_eq!(pasv_port("Entering Passive Mode 127,0,0,1,82,8"), None);
+	}
+
+	#[test]
+	fn rejected_session_reuse_is_explained() {
+		let reply = |code: u32, line: &str| FtpReply { code, lines: vec![format!("{} {}", code, line)] };
+		assert!(matches!(session_reuse_error(&reply(522, "SSL connection failed: session reuse required")), Some(BackendError::Protocol { .. })));
+		assert!(session_reuse_error(&reply(425, "Unable to build data connection: TLS session of data connection not resumed")).is_none());
+		assert!(session_reuse_error(&reply(450, "Unable to build data connection: session reuse required")).is_some());
+	}
+
+	#[test]
+	fn mlsd_paths_use_forward_slashes() {
+		let file = parse_mlsd_line("/home/user", "type=file;size=12;modify=20240101120000; notes.txt").unwrap();
+		assert_eq!(file.path, "/home/user/notes.txt");
+		assert_eq!(file.filename, "notes.txt");
+		assert_eq!(file.size, 12);
+		assert!(!file.is_dir);
+
+		// MLST replies carry the full path as the name
+		let file = parse_mlsd_line("/home/user", "type=dir; /home/user/docs").unwrap();
+		assert_eq!(file.path, "/home/user/docs");
+		assert_eq!(file.filename, "docs");
+		assert!(file.is_dir);
+	}
+
+	#[test]
+	fn list_paths_use_forward_slashes() {
+		let file = parse_list_line("/srv", "lrwxrwxrwx 1 user group 7 Jan 01 12:00 my link -> target").unwrap();
+		assert_eq!(file.path, "/srv/my link");
+		assert_eq!(file.filename, "my link");
+		assert_eq!(file.permissions, 0o777);
+		assert!(file.is_symlink);
+	}
+
+	#[test]
+	fn mlsd_reports_symlinks() {
+		let file = parse_mlsd_line("/srv", "type=OS.unix=slink:/srv/data;size=9; data-link").unwrap();
+		assert!(file.is_symlink);
+		assert!(!file.is_dir);
+
+		let file = parse_mlsd_line("/srv", "type=OS.unix=symlink; other-link").unwrap();
+		assert!(file.is_symlink);
+	}
+
+	/// Integration tests against a real server, run with `cargo test -- --ignored`.
+	///
+	/// The server is configured with `FTP_TEST_HOST`, `FTP_TEST_PORT`, `FTP_TEST_USER`, `FTP_TEST_PASSWORD` and
+	/// `FTP_TEST_PROTOCOL` (`1` for FTP, `2` for explicit FTPS, which needs a certificate trusted by this machine
+	/// and a server that does not require TLS session reuse).
+	/// The defaults match `tests/ftp/docker-compose.yml`.
+	mod server {
+		use super::super::*;
+
+		fn env(name: &str, default: &str) -> String {
+			std::env::var(name).unwrap_or_else(|_| default.to_string())
+		}
+
+		fn options() -> Connection {
+			Connection {
+				id: 0,
+				name: "FTP test server".to_string(),
+				host: env("FTP_TEST_HOST", "127.0.0.1"),
+				port: env("FTP_TEST_PORT", "2121").parse().expect("FTP_TEST_PORT is not a number"),
+				username: env("FTP_TEST_USER", "test"),
+				password: env("FTP_TEST_PASSWORD", "test"),
+				private_key: String::new(),
+				remote_path: String::new(),
+				local_path: String::new(),
+				default: false,
+				protocol: env("FTP_TEST_PROTOCOL", "1").parse().expect("FTP_TEST_PROTOCOL is not a number"),
+				created_at: String::new(),
+				updated_at: String::new(),
+				last_connected_at: String::new(),
+				auth_method: 0,
+				private_key_path: String::new(),
+				jump_host_id: None,
+			}
+		}
+
+		/// Creates an empty directory for a test on the server, named after the test.
+		fn directory(instance: &mut FTPInstance, name: &str) -> String {
+			let directory = format!("/sftp-editor-test-{}", name);
+			if instance.stat(&directory).is_ok() {
+				crate::remote_fs::remove_recursive(instance, &directory).unwrap();
+			}
+			instance.mkdir(&directory).unwrap();
+			directory
+		}
+
+		#[test]
+		#[ignore]
+		fn write_read_and_list() {
+			let mut instance = FTPInstance::connect(&options()).unwrap();
+			let directory = directory(&mut instance, "write-read");
+			let path = join_remote(&directory, "ünïcødé file.txt");
+
+			let written = instance.write(&path, &mut "hello over ftp".as_bytes()).unwrap();
+			assert_eq!(written, 14);
+
+			let mut contents = Vec::new();
+			instance.read(&path, &mut contents).unwrap();
+			assert_eq!(contents, b"hello over ftp");
+
+			let stat = instance.stat(&path).unwrap();
+			assert_eq!(stat.size, 14);
+			assert_eq!(stat.path, path);
+
+			let files = instance.list(&directory, true).unwrap();
+			assert_eq!(files.len(), 1);
+			assert_eq!(files[0].filename, "ünïcødé file.txt");
+			assert_eq!(files[0].path, path);
+
+			crate::remote_fs::remove_recursive(&mut instance, &directory).unwrap();
+			instance.disconnect();
+		}
+
+		#[test]
+		#[ignore]
+		fn rename_and_remove() {
+			let mut instance = FTPInstance::connect(&options()).unwrap();
+			let directory = directory(&mut instance, "rename");
+			let from = join_remote(&directory, "before.txt");
+			let to = join_remote(&directory, "after.txt");
+
+			instance.write(&from, &mut "contents".as_bytes()).unwrap();
+			instance.rename(&from, &to).unwrap();
+			assert!(matches!(instance.stat(&from), Err(BackendError::NotFound { .. })));
+			assert_eq!(instance.stat(&to).unwrap().size, 8);
+
+			instance.remove(&to).unwrap();
+			instance.remove(&directory).unwrap();
+			assert!(instance.stat(&directory).is_err());
+			instance.disconnect();
+		}
+
+		#[test]
+		#[ignore]
+		fn injected_commands_are_not_sent() {
+			let mut instance = FTPInstance::connect(&options()).unwrap();
+			let directory = directory(&mut instance, "injection");
+			let victim = join_remote(&directory, "victim.txt");
+			instance.write(&victim, &mut "keep me".as_bytes()).unwrap();
+
+			let injected = format!("{}/missing.txt\r\nDELE {}", directory, victim);
+			assert!(matches!(instance.remove(&injected), Err(BackendError::InvalidInput { .. })));
+			assert_eq!(instance.stat(&victim).unwrap().size, 7);
+
+			crate::remote_fs::remove_recursive(&mut instance, &directory).unwrap();
+			instance.disconnect();
+		}
+
+		#[test]
+		#[ignore]
+		fn wrong_password_is_auth_failed() {
+			let mut options = options();
+			options.password = "wrong password".to_string();
+			assert!(matches!(FTPInstance::connect(&options), Err(BackendError::AuthFailed { .. })));
+		}
+	}
+}
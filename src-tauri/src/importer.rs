@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::connection_manager::{get_connections, insert_connection, update_connection, AuthMethod, Connection, Protocol};
+use crate::error::BackendError;
+
+/// A connection found in a foreign configuration, e.g. an OpenSSH config file.
+pub struct ImportCandidate {
+	/// The connection to create, matched against existing connections by its name.
+	pub connection: Connection,
+	/// The name of the connection to tunnel through, which may be another candidate or an existing connection.
+	pub jump_host: Option<String>,
+	/// Something the user has to know about the imported connection, e.g. a key file that can not be used.
+	pub warning: Option<String>,
+}
+
+/// What an import does with a candidate.
+#[derive(Clone, Copy, PartialEq, serde::Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+	/// No connection with the name exists yet.
+	Add,
+	/// A connection with the name exists and differs.
+	Update,
+	/// A connection with the name exists and already matches.
+	Unchanged,
+	/// A connection with the name exists and is kept as it is, the candidate is not imported.
+	Skip,
+}
+
+/// A candidate as shown in the import preview, secrets are only reported as present or not.
+#[derive(Clone, serde::Serialize, Debug)]
+pub struct ImportPreview {
+	pub name: String,
+	pub host: String,
+	pub port: i32,
+	pub username: String,
+	pub protocol: i8,
+	pub auth_method: i8,
+	pub private_key_path: String,
+	pub jump_host: Option<String>,
+	pub has_password: bool,
+	pub action: ImportAction,
+	pub warning: Option<String>,
+}
+
+/// Creates a connection with the defaults used for imported entries.
+///
+/// # Arguments
+///
+/// * `name` - The name of the connection, used to match it against existing connections.
+/// * `host` - The host name or IP address.
+/// * `port` - The port.
+/// * `username` - The user to log in as.
+pub fn new_connection(name: &str, host: &str, port: i32, username: &str) -> Connection {
+	Connection {
+		id: -1,
+		name: name.to_string(),
+		host: host.to_string(),
+		port,
+		username: username.to_string(),
+		password: String::new(),
+		private_key: String::new(),
+		remote_path: String::new(),
+		local_path: String::new(),
+		default: false,
+		protocol: i32::from(Protocol::Sftp) as i8,
+		created_at: String::new(),
+		updated_at: String::new(),
+		last_connected_at: String::new(),
+		auth_method: AuthMethod::Password as i8,
+		private_key_path: String::new(),
+		jump_host_id: None,
+	}
+}
+
+/// Sets the key file an imported connection authenticates with.
+///
+/// WinSCP and FileZilla usually refer to PuTTY keys (`.ppk`), which libssh2 can not load. Such connections
+/// authenticate with the SSH agent instead, e.g. Pageant, and the returned warning tells the user to convert the key.
+///
+/// # Returns
+///
+/// A warning for the import preview if the key file can not be used.
+pub fn use_key_file(connection: &mut Connection, key_file: &str) -> Option<String> {
+	if key_file.to_lowercase().ends_with(".ppk") {
+		connection.auth_method = AuthMethod::Agent as i8;
+		return Some(format!("{} is a PuTTY key and can not be used, the SSH agent is used instead. Convert the key to the OpenSSH format with PuTTYgen to use it as key file.", key_file));
+	}
+	connection.auth_method = AuthMethod::KeyFile as i8;
+	connection.private_key_path = key_file.to_string();
+	None
+}
+
+/// Returns the name of the jump host of an existing connection.
+fn jump_host_name(connection: &Connection, existing: &[Connection]) -> Option<String> {
+	connection.jump_host_id.and_then(|id| existing.iter().find(|other| other.id == id)).map(|jump| jump.name.clone())
+}
+
+/// Decides whether a candidate adds, updates or leaves an existing connection as it is.
+fn action(candidate: &ImportCandidate, existing: &[Connection]) -> ImportAction {
+	let current = match existing.iter().find(|connection| connection.name == candidate.connection.name) {
+		Some(current) => current,
+		None => return ImportAction::Add,
+	};
+	let imported = &candidate.connection;
+	let unchanged = current.host == imported.host
+		&& current.port == imported.port
+		&& current.username == imported.username
+		&& current.protocol == imported.protocol
+		&& current.auth_method == imported.auth_method
+		&& current.private_key_path == imported.private_key_path
+		&& jump_host_name(current, existing) == candidate.jump_host
+		// Stored secrets are not compared, importing a secret always updates it
+		&& imported.password.is_empty()
+		&& imported.private_key.is_empty();
+	if unchanged {
+		ImportAction::Unchanged
+	} else {
+		ImportAction::Update
+	}
+}
+
+/// Describes what happens to a candidate, without its secrets.
+pub fn describe(candidate: &ImportCandidate, action: ImportAction) -> ImportPreview {
+	ImportPreview {
+		name: candidate.connection.name.clone(),
+		host: candidate.connection.host.clone(),
+		port: candidate.connection.port,
+		username: candidate.connection.username.clone(),
+		protocol: candidate.connection.protocol,
+		auth_method: candidate.connection.auth_method,
+		private_key_path: candidate.connection.private_key_path.clone(),
+		jump_host: candidate.jump_host.clone(),
+		has_password: !candidate.connection.password.is_empty(),
+		action,
+		warning: candidate.warning.clone(),
+	}
+}
+
+/// Shows what importing the candidates would change, without changing anything.
+pub fn preview(candidates: &[ImportCandidate]) -> Result<Vec<ImportPreview>, BackendError> {
+	let existing = get_connections(None)?;
+	Ok(candidates.iter().map(|candidate| describe(candidate, action(candidate, &existing))).collect())
+}
+
+/// Adds or updates the candidates and links them to their jump hosts.
+///
+/// Existing connections keep their id, paths, default flag and any stored secret the candidate does not replace.
+///
+/// # Returns
+///
+/// The preview of the candidates as they were before the import.
+pub fn apply(candidates: Vec<ImportCandidate>) -> Result<Vec<ImportPreview>, BackendError> {
+	let result = preview(&candidates)?;
+	let existing = get_connections(None)?;
+	let mut ids: HashMap<String, i32> = existing.iter().map(|connection| (connection.name.clone(), connection.id)).collect();
+
+	// Create or update every connection first, jump hosts can refer to candidates further down the list
+	for (candidate, preview) in candidates.iter().zip(&result) {
+		let mut connection = candidate.connection.clone();
+		match preview.action {
+			ImportAction::Add => {
+				connection.jump_host_id = None;
+				let id = insert_connection(connection)?;
+				ids.insert(candidate.connection.name.clone(), id);
+			}
+			ImportAction::Update => {
+				let current = existing.iter().find(|current| current.name == connection.name).ok_or_else(|| BackendError::not_found(format!("Connection {} disappeared", connection.name)))?;
+				connection.id = current.id;
+				connection.remote_path = if connection.remote_path.is_empty() { current.remote_path.clone() } else { connection.remote_path };
+				connection.local_path = if connection.local_path.is_empty() { current.local_path.clone() } else { connection.local_path };
+				connection.default = current.default;
+				connection.jump_host_id = current.jump_host_id;
+				update_connection(current.id, connection, None, None)?;
+			}
+			ImportAction::Unchanged | ImportAction::Skip => (),
+		}
+	}
+
+	// Link the jump hosts now that every connection has an id
+	let stored = get_connections(None)?;
+	for candidate in candidates.iter() {
+		let jump_host_id = match &candidate.jump_host {
+			Some(name) => Some(*ids.get(name).ok_or_else(|| BackendError::not_found(format!("Jump host {} of {} was not found", name, candidate.connection.name)))?),
+			None => None,
+		};
+		if let Some(connection) = stored.iter().find(|connection| connection.name == candidate.connection.name) {
+			if connection.jump_host_id != jump_host_id {
+				// Secrets are returned empty, which keeps the stored ones
+				let mut connection = connection.clone();
+				connection.jump_host_id = jump_host_id;
+				update_connection(connection.id, connection, None, None)?;
+			}
+		}
+	}
+	Ok(result)
+}
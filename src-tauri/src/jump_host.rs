@@ -0,0 +1,163 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::app_settings;
+use crate::connection_manager::{get_connection_by_id, Connection};
+use crate::error::BackendError;
+use crate::ssh_instance::{retry, SSHInstance};
+
+/// The longest chain of jump hosts that is followed, guards against misconfigured chains.
+const MAX_JUMP_HOPS: usize = 8;
+
+/// How long the tunnel thread sleeps when no data is flowing in either direction.
+const IDLE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Opens the TCP stream an SSH session to a connection runs over.
+///
+/// Connections without a jump host are connected directly. Otherwise the jump host is connected first,
+/// through its own jump host if it has one, and a `direct-tcpip` channel to the target is opened on it.
+/// libssh2 can only run over a real socket, so the channel is bridged to a local socket by a thread.
+///
+/// # Arguments
+///
+/// * `options` - The connection to open the stream for.
+/// * `chain` - The ids of the connections that are already being tunneled through, to detect loops.
+pub fn open_stream(options: &Connection, chain: &[i32]) -> Result<TcpStream, BackendError> {
+	let jump_host_id = match options.jump_host_id {
+		Some(jump_host_id) => jump_host_id,
+		None => return connect_tcp(&options.host, options.port as u16),
+	};
+
+	if jump_host_id == options.id || chain.contains(&jump_host_id) {
+		return Err(BackendError::invalid_input(format!("The jump host chain of {} loops back to connection {}", options.name, jump_host_id)));
+	}
+	if chain.len() >= MAX_JUMP_HOPS {
+		return Err(BackendError::invalid_input(format!("The jump host chain of {} is longer than {} hops", options.name, MAX_JUMP_HOPS)));
+	}
+
+	// The secrets of the jump host are filled in when connecting to it
+	let jump_options = get_connection_by_id(jump_host_id, None)?;
+	let mut next_chain = chain.to_vec();
+	next_chain.push(options.id);
+	let jump = SSHInstance::connect_through(jump_options, &next_chain)?;
+
+	let channel = jump.session.channel_direct_tcpip(&options.host, options.port as u16, None)
+		.map_err(|e| BackendError::ssh(&format!("Error opening tunnel to {}:{} through jump host", options.host, options.port), &e))?;
+	tunnel(jump, channel)
+}
+
+/// Opens a TCP connection, giving up once the connect timeout of the network settings has passed.
+///
+/// Every address the host resolves to is tried in turn, like `TcpStream::connect` does.
+///
+/// # Arguments
+///
+/// * `host` - The host name or address to connect to.
+/// * `port` - The port to connect to.
+pub fn connect_tcp(host: &str, port: u16) -> Result<TcpStream, BackendError> {
+	let context = format!("Error connecting to {}:{}", host, port);
+	let timeout = app_settings::network_settings().connect_timeout();
+	let addresses = (host, port).to_socket_addrs().map_err(|e| BackendError::io(&context, &e))?;
+
+	let mut last_error = None;
+	for address in addresses {
+		let result = match timeout {
+			Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+			None => TcpStream::connect(address),
+		};
+		match result {
+			Ok(tcp) => return Ok(tcp),
+			Err(e) => last_error = Some(e),
+		}
+	}
+	Err(match last_error {
+		Some(e) => BackendError::io(&context, &e),
+		None => BackendError::not_found(format!("{}: the host has no addresses", context)),
+	})
+}
+
+/// Bridges a `direct-tcpip` channel to a local socket and returns the other end of that socket.
+///
+/// The jump host session is kept alive by the bridge thread, which ends once either side closes.
+fn tunnel(jump: SSHInstance, channel: ssh2::Channel) -> Result<TcpStream, BackendError> {
+	let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| BackendError::io("Error creating tunnel socket", &e))?;
+	let address = listener.local_addr().map_err(|e| BackendError::io("Error creating tunnel socket", &e))?;
+	let client = TcpStream::connect(address).map_err(|e| BackendError::io("Error connecting tunnel socket", &e))?;
+	let (local, peer) = listener.accept().map_err(|e| BackendError::io("Error accepting tunnel socket", &e))?;
+
+	// Another local process could have connected first, only ever bridge our own socket
+	if Some(peer) != client.local_addr().ok() {
+		return Err(BackendError::other("Unexpected connection on the tunnel socket"));
+	}
+
+	local.set_nonblocking(true).map_err(|e| BackendError::io("Error configuring tunnel socket", &e))?;
+	jump.session.set_blocking(false);
+	std::thread::spawn(move || {
+		let mut local = local;
+		let mut channel = channel;
+		bridge(&mut local, &mut channel);
+		let _ = retry(|| channel.close().map_err(io::Error::from));
+		// The jump host session has to outlive its channel
+		drop(channel);
+		drop(jump);
+	});
+	Ok(client)
+}
+
+/// Copies data between the local socket and the channel until one of them is closed or fails.
+fn bridge(local: &mut TcpStream, channel: &mut ssh2::Channel) {
+	let mut buffer = [0; 32768];
+	loop {
+		let mut idle = true;
+
+		// Forward what the SSH session on this machine sends to the target
+		match local.read(&mut buffer) {
+			Ok(0) => return,
+			Ok(n) => {
+				idle = false;
+				if write_all(|data| channel.write(data), &buffer[..n]).is_err() {
+					return;
+				}
+			}
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+			Err(_) => return,
+		}
+
+		// Forward what the target answers back to the local socket
+		match channel.read(&mut buffer) {
+			Ok(0) => {
+				if channel.eof() {
+					return;
+				}
+			}
+			Ok(n) => {
+				idle = false;
+				if write_all(|data| local.write(data), &buffer[..n]).is_err() {
+					return;
+				}
+			}
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+			Err(_) => return,
+		}
+
+		if idle {
+			std::thread::sleep(IDLE_INTERVAL);
+		}
+	}
+}
+
+/// Writes all data to a non-blocking writer, retrying while it would block.
+fn write_all<F>(mut write: F, data: &[u8]) -> io::Result<()>
+where
+	F: FnMut(&[u8]) -> io::Result<usize>,
+{
+	let mut written = 0;
+	while written < data.len() {
+		match retry(|| write(&data[written..]))? {
+			0 => return Err(io::ErrorKind::WriteZero.into()),
+			n => written += n,
+		}
+	}
+	Ok(())
+}
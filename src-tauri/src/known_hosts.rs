@@ -0,0 +1,307 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use ssh2::{CheckResult, HashType, HostKeyType, KnownHostFileKind, KnownHosts, Session};
+use tauri::AppHandle;
+
+use crate::app_paths;
+use crate::connection_manager::Connection;
+use crate::error::BackendError;
+use crate::jump_host;
+use crate::operations::run_blocking;
+
+/// The key a server presented during the handshake.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HostKey {
+	pub host: String,
+	pub port: u16,
+	pub key_type: String,
+	pub fingerprint: String,
+}
+
+/// Returns the OpenSSH name of a host key type.
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+	match key_type {
+		HostKeyType::Rsa => "ssh-rsa",
+		HostKeyType::Dss => "ssh-dss",
+		HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+		HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+		HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+		HostKeyType::Ed255219 => "ssh-ed25519",
+		HostKeyType::Unknown => "unknown",
+	}
+}
+
+/// Returns the SHA256 fingerprint of the host key of a session, in the format OpenSSH prints it.
+fn fingerprint(session: &Session) -> Option<String> {
+	session.host_key_hash(HashType::Sha256)
+		.map(|hash| format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash)))
+}
+
+/// Returns the name known_hosts files use for a host, non-standard ports are written as `[host]:port`.
+fn known_hosts_name(host: &str, port: u16) -> String {
+	if port == 22 {
+		host.to_string()
+	} else {
+		format!("[{}]:{}", host, port)
+	}
+}
+
+/// Reads the host key of a session.
+fn host_key(session: &Session, host: &str, port: u16) -> Result<HostKey, BackendError> {
+	let (_, key_type) = session.host_key().ok_or_else(|| BackendError::protocol(format!("{} did not send a host key", host)))?;
+	Ok(HostKey {
+		host: host.to_string(),
+		port,
+		key_type: key_type_name(key_type).to_string(),
+		fingerprint: fingerprint(session).ok_or_else(|| BackendError::protocol(format!("The host key of {} could not be hashed", host)))?,
+	})
+}
+
+/// Loads a known_hosts file, a missing file is treated as empty.
+fn load(session: &Session, path: &Path) -> Result<KnownHosts, BackendError> {
+	let mut known_hosts = session.known_hosts().map_err(|e| BackendError::ssh("Error initializing known hosts", &e))?;
+	if path.exists() {
+		known_hosts.read_file(path, KnownHostFileKind::OpenSSH).map_err(|e| BackendError::ssh(&format!("Error reading {}", path.display()), &e))?;
+	}
+	Ok(known_hosts)
+}
+
+/// Returns the key type of a known_hosts line, e.g. `ssh-ed25519`, skipping a leading marker like `@revoked`.
+fn line_key_type(line: &str) -> Option<&str> {
+	let mut fields = line.split_whitespace().skip_while(|field| field.starts_with('@'));
+	fields.next()?;
+	fields.next()
+}
+
+/// Loads the keys of one type from a known_hosts file line by line, skipping the lines libssh2 can not parse.
+///
+/// `~/.ssh/known_hosts` is written by OpenSSH and may contain markers like `@cert-authority` or key types libssh2
+/// does not know, which make `KnownHosts::read_file` fail as a whole. A file that can not be read is treated as empty.
+/// The result must not be written back, it would drop the skipped lines.
+///
+/// Only keys of the negotiated type are loaded, as ssh2 checks keys without their type and libssh2 then reports a
+/// stored key of any other type as a mismatch. A host that only has keys of other types is unknown instead.
+fn load_lenient(session: &Session, path: &Path, key_type: &str) -> Result<KnownHosts, BackendError> {
+	let mut known_hosts = session.known_hosts().map_err(|e| BackendError::ssh("Error initializing known hosts", &e))?;
+	let contents = fs::read_to_string(path).unwrap_or_default();
+	for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+		if line_key_type(line) == Some(key_type) {
+			let _ = known_hosts.read_str(line, KnownHostFileKind::OpenSSH);
+		}
+	}
+	Ok(known_hosts)
+}
+
+/// Verifies the host key of a session after the handshake.
+///
+/// The key is checked against the known_hosts file of the application first and, if the server is not
+/// in there, against the `~/.ssh/known_hosts` file of the user if it exists.
+///
+/// # Arguments
+///
+/// * `session` - The session, the handshake must already be done.
+/// * `host` - The host that was connected to.
+/// * `port` - The port that was connected to.
+///
+/// # Returns
+///
+/// * `Ok(())` if the key is known and matches.
+/// * `Err(BackendError::UnknownHostKey)` or `Err(BackendError::HostKeyMismatch)` with the fingerprint of the key,
+///   so the frontend can ask the user whether to trust it.
+pub fn verify(session: &Session, host: &str, port: u16) -> Result<(), BackendError> {
+	let key = host_key(session, host, port)?;
+	let (raw_key, _) = session.host_key().ok_or_else(|| BackendError::protocol(format!("{} did not send a host key", host)))?;
+
+	// The application store always wins, it holds the keys the user explicitly trusted
+	let mut files = vec![PathBuf::from(get_known_hosts_path())];
+	if let Some(user_file) = get_user_known_hosts_path() {
+		files.push(user_file);
+	}
+
+	for file in files {
+		let known_hosts = load_lenient(session, &file, &key.key_type)?;
+		match known_hosts.check_port(host, port, raw_key) {
+			CheckResult::Match => return Ok(()),
+			CheckResult::Mismatch => return Err(BackendError::HostKeyMismatch {
+				message: format!("The host key of {} changed to {}, this may be a man-in-the-middle attack", host, key.fingerprint),
+				host: key.host,
+				port,
+				key_type: key.key_type,
+				fingerprint: key.fingerprint,
+			}),
+			// A file that can not be checked must not block the connection, the key is then treated as unknown
+			CheckResult::NotFound | CheckResult::Failure => continue,
+		}
+	}
+
+	Err(BackendError::UnknownHostKey {
+		message: format!("The host key of {} is not known yet, its fingerprint is {}", host, key.fingerprint),
+		host: key.host,
+		port,
+		key_type: key.key_type,
+		fingerprint: key.fingerprint,
+	})
+}
+
+/// Connects to a server, through its jump hosts if it has any, and performs the handshake without authenticating.
+fn handshake(options: &Connection) -> Result<Session, BackendError> {
+	let tcp = jump_host::open_stream(options, &[])?;
+	let mut session = Session::new().map_err(|e| BackendError::ssh("Error creating session", &e))?;
+	session.set_tcp_stream(tcp);
+	session.handshake().map_err(|e| BackendError::ssh("Error handshaking", &e))?;
+	Ok(session)
+}
+
+/// Retrieves the host key a server presents, so the user can compare it before trusting it.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to run the handshake on the blocking thread pool.
+/// * `options` - The connection to retrieve the key for.
+/// * `operation_id` - An id to cancel the command with, see `cancel_operation`.
+#[tauri::command]
+pub async fn get_host_key(app: AppHandle, options: Connection, operation_id: Option<String>) -> Result<HostKey, BackendError> {
+	run_blocking(app, operation_id, move |_, _| {
+		let session = handshake(&options)?;
+		host_key(&session, &options.host, options.port as u16)
+	}).await
+}
+
+/// Adds the key a server currently presents to the known_hosts file of the application.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to run the handshake on the blocking thread pool.
+/// * `options` - The connection to trust.
+/// * `fingerprint` - The fingerprint the user accepted, the key is only stored if the server still presents it.
+/// * `operation_id` - An id to cancel the command with, see `cancel_operation`.
+#[tauri::command]
+pub async fn trust_host_key(app: AppHandle, options: Connection, fingerprint: String, operation_id: Option<String>) -> Result<(), BackendError> {
+	run_blocking(app, operation_id, move |_, _| trust(&options, &fingerprint)).await
+}
+
+/// Adds the key a server currently presents to the known_hosts file, if it still has the accepted fingerprint.
+fn trust(options: &Connection, fingerprint: &str) -> Result<(), BackendError> {
+	let port = options.port as u16;
+	let session = handshake(options)?;
+	let key = host_key(&session, &options.host, port)?;
+	if key.fingerprint != fingerprint {
+		return Err(BackendError::HostKeyMismatch {
+			message: format!("The host key of {} changed to {} while it was being trusted", options.host, key.fingerprint),
+			host: key.host,
+			port,
+			key_type: key.key_type,
+			fingerprint: key.fingerprint,
+		});
+	}
+
+	// A known but different key has to be forgotten explicitly first
+	match verify(&session, &options.host, port) {
+		Ok(()) => return Ok(()),
+		Err(BackendError::UnknownHostKey { .. }) => (),
+		Err(error) => return Err(error),
+	}
+
+	let path = PathBuf::from(get_known_hosts_path());
+	let mut known_hosts = load(&session, &path)?;
+	let (raw_key, key_type) = session.host_key().ok_or_else(|| BackendError::protocol(format!("{} did not send a host key", options.host)))?;
+	known_hosts.add(&known_hosts_name(&options.host, port), raw_key, "added by sftp-editor", key_type.into())
+		.map_err(|e| BackendError::ssh("Error adding host key", &e))?;
+	known_hosts.write_file(&path, KnownHostFileKind::OpenSSH).map_err(|e| BackendError::ssh(&format!("Error writing {}", path.display()), &e))
+}
+
+/// Removes all keys of a server from the known_hosts file of the application, e.g. after a legitimate key change.
+///
+/// Keys in `~/.ssh/known_hosts` are never modified.
+///
+/// # Arguments
+///
+/// * `host` - The host to forget.
+/// * `port` - The port of the host.
+#[tauri::command]
+pub fn forget_host_key(host: String, port: u16) -> Result<(), BackendError> {
+	let path = PathBuf::from(get_known_hosts_path());
+	if !path.exists() {
+		return Ok(());
+	}
+
+	// KnownHosts is bound to a session, but does not need a connection
+	let session = Session::new().map_err(|e| BackendError::ssh("Error creating session", &e))?;
+	let known_hosts = load(&session, &path)?;
+	let name = known_hosts_name(&host, port);
+	for entry in known_hosts.hosts().map_err(|e| BackendError::ssh("Error reading known hosts", &e))? {
+		if entry.name() == Some(name.as_str()) {
+			known_hosts.remove(&entry).map_err(|e| BackendError::ssh("Error removing host key", &e))?;
+		}
+	}
+	known_hosts.write_file(&path, KnownHostFileKind::OpenSSH).map_err(|e| BackendError::ssh(&format!("Error writing {}", path.display()), &e))
+}
+
+/// Returns the `~/.ssh/known_hosts` file of the user, if it exists.
+fn get_user_known_hosts_path() -> Option<PathBuf> {
+	tauri::api::path::home_dir()
+		.map(|home| home.join(".ssh").join("known_hosts"))
+		.filter(|path| path.exists())
+}
+
+/// Retrieves the path to the known_hosts file of the application.
+///
+/// The file is stored in the data directory of the user, like the connection database.
+fn get_known_hosts_path() -> String {
+	app_paths::data_file("known_hosts").to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lenient_loading_skips_unparsable_lines() {
+		let path = std::env::temp_dir().join(format!("known_hosts-test-{}", std::process::id()));
+		fs::write(&path, concat!(
+			"# a comment\n",
+			"@cert-authority *.example.com ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl\n",
+			"example.com sk-ssh-ed25519@openssh.com AAAAGnNrLXNzaC1lZDI1NTE5QG9wZW5zc2guY29tAAAAIA==\n",
+			"example.com ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl\n",
+		)).unwrap();
+
+		let session = Session::new().unwrap();
+		let known_hosts = load_lenient(&session, &path, "ssh-ed25519");
+		let _ = fs::remove_file(&path);
+
+		let hosts = known_hosts.unwrap().hosts().unwrap();
+		assert!(hosts.iter().any(|host| host.name() == Some("example.com")));
+	}
+
+	#[test]
+	fn lenient_loading_treats_a_missing_file_as_empty() {
+		let session = Session::new().unwrap();
+		let known_hosts = load_lenient(&session, Path::new("/nonexistent/known_hosts"), "ssh-ed25519").unwrap();
+		assert!(known_hosts.hosts().unwrap().is_empty());
+	}
+
+	#[test]
+	fn a_stored_key_of_another_type_is_unknown() {
+		let path = std::env::temp_dir().join(format!("known_hosts-type-test-{}", std::process::id()));
+		fs::write(&path, "example.com ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIL/kbOCDL6da9OPnFreALXBx/Eh6ZFX9M+L0ptKjiZGL\n").unwrap();
+		let ecdsa = base64::engine::general_purpose::STANDARD.decode("AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBH7+9V3+8jd7uZhUQHKs2tBPcwF5Irg5db0m4vDiAiaeNWBTNYP+TLA/FLtitFxjXhYWUk6AdUuGCISLrv663kM=").unwrap();
+		let ed25519 = base64::engine::general_purpose::STANDARD.decode("AAAAC3NzaC1lZDI1NTE5AAAAIL/kbOCDL6da9OPnFreALXBx/Eh6ZFX9M+L0ptKjiZGL").unwrap();
+
+		let session = Session::new().unwrap();
+		let other_type = load_lenient(&session, &path, "ecdsa-sha2-nistp256").unwrap().check_port("example.com", 22, &ecdsa);
+		let same_type = load_lenient(&session, &path, "ssh-ed25519").unwrap().check_port("example.com", 22, &ed25519);
+		let _ = fs::remove_file(&path);
+
+		assert!(matches!(other_type, CheckResult::NotFound));
+		assert!(matches!(same_type, CheckResult::Match));
+	}
+
+	#[test]
+	fn key_types_skip_markers() {
+		assert_eq!(line_key_type("example.com ssh-rsa AAAA"), Some("ssh-rsa"));
+		assert_eq!(line_key_type("@revoked example.com ssh-rsa AAAA"), Some("ssh-rsa"));
+		assert_eq!(line_key_type("example.com"), None);
+	}
+}
@@ -1,10 +1,17 @@
-use std::path::Path;
-
 use serde::{Deserialize, Serialize};
 use sqlite::State;
 use tauri::{LogicalSize, Manager, Size};
 use window_shadows::set_shadow;
 
+use crate::app_paths;
+use crate::error::BackendError;
+
+/// The `LogType.INFO` value of the frontend, for messages the backend logs itself.
+pub(crate) const LOG_INFO: i64 = 1;
+
+/// The `LogType.WARN` value of the frontend.
+pub(crate) const LOG_WARN: i64 = 2;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LogMessage {
 	id: i64,
@@ -26,9 +33,9 @@ pub struct LogMessage {
 /// # Errors
 /// Returns an Err if there are issues obtaining the LOG_FILE_PATH environment variable,
 /// opening the log file database, preparing or executing the SQL statement
-pub fn log(message: &str, arguments: &str, log_type: i64) -> Result<(), String> {
+pub fn log(message: &str, arguments: &str, log_type: i64) -> Result<(), BackendError> {
 	// Get log file path from environment variable
-	let log_file_path = std::env::var("LOG_FILE_PATH").map_err(|e| format!("Failed to get LOG_FILE_PATH environment variable: {}", e))?;
+	let log_file_path = std::env::var("LOG_FILE_PATH").map_err(|e| BackendError::other(format!("Failed to get LOG_FILE_PATH environment variable: {}", e)))?;
 
 	// Open sqlite connection
 	match sqlite::open(log_file_path) {
@@ -37,9 +44,9 @@ pub fn log(message: &str, arguments: &str, log_type: i64) -> Result<(), String>
 			match connection.prepare("INSERT INTO `logs` (`type`, `message`, `arguments`) VALUES (?, ?, ?);") {
 				Ok(mut statement) => {
 					// Bind values to SQL statement
-					statement.bind((1, log_type)).map_err(|e| format!("Failed to bind log_type: {}", e))?;
-					statement.bind((2, message)).map_err(|e| format!("Failed to bind message: {}", e))?;
-					statement.bind((3, arguments)).map_err(|e| format!("Failed to bind arguments: {}", e))?;
+					statement.bind((1, log_type)).map_err(|e| BackendError::database(format!("Failed to bind log_type: {}", e)))?;
+					statement.bind((2, message)).map_err(|e| BackendError::database(format!("Failed to bind message: {}", e)))?;
+					statement.bind((3, arguments)).map_err(|e| BackendError::database(format!("Failed to bind arguments: {}", e)))?;
 					// Execute the SQL statement
 					loop {
 						match statement.next() {
@@ -48,39 +55,30 @@ pub fn log(message: &str, arguments: &str, log_type: i64) -> Result<(), String>
 									break;
 								}
 							},
-							Err(e) => return Err(format!("Failed to move statement cursor to the next row: {}", e))
+							Err(e) => return Err(BackendError::database(format!("Failed to move statement cursor to the next row: {}", e)))
 						}
 					}
 					// Output message for debugging purposes
 					println!("{}", message);
 					Ok(())
 				},
-				Err(e) => Err(format!("Failed to prepare log statement: {}", e))
+				Err(e) => Err(BackendError::database(format!("Failed to prepare log statement: {}", e)))
 			}
 		},
-		Err(e) => Err(format!("Failed to open log file: {}", e))
+		Err(e) => Err(BackendError::database(format!("Failed to open log file: {}", e)))
 	}
 }
 
-/// Initializes the log file by creating a SQLite database and a log table
+/// Initializes the log file by creating a SQLite database and a log table in the data directory
 ///
 /// # Examples
 ///
 /// ```
 /// initialize_log_file();
 /// ```
-pub fn initialize_log_file() -> Result<(), String> {
-	// Define the name of the log database file
-	let file_name = "sftp-editor-client-log.db";
-
-	// Get the path to the temporary directory of the system
-	let temp_dir = std::env::temp_dir();
-
-	// Convert the path to a Path object for further operations
-	let temp_dir = Path::new(&temp_dir);
-
-	// Join the database file name to the path of the temporary directory
-	let file_path = temp_dir.join(file_name);
+pub fn initialize_log_file() -> Result<(), BackendError> {
+	// Keep the log database with the other data of the application, which is beside the executable in portable mode
+	let file_path = app_paths::data_file("sftp-editor-client-log.db");
 
 	// Set an environment variable with the location of the log database file
 	std::env::set_var("LOG_FILE_PATH", &file_path);
@@ -91,11 +89,11 @@ pub fn initialize_log_file() -> Result<(), String> {
 		Ok(connection) => {
 			// Execute an SQL command to create a new table for the logs if it doesn't exist
 			connection.execute("CREATE TABLE IF NOT EXISTS `logs` ('id' INTEGER PRIMARY KEY, 'type' TINYINT, 'message' TEXT, 'arguments' TEXT DEFAULT NULL, 'created' TIMESTAMP DEFAULT CURRENT_TIMESTAMP);")
-			          .map_err(|e| format!("Error creating log table: {}", e))?; // If the table creation failed, print an error message
+			          .map_err(|e| BackendError::database(format!("Error creating log table: {}", e)))?; // If the table creation failed, print an error message
 			Ok(())
 		}
 		// If the file opening/creation failed, print an error message
-		Err(e) => Err(format!("Error creating log file: {}", e))
+		Err(e) => Err(BackendError::database(format!("Error creating log file: {}", e)))
 	}
 }
 
@@ -112,7 +110,7 @@ pub fn initialize_log_file() -> Result<(), String> {
 /// # Returns
 ///
 /// * `Ok(Vec<LogMessage>)` - A vector containing the retrieved log messages.
-/// * `Err(String)` - An error message if there was a problem executing the query.
+/// * `Err(BackendError)` - If there was a problem executing the query.
 ///
 /// # Examples
 ///
@@ -127,11 +125,11 @@ pub fn initialize_log_file() -> Result<(), String> {
 /// assert!(result.is_ok());
 /// ```
 #[tauri::command]
-pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types: Vec<i8>, search: Option<&str>) -> Result<Vec<LogMessage>, String> {
+pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types: Vec<i8>, search: Option<&str>) -> Result<Vec<LogMessage>, BackendError> {
 
 	// Build SQL query string based on provided start_date, end_date, limit, log_types and search parameters
 	let mut query: String = format!("SELECT * FROM `logs` WHERE `created` BETWEEN '{}' AND '{}'", start_date, end_date);
-	if log_types.len() > 0 {
+	if !log_types.is_empty() {
 		query.push_str(" AND `type` IN (");
 		// add types to the SQL query string
 		for (i, log_type) in log_types.iter().enumerate() {
@@ -140,7 +138,7 @@ pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types:
 				query.push_str(", ");
 			}
 		}
-		query.push_str(")");
+		query.push(')');
 	}
 	// if search query is provided, add it to the SQL query string
 	if let Some(q) = search {
@@ -152,7 +150,7 @@ pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types:
 	println!("{}", query); // Output the query for debugging purposes
 
 	// Open the log file
-	match sqlite::open(std::env::var("LOG_FILE_PATH").unwrap_or_else(|_| return "Failed to get LOG_FILE_PATH environment variable.".to_string())) {
+	match sqlite::open(std::env::var("LOG_FILE_PATH").unwrap_or_else(|_| "Failed to get LOG_FILE_PATH environment variable.".to_string())) {
 		Ok(conn) => {
 			// Prepare the SQL statement
 			match conn.prepare(&query) {
@@ -163,18 +161,15 @@ pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types:
 					while let Ok(state) = statement.next() {
 						if state == State::Row {
 							// For each row, read out each column
-							let id = statement.read::<i64, usize>(0).map_err(|e| format!("Failed to get column from database: {}", e.to_string()));
-							let log_type = statement.read::<i64, usize>(1).map_err(|e| format!("Failed to get column from database: {}", e.to_string()))? as i8;
-							let message = statement.read::<String, usize>(2).map_err(|e| format!("Failed to get column from database: {}", e.to_string()));
-							let args = statement.read::<String, usize>(3).map_err(|e| format!("Failed to get column from database: {}", e.to_string()));
-							let created = statement.read::<String, usize>(4).map_err(|e| format!("Failed to get column from database: {}", e.to_string()));
+							let id = statement.read::<i64, usize>(0).map_err(|e| BackendError::database(format!("Failed to get column from database: {}", e)));
+							let log_type = statement.read::<i64, usize>(1).map_err(|e| BackendError::database(format!("Failed to get column from database: {}", e)))? as i8;
+							let message = statement.read::<String, usize>(2).map_err(|e| BackendError::database(format!("Failed to get column from database: {}", e)));
+							let args = statement.read::<String, usize>(3).map_err(|e| BackendError::database(format!("Failed to get column from database: {}", e)));
+							let created = statement.read::<String, usize>(4).map_err(|e| BackendError::database(format!("Failed to get column from database: {}", e)));
 
 							// Create the LogMessage from the read values and add it to the vector
-							match (id, log_type, message, args, created) {
-								(Ok(id), log_type, Ok(message), Ok(args), Ok(created)) => {
-									logs.push(LogMessage { id, log_type, message, args, created });
-								},
-								_ => {}
+							if let (Ok(id), log_type, Ok(message), Ok(args), Ok(created)) = (id, log_type, message, args, created) {
+								logs.push(LogMessage { id, log_type, message, args, created });
 							}
 						}
 						if state == State::Done
@@ -183,13 +178,13 @@ pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types:
 						}
 					}
 					// Return the vector of LogMessages
-					return Ok(logs);
+					Ok(logs)
 				},
-				Err(e) => Err(format!("Error executing log query: {}", e))    // If there is a problem with the SQL execution, return an error
+				Err(e) => Err(BackendError::database(format!("Error executing log query: {}", e)))    // If there is a problem with the SQL execution, return an error
 			}
 		}
 		// If there is a problem opening the file, return an error
-		Err(e) => Err(format!("Error opening the Log file: {}", e))
+		Err(e) => Err(BackendError::database(format!("Error opening the Log file: {}", e)))
 	}
 }
 
@@ -203,13 +198,13 @@ pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types:
 /// # Returns
 ///
 /// - `Ok(String)`: The oldest log message date.
-/// - `Err(String)`: An error message if an error occurs during the retrieval process.
+/// - `Err(BackendError)`: If an error occurs during the retrieval process.
 ///
 /// # Errors
 ///
 /// - If the log file fails to open, an error message is returned.
 /// - If the SQL statement fails to execute, an error message is returned.
-/// - If no logs are found, an error message is returned.
+/// - If no logs are found, a `NotFound` error is returned.
 ///
 /// # Example
 ///
@@ -220,28 +215,29 @@ pub fn get_log_history(start_date: &str, end_date: &str, limit: i32, log_types:
 /// println!("Oldest log date: {}", oldest_date);
 /// ```
 #[tauri::command]
-pub fn get_oldest_log_date() -> Result<String, String>
+pub fn get_oldest_log_date() -> Result<String, BackendError>
 {
 	// Try to open the log file
-	match sqlite::open(std::env::var("LOG_FILE_PATH").unwrap()) {
+	let log_file_path = std::env::var("LOG_FILE_PATH").map_err(|e| BackendError::other(format!("Failed to get LOG_FILE_PATH environment variable: {}", e)))?;
+	match sqlite::open(log_file_path) {
 		Ok(conn) => {
 			// Prepare the SQL statement to select the oldest log message
 			match conn.prepare("SELECT `created` FROM `logs` ORDER BY `created` ASC LIMIT 1") {
 				Ok(mut statement) => {
 					// Executing the statement
-					if let State::Row = statement.next().unwrap() {
+					if let State::Row = statement.next()? {
 						// Read the date from the returned row
-						let date = statement.read::<String, usize>(0).unwrap();
+						let date = statement.read::<String, usize>(0)?;
 						// Return the date
 						Ok(date)
 					} else {
-						Err("No logs found".to_string())
+						Err(BackendError::not_found("No logs found"))
 					}
 				}
-				Err(e) => Err(format!("Error executing log query: {}", e))
+				Err(e) => Err(BackendError::database(format!("Error executing log query: {}", e)))
 			}
 		}
-		Err(e) => Err(format!("Error creating log file: {}", e))
+		Err(e) => Err(BackendError::database(format!("Error creating log file: {}", e)))
 	}
 }
 
@@ -254,7 +250,7 @@ pub fn get_oldest_log_date() -> Result<String, String>
 ///
 /// # Returns
 ///
-/// Returns `Ok(())` if the operation is successful, or an error message as a `String` if an error occurs.
+/// Returns `Ok(())` if the operation is successful, or a `BackendError` if an error occurs.
 ///
 /// # Example
 ///
@@ -262,19 +258,19 @@ pub fn get_oldest_log_date() -> Result<String, String>
 /// use tauri::AppHandle;
 ///
 /// # #[tauri::command]
-/// # async fn open_log_window(handle: AppHandle, always_on_top: bool) -> Result<(), String> {
+/// # async fn open_log_window(handle: AppHandle, always_on_top: bool) -> Result<(), BackendError> {
 /// #     // implementation details...
 /// #     # Ok(())
 /// # }
 /// ```
 #[tauri::command]
-pub async fn open_log_window(handle: tauri::AppHandle, always_on_top: bool) -> Result<(), String> {
+pub async fn open_log_window(handle: tauri::AppHandle, always_on_top: bool) -> Result<(), BackendError> {
 	// Check if the window is already open
 	let window = handle.get_window("logger");
 
 	// If it is, focus the window
-	if window.is_some() {
-		window.unwrap().set_focus().unwrap();
+	if let Some(window) = window {
+		window.set_focus()?;
 		return Ok(());
 	}
 
@@ -292,17 +288,17 @@ pub async fn open_log_window(handle: tauri::AppHandle, always_on_top: bool) -> R
 		.decorations(false)
 		.always_on_top(always_on_top)
 		.focused(true)
-		.build().unwrap();
+		.build()?;
 
-	// Set a shadow for the window
-	set_shadow(&window, true).unwrap();
+	// Set a shadow for the window, not every platform supports shadows so a failure is not an error
+	let _ = set_shadow(&window, true);
 
 	// Set minimum and initial size for the window
-	window.set_min_size(Some(Size::from(LogicalSize::new(530, 370)))).unwrap();
-	window.set_size(LogicalSize::new(600, 600)).unwrap();
+	window.set_min_size(Some(Size::from(LogicalSize::new(530, 370))))?;
+	window.set_size(LogicalSize::new(600, 600))?;
 
 	// Show the window
-	window.show().unwrap();
+	window.show()?;
 
 	Ok(())
 }
@@ -316,7 +312,7 @@ pub async fn open_log_window(handle: tauri::AppHandle, always_on_top: bool) -> R
 ///
 /// # Errors
 ///
-/// Returns `Err("Log window is not open".to_string())` if the log window does not exist.
+/// Returns a `NotFound` error if the log window does not exist.
 ///
 /// # Example
 ///
@@ -329,16 +325,16 @@ pub async fn open_log_window(handle: tauri::AppHandle, always_on_top: bool) -> R
 /// set_log_window_always_on_top(handle, true)?;
 /// ```
 #[tauri::command]
-pub fn set_log_window_always_on_top(handle: tauri::AppHandle, always_on_top: bool) -> Result<(), String> {
+pub fn set_log_window_always_on_top(handle: tauri::AppHandle, always_on_top: bool) -> Result<(), BackendError> {
 	// Check if the window exists
 	match handle.get_window("logger") {
 		Some(window) => {
 			// If it does, set its 'always on top' property
-			window.set_always_on_top(always_on_top).unwrap();
+			window.set_always_on_top(always_on_top)?;
 			Ok(())
 		},
 		// If it doesn't, return an error message
-		None => Err("Log window is not open".to_string())
+		None => Err(BackendError::not_found("Log window is not open"))
 	}
 }
 
@@ -350,8 +346,8 @@ pub fn set_log_window_always_on_top(handle: tauri::AppHandle, always_on_top: boo
 ///
 /// # Returns
 ///
-/// Returns `Ok(())` if the log window is successfully closed. Otherwise, returns `Err(String)`
-/// with an error message indicating that the log window is not open.
+/// Returns `Ok(())` if the log window is successfully closed. Otherwise, returns a `NotFound` error
+/// indicating that the log window is not open.
 ///
 /// # Example
 ///
@@ -363,16 +359,16 @@ pub fn set_log_window_always_on_top(handle: tauri::AppHandle, always_on_top: boo
 /// assert_eq!(result, Ok(()));
 /// ```
 #[tauri::command]
-pub fn close_log_window(handle: tauri::AppHandle) -> Result<(), String> {
+pub fn close_log_window(handle: tauri::AppHandle) -> Result<(), BackendError> {
 	// Check if the window exists
 	match handle.get_window("logger") {
 		Some(window) => {
 			// If it does, close the window
-			window.close().unwrap();
+			window.close()?;
 			Ok(())
 		},
 		// If it doesn't, return an error message
-		None => Err("Log window is not open".to_string())
+		None => Err(BackendError::not_found("Log window is not open"))
 	}
 }
 
@@ -400,7 +396,7 @@ pub fn close_log_window(handle: tauri::AppHandle) -> Result<(), String> {
 #[tauri::command]
 pub fn clear_log(){
 	// Open the log file
-	match sqlite::open(std::env::var("LOG_FILE_PATH").unwrap_or_else(|_| return "Failed to get LOG_FILE_PATH environment variable.".to_string())) {
+	match sqlite::open(std::env::var("LOG_FILE_PATH").unwrap_or_else(|_| "Failed to get LOG_FILE_PATH environment variable.".to_string())) {
 		Ok(conn) => {
 			// Prepare the SQL statement
 			match conn.prepare("DELETE FROM `logs`") {
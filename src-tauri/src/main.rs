@@ -1,27 +1,61 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::env;
-
 use tauri::Manager;
 
 use app_settings::{get_settings, save_settings};
 use connection_manager::{add_connection, delete_connection, get_connection_by_id, get_connections, initialize, set_default, update_connection, update_join};
 use sftp_manager::{list, send_ssh_command, test_connection};
 
+use crate::known_hosts::{forget_host_key, get_host_key, trust_host_key};
+use crate::auth_prompt::{answer_auth_prompt, AuthPromptManager};
+use crate::connection_bundle::{export_connections, import_connections};
+use crate::credential_store::{get_credential_status, lock_credentials, set_master_password, unlock_credentials};
+use crate::file_editor::{edit_file, resolve_edit_conflict, stop_editing, EditorManager};
+use crate::operations::{cancel_operation, OperationManager};
+use crate::session_manager::{close_session, open_session, SessionManager};
+use crate::terminal_manager::{close_terminal, open_terminal, resize_terminal, write_terminal, TerminalManager};
+use crate::transfer_manager::{cancel_transfer, download_path, upload_path, TransferManager};
+use crate::transfer_queue::{cancel_queued_transfer, clear_finished_transfers, clear_transfer_history, enqueue_transfers, get_transfer_history, get_transfer_queue, move_transfer, pause_transfer, resume_transfer, TransferQueue};
 use crate::logger::{clear_log, close_log_window, get_log_history, get_oldest_log_date, initialize_log_file, log, open_log_window, set_log_window_always_on_top};
-use crate::sftp_manager::download_file;
+use crate::ssh_config::import_ssh_config;
+use crate::filezilla::import_filezilla;
+use crate::winscp::import_winscp;
+use crate::sftp_manager::{copy_path, create_directory, create_file, delete_path, download_file, move_path, rename_path, upload_file};
 
+mod app_paths;
 mod app_settings;
+mod auth_prompt;
+mod connection_bundle;
 mod connection_manager;
+mod credential_store;
+mod error;
+mod file_editor;
+mod filezilla;
+mod ftp_instance;
+mod importer;
+mod jump_host;
+mod known_hosts;
+mod operations;
+mod remote_fs;
+mod resume;
 mod sftp_manager;
+mod ssh_config;
+mod session_manager;
 mod ssh_instance;
+mod terminal_manager;
+mod transfer_manager;
+mod transfer_queue;
+mod winscp;
 mod logger;
 
 fn main() {
 	// Set an environmental variable for webkit
 	std::env::set_var("WEBKIT_DISABLE_COMPOSITING_MODE", "1");
 
+	// Resolve the per-user data directory, moving state that older versions stored beside the executable
+	let data_directory = app_paths::initialize();
+
 	// Initialize the application, handle any occurring error and exit the process is initialization fails
 	match initialize() {
 		Ok(_) => (),
@@ -31,13 +65,37 @@ fn main() {
 		}
 	}
 
-	// Initialize the log file
+	// Initialize the log file, the credential store logs when it stays locked
 	if initialize_log_file().is_err() {
 		println!("Failed to initialize log file");
 		std::process::exit(1);
 	}
+	let _ = log(&format!("Storing data in {}", data_directory.display()), "", logger::LOG_INFO);
+
+	// Create the credential store, and encrypt any passwords that are still stored in plaintext
+	match credential_store::initialize() {
+		Ok(_) => (),
+		Err(e) => {
+			println!("{}", e); // Log out the error
+			std::process::exit(1); // Exit process with an error code
+		}
+	}
 
 	tauri::Builder::default()
+		// Keep SSH sessions open between commands, a few pooled sessions per connection
+		.manage(SessionManager::new())
+		// Keep track of running network operations so they can be cancelled
+		.manage(OperationManager::new())
+		// Keep track of running directory transfers so they can be cancelled
+		.manage(TransferManager::new())
+		// Keep the queue of single file transfers and the workers that run them
+		.manage(TransferQueue::new())
+		// Keep track of remote files that are being edited through a local copy
+		.manage(EditorManager::new())
+		// Keep track of the interactive shells of the terminal panels
+		.manage(TerminalManager::new())
+		// Keep track of authentication prompts that wait for an answer from the user
+		.manage(AuthPromptManager::new())
 		// Set various invoke handlers that control the core functionality of the app 
 		.invoke_handler(tauri::generate_handler![
             get_settings,                                  // retrieves the applications settings
@@ -49,10 +107,52 @@ fn main() {
 			update_join,                                   // invoke an update join operation 
 			set_default,                                   // set a particular connection as the default
 			test_connection,                               // test a particular connection for validity
+			get_credential_status,                         // checks whether a master password is set and the credentials are unlocked
+			unlock_credentials,                            // unlocks the stored passwords with the master password
+			lock_credentials,                              // locks the stored passwords again
+			set_master_password,                           // sets, changes or removes the master password
 			list,                                          // list the file/directory structure via SFTP on the connected server
 			get_connection_by_id,                          // retrieves a specific connection from the app using its ID
+			import_ssh_config,                             // previews or imports the hosts of an OpenSSH config file
+			import_filezilla,                              // previews or imports the sites of a FileZilla site manager
+			import_winscp,                                 // previews or imports the sites of a WinSCP configuration file
+			export_connections,                            // exports connections to a JSON bundle, secrets optionally encrypted with a passphrase
+			import_connections,                            // previews or imports the connections of an exported bundle
 			send_ssh_command,                              // send an SSH command to the connected server
 			download_file,                                 // triggers a download file operation from the connected server
+			upload_file,                                   // uploads a local file to the connected server
+			create_directory,                              // creates a new directory on the connected server
+			create_file,                                   // creates a new empty file on the connected server
+			rename_path,                                   // renames a file or directory on the connected server
+			move_path,                                     // moves a file or directory into another directory
+			copy_path,                                     // copies a file or directory, recursively for directories
+			delete_path,                                   // deletes a file or directory, recursively for directories
+			get_host_key,                                  // retrieves the host key a server presents
+			trust_host_key,                                // adds the host key of a server to the known hosts
+			forget_host_key,                               // removes the stored host key of a server
+			open_session,                                  // opens a pooled SSH session for a connection
+			answer_auth_prompt,                            // answers or cancels a keyboard-interactive authentication prompt
+			close_session,                                 // closes the pooled SSH session of a connection
+			cancel_operation,                              // cancels a running network operation, e.g. a listing on a hung server
+			download_path,                                 // downloads a remote file or directory tree with progress events
+			upload_path,                                   // uploads a local file or directory tree with progress events
+			cancel_transfer,                               // cancels a running directory transfer
+			enqueue_transfers,                             // adds files to the transfer queue
+			get_transfer_queue,                            // retrieves the transfers of the queue in the order they run in
+			pause_transfer,                                // pauses a queued or running transfer
+			resume_transfer,                               // queues a paused or failed transfer again
+			cancel_queued_transfer,                        // cancels a queued, paused or running transfer
+			move_transfer,                                 // moves a transfer to another position in the queue
+			clear_finished_transfers,                      // removes done, failed and cancelled transfers from the queue
+			get_transfer_history,                          // retrieves the finished transfers, the most recent first
+			clear_transfer_history,                        // clears the transfer history
+			edit_file,                                     // downloads a remote file and uploads it again on every local save
+			resolve_edit_conflict,                         // overwrites or reloads an edited file whose remote copy changed
+			stop_editing,                                  // stops uploading local saves of an edited file
+			open_terminal,                                 // opens an interactive shell with a PTY
+			write_terminal,                                // writes input to an interactive shell
+			resize_terminal,                               // resizes the PTY of an interactive shell
+			close_terminal,                                // closes an interactive shell
 			log,                                           // log a new message in the application
 			get_log_history,                               // retrieves the history of logs in the application
 			open_log_window,                               // opens the window displaying the logs
@@ -63,17 +163,18 @@ fn main() {
         ])
 		// Initialize and add a plugin to add single instance functionality
 		.plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
-			app.emit_all("single-instance", Payload { args: argv, cwd }).unwrap(); // Emit the current active instance
+			let _ = app.emit_all("single-instance", Payload { args: argv, cwd }); // Emit the current active instance
 		}))
 		// Add a plugin to manage the window state
 		.plugin(tauri_plugin_window_state::Builder::default().build())
 		// Set up the app window and window properties
 		.setup(|app| {
 			use window_shadows::set_shadow; // Import method to set window shadow
-			let window = app.get_window("main").unwrap(); // Get the main app window
-			set_shadow(&window, true).unwrap(); // Set shadow
-			window.set_decorations(false).unwrap(); // Remove the window's default decoration
-			window.show().unwrap(); // Show the window
+			let window = app.get_window("main").ok_or("The main window is missing")?; // Get the main app window
+			let _ = set_shadow(&window, true); // Set shadow, not every platform supports it
+			window.set_decorations(false)?; // Remove the window's default decoration
+			window.show()?; // Show the window
+			auth_prompt::init(app.app_handle()); // Allow connections to ask the user for authentication answers
 			Ok(())
 		})
 		// Run the Tauri application with the generated context, and throw an error if it fails
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::auth_prompt;
+use crate::error::BackendError;
+
+/// A network operation that runs on the blocking thread pool and can be cancelled from the frontend.
+///
+/// Cancelling sets a flag that guarded readers and writers check between chunks and shuts down the sockets the
+/// operation registered, which makes a call that is blocked on an unresponsive server return right away.
+#[derive(Default)]
+pub struct Operation {
+	cancelled: AtomicBool,
+	sockets: Mutex<Vec<TcpStream>>,
+}
+
+impl Operation {
+	/// Checks whether the operation has been cancelled.
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::SeqCst)
+	}
+
+	/// Registers the socket of the session the operation runs on, so cancelling can interrupt it.
+	///
+	/// The session is unusable after it was interrupted, the session pool notices and reconnects on the next use.
+	///
+	/// # Arguments
+	///
+	/// * `socket` - A handle to the socket, usually a clone of the one the session owns.
+	pub fn attach(&self, socket: Option<TcpStream>) {
+		let socket = match socket {
+			Some(socket) => socket,
+			None => return,
+		};
+		if let Ok(mut sockets) = self.sockets.lock() {
+			// The operation may have been cancelled while it was waiting for its session
+			if self.is_cancelled() {
+				let _ = socket.shutdown(Shutdown::Both);
+			}
+			sockets.push(socket);
+		}
+	}
+
+	/// Cancels the operation and interrupts the sockets it registered.
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::SeqCst);
+		if let Ok(sockets) = self.sockets.lock() {
+			for socket in sockets.iter() {
+				let _ = socket.shutdown(Shutdown::Both);
+			}
+		}
+	}
+
+	/// Wraps a reader or writer so it fails with a `Cancelled` error once the operation is cancelled.
+	pub fn guard<T>(&self, inner: T) -> Guarded<'_, T> {
+		Guarded { operation: self, inner }
+	}
+}
+
+/// A reader or writer that stops as soon as its operation is cancelled, see `Operation::guard`.
+pub struct Guarded<'a, T> {
+	operation: &'a Operation,
+	inner: T,
+}
+
+impl<T> Guarded<'_, T> {
+	fn check(&self) -> io::Result<()> {
+		if self.operation.is_cancelled() {
+			Err(io::Error::other("Operation cancelled"))
+		} else {
+			Ok(())
+		}
+	}
+}
+
+impl<T: Read> Read for Guarded<'_, T> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.check()?;
+		self.inner.read(buf)
+	}
+}
+
+impl<T: Write> Write for Guarded<'_, T> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.check()?;
+		self.inner.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// Keeps track of the operations that can be cancelled, keyed by an id chosen by the frontend.
+#[derive(Default)]
+pub struct OperationManager {
+	operations: Mutex<HashMap<String, Arc<Operation>>>,
+}
+
+impl OperationManager {
+	/// Creates an operation manager without any running operations.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Registers a new operation, an operation without an id can not be cancelled.
+	fn start(&self, id: Option<&str>) -> Arc<Operation> {
+		let operation = Arc::new(Operation::default());
+		if let (Some(id), Ok(mut operations)) = (id, self.operations.lock()) {
+			operations.insert(id.to_string(), operation.clone());
+		}
+		operation
+	}
+
+	/// Forgets a finished operation, unless the id was reused by a newer operation in the meantime.
+	fn finish(&self, id: Option<&str>, operation: &Arc<Operation>) {
+		if let (Some(id), Ok(mut operations)) = (id, self.operations.lock()) {
+			if operations.get(id).is_some_and(|current| Arc::ptr_eq(current, operation)) {
+				operations.remove(id);
+			}
+		}
+	}
+
+	/// Cancels a running operation.
+	///
+	/// # Returns
+	///
+	/// `true` if the operation was running, otherwise `false`.
+	fn cancel(&self, id: &str) -> bool {
+		let operation = match self.operations.lock() {
+			Ok(operations) => operations.get(id).cloned(),
+			Err(_) => None,
+		};
+		match operation {
+			Some(operation) => {
+				operation.cancel();
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+/// Runs a blocking network operation on the blocking thread pool, so an `async` command never ties up the
+/// thread that handles IPC and several operations can run at once.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, passed on to the operation to reach the managed state.
+/// * `id` - The id the frontend can cancel the operation with, see `cancel_operation`.
+/// * `task` - The operation, it should check or `attach` to the `Operation` so it can be cancelled.
+///
+/// # Returns
+///
+/// The result of the operation, or a `Cancelled` error if it was cancelled, whatever error the interrupted
+/// operation ran into.
+pub async fn run_blocking<T, F>(app: AppHandle, id: Option<String>, task: F) -> Result<T, BackendError>
+where
+	T: Send + 'static,
+	F: FnOnce(&AppHandle, &Operation) -> Result<T, BackendError> + Send + 'static,
+{
+	// Register before the task is queued, so it can be cancelled while it waits for a thread
+	let operation = app.state::<OperationManager>().start(id.as_deref());
+	let handle = tauri::async_runtime::spawn_blocking(move || {
+		let result = auth_prompt::allow_prompts(|| task(&app, &operation));
+		app.state::<OperationManager>().finish(id.as_deref(), &operation);
+
+		// An interrupted operation fails with an I/O or protocol error, report it as what it is
+		match result {
+			Err(_) if operation.is_cancelled() => Err(BackendError::cancelled("Operation cancelled")),
+			result => result,
+		}
+	});
+	handle.await.map_err(|e| BackendError::other(format!("Background operation failed: {}", e)))?
+}
+
+/// Cancels a running network operation, e.g. the listing of a directory on a server that stopped responding.
+///
+/// # Arguments
+///
+/// * `id` - The id that was passed to the command as `operation_id`.
+/// * `operations` - The operation manager from the Tauri managed state.
+///
+/// # Returns
+///
+/// `true` if the operation was running, otherwise `false`.
+#[tauri::command]
+pub fn cancel_operation(id: String, operations: State<OperationManager>) -> bool {
+	operations.cancel(&id)
+}
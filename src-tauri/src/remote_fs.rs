@@ -0,0 +1,404 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::app_paths;
+use crate::connection_manager::{Connection, Protocol};
+use crate::error::BackendError;
+use crate::session_manager::SessionManager;
+use crate::sftp_manager::File;
+
+/// File system operations that every remote protocol backend has to provide.
+///
+/// Commands in `sftp_manager` only talk to this trait, so adding a new protocol means adding a new
+/// implementation and a branch in `with_remote_fs`, not touching every command.
+pub trait RemoteFs {
+	/// Lists the entries of a directory, `.` and `..` are never included.
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the directory to list.
+	/// * `show_hidden` - Whether or not to include entries starting with a `.`.
+	fn list(&mut self, path: &str, show_hidden: bool) -> Result<Vec<File>, BackendError>;
+
+	/// Returns the metadata of a single file or directory, following symbolic links.
+	fn stat(&mut self, path: &str) -> Result<File, BackendError>;
+
+	/// Returns the metadata of a single file or directory without following a symbolic link, so a link is
+	/// reported with `is_symlink` set instead of as the file it points to.
+	fn lstat(&mut self, path: &str) -> Result<File, BackendError>;
+
+	/// Reads a remote file and writes its contents to `writer`.
+	///
+	/// # Returns
+	///
+	/// The number of bytes that were read.
+	fn read(&mut self, path: &str, writer: &mut dyn Write) -> Result<u64, BackendError>;
+
+	/// Creates or truncates a remote file and fills it with everything read from `reader`.
+	///
+	/// # Returns
+	///
+	/// The number of bytes that were written.
+	fn write(&mut self, path: &str, reader: &mut dyn Read) -> Result<u64, BackendError>;
+
+	/// Renames or moves a remote file or directory.
+	fn rename(&mut self, from: &str, to: &str) -> Result<(), BackendError>;
+
+	/// Removes a remote file or an empty directory.
+	fn remove(&mut self, path: &str) -> Result<(), BackendError>;
+
+	/// Creates a remote directory.
+	fn mkdir(&mut self, path: &str) -> Result<(), BackendError>;
+
+	/// Returns a handle to the socket of the session, shutting it down interrupts an operation that is blocked on it.
+	fn socket(&self) -> Option<TcpStream>;
+
+	/// Checks whether `read_range` and `write_at` can start in the middle of a file, which resuming transfers needs.
+	fn can_seek(&self) -> bool {
+		false
+	}
+
+	/// Reads a remote file from `offset` on and writes it to `writer`.
+	///
+	/// The default implementation can only read whole files, backends that can seek should override it
+	/// together with `can_seek`.
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the remote file.
+	/// * `offset` - The position to start reading at.
+	/// * `length` - How many bytes to read at most, up to the end of the file if `None`.
+	/// * `writer` - Where to write the contents to.
+	///
+	/// # Returns
+	///
+	/// The number of bytes that were read.
+	fn read_range(&mut self, path: &str, offset: u64, length: Option<u64>, writer: &mut dyn Write) -> Result<u64, BackendError> {
+		if offset != 0 || length.is_some() {
+			return Err(BackendError::invalid_input("Reading part of a file is not supported by this protocol"));
+		}
+		self.read(path, writer)
+	}
+
+	/// Writes everything read from `reader` into a remote file starting at `offset`, the contents before it are kept.
+	///
+	/// An offset of `0` creates or truncates the file like `write`. The default implementation can only do that,
+	/// backends that can seek should override it together with `can_seek`.
+	///
+	/// # Returns
+	///
+	/// The number of bytes that were written.
+	fn write_at(&mut self, path: &str, offset: u64, reader: &mut dyn Read) -> Result<u64, BackendError> {
+		if offset != 0 {
+			return Err(BackendError::invalid_input("Writing part of a file is not supported by this protocol"));
+		}
+		self.write(path, reader)
+	}
+
+	/// Computes the SHA-256 checksum of the first `length` bytes of a remote file on the server.
+	///
+	/// # Returns
+	///
+	/// The checksum in lowercase hex, or `None` if the server can not compute it, e.g. because it can not run commands.
+	fn checksum(&mut self, _path: &str, _length: u64) -> Option<String> {
+		None
+	}
+
+	/// Copies a single remote file to another remote path.
+	///
+	/// The default implementation spools the file to a temporary file in the cache directory, as a single control
+	/// connection can not read and write at the same time. Backends that can stream from one remote file into
+	/// another should override it.
+	fn copy_file(&mut self, from: &str, to: &str) -> Result<(), BackendError> {
+		let mut spool = SpoolFile::create()?;
+		self.read(from, &mut spool.file)?;
+		spool.file.seek(SeekFrom::Start(0)).map_err(|e| BackendError::io("Error rewinding temporary file", &e))?;
+		self.write(to, &mut spool.file).map(|_| ())
+	}
+
+	/// Creates a symbolic link at `to` that points to the same target as the symbolic link at `from`.
+	///
+	/// The default implementation fails, backends that can read and create links should override it.
+	fn copy_symlink(&mut self, from: &str, _to: &str) -> Result<(), BackendError> {
+		Err(BackendError::invalid_input(format!("{} is a symbolic link, copying links is not supported by this protocol", from)))
+	}
+}
+
+/// A temporary file that is removed again when it is dropped, used by the default `RemoteFs::copy_file`.
+struct SpoolFile {
+	path: PathBuf,
+	file: fs::File,
+}
+
+impl SpoolFile {
+	fn create() -> Result<Self, BackendError> {
+		// Several copies can run at once, so every spool file gets its own name
+		static COUNTER: AtomicU64 = AtomicU64::new(0);
+		let name = format!("{}-{}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+		let path = app_paths::cache_directory("copies")
+			.map_err(|e| BackendError::io("Error creating cache directory", &e))?
+			.join(name);
+
+		let file = app_paths::private_file_options().read(true).open(&path).map_err(|e| BackendError::io("Error creating temporary file", &e))?;
+		Ok(SpoolFile { path, file })
+	}
+}
+
+impl Drop for SpoolFile {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+/// Runs an operation on the remote file system of a connection, picking the backend from its protocol.
+///
+/// # Arguments
+///
+/// * `options` - The connection to run the operation on.
+/// * `sessions` - The session manager holding the pooled sessions.
+/// * `operation` - The operation to run against the backend.
+///
+/// # Returns
+///
+/// The result of the operation, or an error if the protocol is unknown or not supported.
+pub fn with_remote_fs<T, F>(options: &Connection, sessions: &SessionManager, operation: F) -> Result<T, BackendError>
+where
+	F: FnOnce(&mut dyn RemoteFs) -> Result<T, BackendError>,
+{
+	match Protocol::try_from(options.protocol)? {
+		Protocol::Sftp => sessions.with_session(options, |instance| operation(instance)),
+		Protocol::Ftp | Protocol::Ftps => sessions.with_ftp_session(options, |instance| operation(instance)),
+	}
+}
+
+/// Removes a remote file, or a remote directory including everything inside it.
+///
+/// # Arguments
+///
+/// * `fs` - The backend to remove the path with.
+/// * `path` - The remote path to remove.
+pub fn remove_recursive(fs: &mut dyn RemoteFs, path: &str) -> Result<(), BackendError> {
+	// A link to a directory is removed as a link, the directory it points to is left alone
+	let file = fs.lstat(path)?;
+	if file.is_dir && !file.is_symlink {
+		// Empty the directory first, hidden entries have to go as well
+		for entry in fs.list(path, true)? {
+			remove_recursive(fs, &entry.path)?;
+		}
+	}
+	fs.remove(path)
+}
+
+/// Copies a remote file, or a remote directory including everything inside it, to another remote path.
+///
+/// # Arguments
+///
+/// * `fs` - The backend to copy with.
+/// * `from` - The remote path to copy.
+/// * `to` - The remote path to create.
+pub fn copy_recursive(fs: &mut dyn RemoteFs, from: &str, to: &str) -> Result<(), BackendError> {
+	// Links are copied as links, following them could copy the same tree over and over
+	let file = fs.lstat(from)?;
+	if file.is_symlink {
+		return fs.copy_symlink(from, to);
+	}
+	if !file.is_dir {
+		return fs.copy_file(from, to);
+	}
+	fs.mkdir(to)?;
+	for entry in fs.list(from, true)? {
+		copy_recursive(fs, &entry.path, &join_remote(to, &entry.filename))?;
+	}
+	Ok(())
+}
+
+/// Returns the last component of a remote path.
+pub fn remote_filename(path: &str) -> &str {
+	path.trim_end_matches('/').rsplit('/').next().unwrap_or(path)
+}
+
+/// Returns the parent directory of a remote path.
+pub fn remote_parent(path: &str) -> &str {
+	match path.trim_end_matches('/').rfind('/') {
+		Some(0) => "/",
+		Some(index) => &path[..index],
+		None => "",
+	}
+}
+
+/// Joins a remote directory and an entry name.
+///
+/// Remote paths always use `/` as separator, so `Path::join` can not be used as it would produce
+/// `\` separators on Windows.
+pub fn join_remote(directory: &str, name: &str) -> String {
+	if directory.is_empty() {
+		name.to_string()
+	} else if directory.ends_with('/') {
+		format!("{}{}", directory, name)
+	} else {
+		format!("{}/{}", directory, name)
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::collections::BTreeMap;
+
+	use super::*;
+
+	/// An entry of `MemoryFs`, a link stores the path it points to.
+	#[derive(Clone)]
+	pub(crate) enum Entry {
+		File(Vec<u8>),
+		Directory,
+		Link(String),
+	}
+
+	/// An in-memory backend that only implements what the trait requires, so the default methods are tested.
+	#[derive(Default)]
+	pub(crate) struct MemoryFs {
+		entries: BTreeMap<String, Entry>,
+	}
+
+	impl MemoryFs {
+		pub(crate) fn with(entries: &[(&str, Entry)]) -> Self {
+			MemoryFs { entries: entries.iter().map(|(path, entry)| (path.to_string(), entry.clone())).collect() }
+		}
+
+		fn file(&self, path: &str, entry: &Entry) -> File {
+			File {
+				path: path.to_string(),
+				filename: remote_filename(path).to_string(),
+				is_dir: matches!(entry, Entry::Directory),
+				is_symlink: matches!(entry, Entry::Link(_)),
+				size: match entry {
+					Entry::File(contents) => contents.len() as u64,
+					_ => 0,
+				},
+				modified: 0,
+				access: 0,
+				permissions: 0,
+				owner: 0,
+				group: 0,
+			}
+		}
+
+		fn entry(&self, path: &str) -> Result<&Entry, BackendError> {
+			self.entries.get(path).ok_or_else(|| BackendError::not_found(format!("No such file: {}", path)))
+		}
+
+		/// Follows links like a server does for everything but `lstat` and `remove`.
+		fn resolve(&self, path: &str) -> Result<(String, &Entry), BackendError> {
+			match self.entry(path)? {
+				Entry::Link(target) => self.resolve(target),
+				entry => Ok((path.to_string(), entry)),
+			}
+		}
+	}
+
+	impl RemoteFs for MemoryFs {
+		fn list(&mut self, path: &str, _show_hidden: bool) -> Result<Vec<File>, BackendError> {
+			let (directory, _) = self.resolve(path)?;
+			Ok(self.entries.iter()
+				.filter(|(entry_path, _)| remote_parent(entry_path) == directory && **entry_path != directory)
+				.map(|(entry_path, entry)| {
+					let mut file = self.file(entry_path, entry);
+					file.path = join_remote(path, &file.filename);
+					file
+				})
+				.collect())
+		}
+
+		fn stat(&mut self, path: &str) -> Result<File, BackendError> {
+			let (_, entry) = self.resolve(path)?;
+			Ok(self.file(path, entry))
+		}
+
+		fn lstat(&mut self, path: &str) -> Result<File, BackendError> {
+			Ok(self.file(path, self.entry(path)?))
+		}
+
+		fn read(&mut self, path: &str, writer: &mut dyn Write) -> Result<u64, BackendError> {
+			match self.resolve(path)? {
+				(_, Entry::File(contents)) => writer.write_all(contents).map(|_| contents.len() as u64).map_err(|e| BackendError::io("Error writing", &e)),
+				_ => Err(BackendError::invalid_input(format!("{} is not a file", path))),
+			}
+		}
+
+		fn write(&mut self, path: &str, reader: &mut dyn Read) -> Result<u64, BackendError> {
+			let mut contents = Vec::new();
+			reader.read_to_end(&mut contents).map_err(|e| BackendError::io("Error reading", &e))?;
+			let length = contents.len() as u64;
+			self.entries.insert(path.to_string(), Entry::File(contents));
+			Ok(length)
+		}
+
+		fn rename(&mut self, from: &str, to: &str) -> Result<(), BackendError> {
+			// Move the entry and everything below it
+			let prefix = format!("{}/", from);
+			let moved: Vec<String> = self.entries.keys().filter(|path| *path == from || path.starts_with(&prefix)).cloned().collect();
+			if moved.is_empty() {
+				return Err(BackendError::not_found(format!("No such file: {}", from)));
+			}
+			for path in moved {
+				if let Some(entry) = self.entries.remove(&path) {
+					self.entries.insert(format!("{}{}", to, &path[from.len()..]), entry);
+				}
+			}
+			Ok(())
+		}
+
+		fn remove(&mut self, path: &str) -> Result<(), BackendError> {
+			if self.entries.keys().any(|entry_path| remote_parent(entry_path) == path && entry_path != path) {
+				return Err(BackendError::invalid_input(format!("{} is not empty", path)));
+			}
+			self.entries.remove(path).map(|_| ()).ok_or_else(|| BackendError::not_found(format!("No such file: {}", path)))
+		}
+
+		fn mkdir(&mut self, path: &str) -> Result<(), BackendError> {
+			self.entries.insert(path.to_string(), Entry::Directory);
+			Ok(())
+		}
+
+		fn socket(&self) -> Option<TcpStream> {
+			None
+		}
+	}
+
+	#[test]
+	fn remove_recursive_keeps_link_targets() {
+		let mut fs = MemoryFs::with(&[
+			("/data", Entry::Directory),
+			("/data/keep.txt", Entry::File(b"keep".to_vec())),
+			("/trash", Entry::Directory),
+			("/trash/link", Entry::Link("/data".to_string())),
+		]);
+		remove_recursive(&mut fs, "/trash").unwrap();
+
+		assert!(!fs.entries.contains_key("/trash"));
+		assert!(fs.entries.contains_key("/data/keep.txt"));
+	}
+
+	#[test]
+	fn copy_recursive_does_not_follow_links() {
+		// A link back up the tree would be copied forever if it was followed
+		let mut fs = MemoryFs::with(&[
+			("/tree", Entry::Directory),
+			("/tree/file.txt", Entry::File(b"contents".to_vec())),
+			("/tree/loop", Entry::Link("/tree".to_string())),
+		]);
+		let error = copy_recursive(&mut fs, "/tree", "/copy").unwrap_err();
+		assert!(matches!(error, BackendError::InvalidInput { .. }));
+		assert!(!fs.entries.contains_key("/copy/loop"));
+	}
+
+	#[test]
+	fn copy_file_spools_through_a_temporary_file() {
+		let mut fs = MemoryFs::with(&[("/a.txt", Entry::File(b"contents".to_vec()))]);
+		fs.copy_file("/a.txt", "/b.txt").unwrap();
+		assert!(matches!(fs.entries.get("/b.txt"), Some(Entry::File(contents)) if contents == b"contents"));
+	}
+}
@@ -0,0 +1,141 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::BackendError;
+use crate::logger;
+use crate::remote_fs::RemoteFs;
+
+/// How many bytes at the end of a partial file are compared by `ResumeCheck::Tail`.
+const TAIL_SIZE: u64 = 64 * 1024;
+
+/// How a partial file is validated before a transfer continues it.
+///
+/// A partial file that fails the check, or is longer than the complete file, is transferred again from the start.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResumeCheck {
+	/// Trust the partial file, only its length is compared.
+	Length,
+	/// Compare the last 64 KiB of the partial file with the same range of the complete file.
+	Tail,
+	/// Compare the SHA-256 checksums of the partial file and the same range of the complete file, computed with
+	/// `sha256sum` on the server. Falls back to `Tail` if the server can not run commands.
+	Checksum,
+}
+
+/// Finds out where a download can continue a partial local file.
+///
+/// # Arguments
+///
+/// * `fs` - The backend to read the remote file with.
+/// * `remote_path` - The remote file that is downloaded.
+/// * `local_path` - The local file that is downloaded to.
+/// * `check` - How to validate the partial local file.
+///
+/// # Returns
+///
+/// The length of the partial local file if the download can continue after it, otherwise `0`.
+pub fn download_offset(fs: &mut dyn RemoteFs, remote_path: &str, local_path: &str, check: ResumeCheck) -> Result<u64, BackendError> {
+	let partial = match fs::metadata(local_path) {
+		Ok(metadata) if metadata.is_file() => metadata.len(),
+		_ => return Ok(0),
+	};
+	let complete = fs.stat(remote_path)?.size;
+	resume_offset(fs, remote_path, local_path, partial, complete, check)
+}
+
+/// Finds out where an upload can continue a partial remote file.
+///
+/// # Arguments
+///
+/// * `fs` - The backend to read the remote file with.
+/// * `local_path` - The local file that is uploaded.
+/// * `remote_path` - The remote file that is uploaded to.
+/// * `check` - How to validate the partial remote file.
+///
+/// # Returns
+///
+/// The length of the partial remote file if the upload can continue after it, otherwise `0`.
+pub fn upload_offset(fs: &mut dyn RemoteFs, local_path: &str, remote_path: &str, check: ResumeCheck) -> Result<u64, BackendError> {
+	let partial = match fs.stat(remote_path) {
+		Ok(file) if !file.is_dir => file.size,
+		_ => return Ok(0),
+	};
+	let complete = fs::metadata(local_path).map_err(|e| BackendError::io(&format!("Error reading {}", local_path), &e))?.len();
+	resume_offset(fs, remote_path, local_path, partial, complete, check)
+}
+
+/// Validates a partial file against the start of the complete one, the shared part of `download_offset` and `upload_offset`.
+fn resume_offset(fs: &mut dyn RemoteFs, remote_path: &str, local_path: &str, partial: u64, complete: u64, check: ResumeCheck) -> Result<u64, BackendError> {
+	// Backends that can not seek always start over
+	if partial == 0 || partial > complete || !fs.can_seek() {
+		return Ok(0);
+	}
+
+	let valid = match check {
+		ResumeCheck::Length => true,
+		ResumeCheck::Tail => tail_matches(fs, remote_path, local_path, partial)?,
+		ResumeCheck::Checksum => match fs.checksum(remote_path, partial) {
+			Some(remote) => remote == local_checksum(local_path, partial)?,
+			None => tail_matches(fs, remote_path, local_path, partial)?,
+		},
+	};
+	if !valid {
+		let _ = logger::log(&format!("The partial file of {} does not match, transferring it again", local_path), "", logger::LOG_INFO);
+		return Ok(0);
+	}
+	Ok(partial)
+}
+
+/// Compares the last `TAIL_SIZE` bytes before `length` of the local and the remote file.
+fn tail_matches(fs: &mut dyn RemoteFs, remote_path: &str, local_path: &str, length: u64) -> Result<bool, BackendError> {
+	let start = length.saturating_sub(TAIL_SIZE);
+
+	let mut remote = Vec::new();
+	fs.read_range(remote_path, start, Some(length - start), &mut remote)?;
+
+	let mut local = Vec::new();
+	let mut local_file = fs::File::open(local_path).map_err(|e| BackendError::io(&format!("Error opening {}", local_path), &e))?;
+	local_file.seek(SeekFrom::Start(start)).map_err(|e| BackendError::io(&format!("Error seeking {}", local_path), &e))?;
+	local_file.take(length - start).read_to_end(&mut local).map_err(|e| BackendError::io(&format!("Error reading {}", local_path), &e))?;
+
+	Ok(remote == local)
+}
+
+/// Computes the SHA-256 checksum of the first `length` bytes of a local file, in lowercase hex like `sha256sum`.
+fn local_checksum(local_path: &str, length: u64) -> Result<String, BackendError> {
+	let local_file = fs::File::open(local_path).map_err(|e| BackendError::io(&format!("Error opening {}", local_path), &e))?;
+	let mut hasher = Sha256::new();
+	io::copy(&mut local_file.take(length), &mut hasher).map_err(|e| BackendError::io(&format!("Error reading {}", local_path), &e))?;
+	Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Opens the local file a download writes to, keeping the first `offset` bytes and dropping anything after them.
+///
+/// # Returns
+///
+/// The file, positioned at `offset`.
+pub fn create_local_at(local_path: &str, offset: u64) -> Result<fs::File, BackendError> {
+	let mut local_file = fs::OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(false)
+		.open(local_path)
+		.map_err(|e| BackendError::io(&format!("Error creating local file {}", local_path), &e))?;
+	local_file.set_len(offset).map_err(|e| BackendError::io(&format!("Error truncating local file {}", local_path), &e))?;
+	local_file.seek(SeekFrom::Start(offset)).map_err(|e| BackendError::io(&format!("Error seeking local file {}", local_path), &e))?;
+	Ok(local_file)
+}
+
+/// Opens the local file an upload reads from.
+///
+/// # Returns
+///
+/// The file, positioned at `offset`.
+pub fn open_local_at(local_path: &str, offset: u64) -> Result<fs::File, BackendError> {
+	let mut local_file = fs::File::open(local_path).map_err(|e| BackendError::io(&format!("Error opening local file {}", local_path), &e))?;
+	local_file.seek(SeekFrom::Start(offset)).map_err(|e| BackendError::io(&format!("Error seeking local file {}", local_path), &e))?;
+	Ok(local_file)
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::connection_manager::{Connection, Protocol};
+use crate::error::BackendError;
+use crate::ftp_instance::FTPInstance;
+use crate::operations::run_blocking;
+use crate::ssh_instance::SSHInstance;
+
+/// A protocol session that can be kept open in the session pool.
+pub trait PooledConnection: Sized + Send + 'static {
+	/// Opens and authenticates a new session.
+	fn connect(options: &Connection) -> Result<Self, BackendError>;
+
+	/// Checks whether the session can still be used.
+	fn is_alive(&mut self) -> bool;
+
+	/// Ends the session, errors are ignored.
+	fn disconnect(&mut self);
+}
+
+impl PooledConnection for SSHInstance {
+	fn connect(options: &Connection) -> Result<Self, BackendError> {
+		SSHInstance::connect(options.clone())
+	}
+
+	fn is_alive(&mut self) -> bool {
+		SSHInstance::is_alive(self)
+	}
+
+	fn disconnect(&mut self) {
+		// Politely tell the server we are leaving, the socket is closed once the session is dropped
+		let _ = self.session.disconnect(None, "Closing session", None);
+	}
+}
+
+impl PooledConnection for FTPInstance {
+	fn connect(options: &Connection) -> Result<Self, BackendError> {
+		FTPInstance::connect(options)
+	}
+
+	fn is_alive(&mut self) -> bool {
+		FTPInstance::is_alive(self)
+	}
+
+	fn disconnect(&mut self) {
+		FTPInstance::disconnect(self)
+	}
+}
+
+/// How many sessions are opened to the same connection at most, so several panes can work on one server at once.
+const MAX_SESSIONS_PER_CONNECTION: usize = 4;
+
+/// A pooled session together with the options that were used to open it.
+struct PooledSession<T> {
+	options: Connection,
+	instance: Arc<Mutex<T>>,
+}
+
+/// Sessions of a single protocol, keyed by `Connection.id`.
+struct Pool<T> {
+	sessions: Mutex<HashMap<i32, Vec<PooledSession<T>>>>,
+}
+
+impl<T: PooledConnection> Pool<T> {
+	fn new() -> Self {
+		Pool {
+			sessions: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Returns the pooled sessions of a connection, dropping those that were opened with different connection details.
+	///
+	/// Only the pool itself is locked here, whether a session still responds is checked once it was claimed,
+	/// so a server that stopped responding never holds up the sessions of other connections.
+	fn sessions_of(&self, options: &Connection) -> Result<Vec<Arc<Mutex<T>>>, BackendError> {
+		let mut sessions = self.sessions.lock().map_err(|e| BackendError::other(format!("Session pool is poisoned: {:?}", e)))?;
+		let pooled = sessions.entry(options.id).or_default();
+		pooled.retain(|pooled| same_target(&pooled.options, options));
+		Ok(pooled.iter().map(|pooled| pooled.instance.clone()).collect())
+	}
+
+	/// Runs an operation on a pooled session of a connection.
+	///
+	/// An idle session is claimed by locking it, so no other caller can pick the same one, and only used
+	/// if it still responds. If all sessions are busy another one is connected, once the connection has
+	/// `MAX_SESSIONS_PER_CONNECTION` sessions the caller waits for a busy one instead. Callers that connect
+	/// at the same time may briefly open a session more than that.
+	///
+	/// If the operation fails and the session turns out to be dead, it is removed from the pool so
+	/// that the next call reconnects.
+	fn with_session<R, F>(&self, options: &Connection, operation: F) -> Result<R, BackendError>
+	where
+		F: FnOnce(&mut T) -> Result<R, BackendError>,
+	{
+		let pooled = self.sessions_of(options)?;
+
+		let mut busy = Vec::new();
+		for instance in &pooled {
+			match instance.try_lock() {
+				Ok(mut guard) => {
+					if guard.is_alive() {
+						return self.run(options.id, instance, guard, operation);
+					}
+					guard.disconnect();
+					drop(guard);
+					self.discard(options.id, instance);
+				}
+				Err(TryLockError::WouldBlock) => busy.push(instance),
+				Err(TryLockError::Poisoned(_)) => self.discard(options.id, instance),
+			}
+		}
+
+		// The connection may not open more sessions, wait for a busy one
+		if let Some(instance) = busy.first().filter(|_| busy.len() >= MAX_SESSIONS_PER_CONNECTION) {
+			let mut guard = instance.lock().map_err(|e| BackendError::other(format!("Session is poisoned: {:?}", e)))?;
+			if guard.is_alive() {
+				return self.run(options.id, instance, guard, operation);
+			}
+			guard.disconnect();
+			drop(guard);
+			self.discard(options.id, instance);
+		}
+
+		// Connect without holding the pool, so other connections are not blocked by a slow server,
+		// and claim the new session before it is added so nobody else takes it
+		let instance = Arc::new(Mutex::new(T::connect(options)?));
+		let guard = instance.lock().map_err(|e| BackendError::other(format!("Session is poisoned: {:?}", e)))?;
+		self.sessions.lock().map_err(|e| BackendError::other(format!("Session pool is poisoned: {:?}", e)))?
+			.entry(options.id)
+			.or_default()
+			.push(PooledSession {
+				options: options.clone(),
+				instance: instance.clone(),
+			});
+		self.run(options.id, &instance, guard, operation)
+	}
+
+	/// Runs an operation on a claimed session, removing the session from the pool if it died.
+	fn run<R, F>(&self, id: i32, instance: &Arc<Mutex<T>>, mut guard: MutexGuard<'_, T>, operation: F) -> Result<R, BackendError>
+	where
+		F: FnOnce(&mut T) -> Result<R, BackendError>,
+	{
+		let result = operation(&mut guard);
+		if result.is_err() && !guard.is_alive() {
+			guard.disconnect();
+			drop(guard);
+			self.discard(id, instance);
+		}
+		result
+	}
+
+	/// Removes a single session from the pool, the other sessions of the connection stay open.
+	fn discard(&self, id: i32, instance: &Arc<Mutex<T>>) {
+		if let Ok(mut sessions) = self.sessions.lock() {
+			if let Some(pooled) = sessions.get_mut(&id) {
+				pooled.retain(|pooled| !Arc::ptr_eq(&pooled.instance, instance));
+			}
+		}
+	}
+
+	/// Removes the sessions of a connection from the pool and disconnects them.
+	fn close(&self, id: i32) -> bool {
+		let pooled = match self.sessions.lock() {
+			Ok(mut sessions) => sessions.remove(&id).unwrap_or_default(),
+			Err(_) => Vec::new(),
+		};
+		for session in &pooled {
+			if let Ok(mut instance) = session.instance.lock() {
+				instance.disconnect();
+			}
+		}
+		!pooled.is_empty()
+	}
+}
+
+/// Keeps authenticated sessions open per connection, so commands do not have to reconnect.
+///
+/// A connection gets another session while all of its sessions are busy, up to `MAX_SESSIONS_PER_CONNECTION`.
+/// SSH sessions also cache their SFTP handle. The manager is registered as Tauri managed state.
+pub struct SessionManager {
+	ssh: Pool<SSHInstance>,
+	ftp: Pool<FTPInstance>,
+}
+
+impl Default for SessionManager {
+	fn default() -> Self {
+		SessionManager {
+			ssh: Pool::new(),
+			ftp: Pool::new(),
+		}
+	}
+}
+
+impl SessionManager {
+	/// Creates an empty session manager.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Runs an operation on an idle pooled SSH session of a connection, connecting first if needed.
+	///
+	/// # Arguments
+	///
+	/// * `options` - The connection to run the operation on.
+	/// * `operation` - The operation to run with exclusive access to the `SSHInstance`.
+	pub fn with_session<R, F>(&self, options: &Connection, operation: F) -> Result<R, BackendError>
+	where
+		F: FnOnce(&mut SSHInstance) -> Result<R, BackendError>,
+	{
+		self.ssh.with_session(options, operation)
+	}
+
+	/// Runs an operation on an idle pooled FTP session of a connection, connecting first if needed.
+	///
+	/// # Arguments
+	///
+	/// * `options` - The connection to run the operation on.
+	/// * `operation` - The operation to run with exclusive access to the `FTPInstance`.
+	pub fn with_ftp_session<R, F>(&self, options: &Connection, operation: F) -> Result<R, BackendError>
+	where
+		F: FnOnce(&mut FTPInstance) -> Result<R, BackendError>,
+	{
+		self.ftp.with_session(options, operation)
+	}
+
+	/// Opens the pooled session of a connection if it is not open yet.
+	pub fn open(&self, options: &Connection) -> Result<(), BackendError> {
+		match Protocol::try_from(options.protocol)? {
+			Protocol::Sftp => self.ssh.with_session(options, |_| Ok(())),
+			Protocol::Ftp | Protocol::Ftps => self.ftp.with_session(options, |_| Ok(())),
+		}
+	}
+
+	/// Removes the sessions of a connection from the pool and disconnects them.
+	///
+	/// # Returns
+	///
+	/// `true` if a session was open for the connection, otherwise `false`.
+	pub fn close(&self, id: i32) -> bool {
+		let ssh = self.ssh.close(id);
+		let ftp = self.ftp.close(id);
+		ssh || ftp
+	}
+}
+
+/// Checks whether two connections point at the same server with the same credentials.
+pub(crate) fn same_target(a: &Connection, b: &Connection) -> bool {
+	same_server(a, b) && a.password == b.password && a.private_key == b.private_key
+}
+
+/// Checks whether two connections point at the same server as the same user, the secrets are not compared.
+pub(crate) fn same_server(a: &Connection, b: &Connection) -> bool {
+	a.host == b.host
+		&& a.port == b.port
+		&& a.username == b.username
+		&& a.auth_method == b.auth_method
+		&& a.private_key_path == b.private_key_path
+		&& a.jump_host_id == b.jump_host_id
+		&& a.protocol == b.protocol
+}
+
+/// Opens (or reuses) a pooled session for the given connection.
+///
+/// The command runs on the blocking thread pool, so `auth-prompt` events can be answered while it waits for the server.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to reach the session manager.
+/// * `options` - The connection to open the session for.
+/// * `operation_id` - An id to cancel the command with, see `cancel_operation`.
+///
+/// # Returns
+///
+/// `Ok(())` if the session is open, otherwise the error that occurred.
+#[tauri::command]
+pub async fn open_session(app: AppHandle, options: Connection, operation_id: Option<String>) -> Result<(), BackendError> {
+	run_blocking(app, operation_id, move |app, _| app.state::<SessionManager>().open(&options)).await
+}
+
+/// Closes the pooled session of a connection.
+///
+/// # Arguments
+///
+/// * `id` - The id of the connection whose session should be closed.
+/// * `sessions` - The session manager from the Tauri managed state.
+///
+/// # Returns
+///
+/// `true` if a session was closed, `false` if there was no open session for the connection.
+#[tauri::command]
+pub fn close_session(id: i32, sessions: State<SessionManager>) -> bool {
+	sessions.close(id)
+}
@@ -1,11 +1,22 @@
-use crate::connection_manager::Connection;
-use crate::ssh_instance::SSHInstance;
+use tauri::{AppHandle, Manager};
+
+use crate::connection_manager::{Connection, Protocol};
+use crate::error::BackendError;
+use crate::ftp_instance::FTPInstance;
+use crate::operations::{run_blocking, Operation};
+use crate::remote_fs::{copy_recursive, join_remote, remote_filename, remote_parent, remove_recursive, with_remote_fs, RemoteFs};
+use crate::resume::{self, ResumeCheck};
+use crate::session_manager::SessionManager;
+use crate::ssh_instance::{CommandResult, OutputStream, SSHInstance};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct File {
     pub path: String,
     pub filename: String,
     pub is_dir: bool,
+	// Only set by listings and `lstat`, `stat` follows links
+    #[serde(default)]
+    pub is_symlink: bool,
     pub size: u64,
     pub modified: u64,
     pub access: u64,
@@ -13,84 +24,197 @@ pub struct File {
     pub owner: u32,
     pub group: u32,
 }
-#[tauri::command()]
-pub fn test_connection(options: Connection) -> bool {
-    return match options.protocol {
-        0 => { // SFTP
-            return match SSHInstance::connect(options) {
-                Ok(instance) => {
-                    let mut session = instance.session;
-                    if let Err(e) = session.handshake() {
-                        println!("Error: {:?}", e);
-                    }
-
-                    if let Err(error) = session.sftp()
-                    {
-                        println!("Error: {:?}", error);
-                    }
-
-                    if let Some(banner) = session.banner()
-                    {
-                        println!("Banner: {:?}", banner);
-                    }
-                    session.authenticated()
-                }
-                Err(e) => {
-                    println!("Error: {:?}", e);
-                    false
-                }
-            }
+/// Connects and authenticates to a server without keeping the connection.
+///
+/// # Returns
+///
+/// `true` once the connection was established, otherwise the error that prevented it.
+#[tauri::command]
+pub async fn test_connection(app: AppHandle, options: Connection, operation_id: Option<String>) -> Result<bool, BackendError> {
+    run_blocking(app, operation_id, move |_, operation| match Protocol::try_from(options.protocol)? {
+        Protocol::Sftp => {
+            let mut instance = SSHInstance::connect(options)?;
+            operation.attach(instance.socket());
+			// Authenticating is not enough, the server also has to offer the SFTP subsystem
+            instance.sftp()?;
+            Ok(instance.session.authenticated())
         }
-        1 => { // FTP
-            false
+        Protocol::Ftp | Protocol::Ftps => {
+            let mut instance = FTPInstance::connect(&options)?;
+            instance.disconnect();
+            Ok(true)
         }
-        _ => {
-            println!("Unknown protocol: {}", options.protocol);
-            return false;
-        }
-    };
+    }).await
 }
-#[tauri::command()]
-pub fn list(path: &str, show_hidden: bool, options: Connection) -> Result<Vec<File>, String> {
-    match options.protocol {
-        0 => {
-            SSHInstance::list_dir(path, show_hidden, options)
-        }
-        1 => {
-            Err("FTP not implemented".to_string())
+
+/// Runs a file system operation of a command on the blocking thread pool, with a pooled session of the connection.
+///
+/// The socket of the session is attached to the operation, so cancelling it interrupts a server that stopped responding.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to reach the session manager.
+/// * `options` - The connection to run the operation on.
+/// * `operation_id` - An id to cancel the operation with, see `cancel_operation`.
+/// * `task` - The operation to run.
+async fn run_remote<T, F>(app: AppHandle, options: Connection, operation_id: Option<String>, task: F) -> Result<T, BackendError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn RemoteFs, &Operation) -> Result<T, BackendError> + Send + 'static,
+{
+    run_blocking(app, operation_id, move |app, operation| {
+        with_remote_fs(&options, &app.state::<SessionManager>(), |fs| {
+            operation.attach(fs.socket());
+            task(fs, operation)
+        })
+    }).await
+}
+
+#[tauri::command]
+pub async fn list(app: AppHandle, path: String, show_hidden: bool, options: Connection, operation_id: Option<String>) -> Result<Vec<File>, BackendError> {
+    run_remote(app, options, operation_id, move |fs, _| fs.list(&path, show_hidden)).await
+}
+
+/// A chunk of command output, emitted as the `command-output` event when a command is streamed.
+#[derive(Clone, serde::Serialize)]
+pub struct CommandOutput {
+    stream_id: String,
+    stream: OutputStream,
+    data: String,
+}
+
+#[tauri::command]
+pub async fn send_ssh_command(app: AppHandle, command: String, options: Connection, stream_id: Option<String>, operation_id: Option<String>) -> Result<CommandResult, BackendError> {
+    match Protocol::try_from(options.protocol)? {
+        Protocol::Sftp => {
+            run_blocking(app, operation_id, move |app, operation| {
+                app.state::<SessionManager>().with_session(&options, |instance| {
+                    operation.attach(instance.socket());
+                    instance.send_ssh_command(&command, |stream, data| {
+                        // Only stream the output if the frontend asked for it
+                        if let Some(stream_id) = &stream_id {
+                            let _ = app.emit_all("command-output", CommandOutput {
+                                stream_id: stream_id.clone(),
+                                stream,
+                                data: String::from_utf8_lossy(data).to_string(),
+                            });
+                        }
+                    })
+                })
+            }).await
         }
-        _ => {
-            Err("Unknown protocol".to_string())
+        Protocol::Ftp | Protocol::Ftps => {
+            Err(BackendError::invalid_input("SSH commands are not supported over FTP"))
         }
     }
 }
 
-#[tauri::command()]
-pub fn send_ssh_command(command: &str, options: Connection) -> Result<String, String> {
-    match options.protocol {
-        0 => {
-            SSHInstance::send_ssh_command(command, options)
-        }
-        1 => {
-            Err("FTP not implemented".to_string())
+/// Downloads a remote file to a local path.
+///
+/// With `resume` set, a partial local file left behind by an interrupted download is continued instead of
+/// downloaded again, if it passes the given check. Without it an existing local file is overwritten.
+#[tauri::command]
+pub async fn download_file(app: AppHandle, remote_path: String, local_path: String, options: Connection, resume: Option<ResumeCheck>, operation_id: Option<String>) -> Result<(), BackendError> {
+    run_remote(app, options, operation_id, move |fs, operation| {
+        let offset = match resume {
+            Some(check) => resume::download_offset(fs, &remote_path, &local_path, check)?,
+            None => 0,
+        };
+        // Open the local file after the part that is kept and stream the rest of the remote file into it, stopping once cancelled
+        let local_file = resume::create_local_at(&local_path, offset)?;
+        fs.read_range(&remote_path, offset, None, &mut operation.guard(local_file)).map(|_| ())
+    }).await
+}
+
+/// Uploads a local file to a remote path.
+///
+/// With `resume` set, a partial remote file left behind by an interrupted upload is continued instead of
+/// uploaded again, if it passes the given check. Without it an existing remote file is overwritten.
+#[tauri::command]
+pub async fn upload_file(app: AppHandle, local_path: String, remote_path: String, preserve_permissions: Option<bool>, options: Connection, resume: Option<ResumeCheck>, operation_id: Option<String>) -> Result<(), BackendError> {
+    match Protocol::try_from(options.protocol)? {
+        Protocol::Sftp => {
+            run_blocking(app, operation_id, move |app, operation| {
+                app.state::<SessionManager>().with_session(&options, |instance| {
+                    operation.attach(instance.socket());
+                    let offset = match resume {
+                        Some(check) => resume::upload_offset(instance, &local_path, &remote_path, check)?,
+                        None => 0,
+                    };
+                    instance.upload_file(&local_path, &remote_path, preserve_permissions.unwrap_or(false), offset)
+                })
+            }).await
         }
-        _ => {
-            Err("Unknown protocol".to_string())
+        // FTP has no way to set timestamps or permissions, so only the contents are uploaded, and always from the start
+        Protocol::Ftp | Protocol::Ftps => {
+            run_remote(app, options, operation_id, move |fs, operation| {
+                let local_file = std::fs::File::open(&local_path).map_err(|e| BackendError::io("Error opening local file", &e))?;
+                fs.write(&remote_path, &mut operation.guard(local_file)).map(|_| ())
+            }).await
         }
     }
 }
 
-#[tauri::command()]
-pub fn download_file(remote_path: &str, local_path: &str, options: Connection) -> Result<(), String> {
-    match options.protocol {
-        0 => {
-            SSHInstance::download_file(remote_path, local_path, options)
+#[tauri::command]
+pub async fn create_directory(app: AppHandle, path: String, options: Connection, operation_id: Option<String>) -> Result<(), BackendError> {
+    run_remote(app, options, operation_id, move |fs, _| fs.mkdir(&path)).await
+}
+
+#[tauri::command]
+pub async fn create_file(app: AppHandle, path: String, options: Connection, operation_id: Option<String>) -> Result<(), BackendError> {
+    run_remote(app, options, operation_id, move |fs, _| {
+        // Never truncate an existing file
+        if fs.stat(&path).is_ok() {
+            return Err(BackendError::invalid_input(format!("{} already exists", path)));
         }
-        1 => {
-            Err("FTP not implemented".to_string())
+        fs.write(&path, &mut std::io::empty()).map(|_| ())
+    }).await
+}
+
+#[tauri::command]
+pub async fn rename_path(app: AppHandle, path: String, new_name: String, options: Connection, operation_id: Option<String>) -> Result<String, BackendError> {
+    // Renaming keeps the entry in its directory, only the last path component changes
+    let target = join_remote(remote_parent(&path), &new_name);
+    run_remote(app, options, operation_id, move |fs, _| fs.rename(&path, &target).map(|_| target)).await
+}
+
+#[tauri::command]
+pub async fn move_path(app: AppHandle, path: String, destination: String, options: Connection, operation_id: Option<String>) -> Result<String, BackendError> {
+    // Moving keeps the name and puts the entry into the destination directory
+    let target = join_remote(&destination, remote_filename(&path));
+    run_remote(app, options, operation_id, move |fs, _| fs.rename(&path, &target).map(|_| target)).await
+}
+
+#[tauri::command]
+pub async fn copy_path(app: AppHandle, path: String, destination: String, options: Connection, operation_id: Option<String>) -> Result<String, BackendError> {
+    let target = join_remote(&destination, remote_filename(&path));
+    match Protocol::try_from(options.protocol)? {
+        // SFTP connections try a server side copy first
+        Protocol::Sftp => {
+            run_blocking(app, operation_id, move |app, operation| {
+                app.state::<SessionManager>().with_session(&options, |instance| {
+                    operation.attach(instance.socket());
+                    if instance.stat(&target).is_ok() {
+                        return Err(BackendError::invalid_input(format!("{} already exists", target)));
+                    }
+                    instance.copy_path(&path, &target)?;
+                    Ok(target)
+                })
+            }).await
         }
-        _ => {
-            Err("Unknown protocol".to_string())
+        Protocol::Ftp | Protocol::Ftps => {
+            run_remote(app, options, operation_id, move |fs, _| {
+                if fs.stat(&target).is_ok() {
+                    return Err(BackendError::invalid_input(format!("{} already exists", target)));
+                }
+                copy_recursive(fs, &path, &target)?;
+                Ok(target)
+            }).await
         }
     }
 }
+
+#[tauri::command]
+pub async fn delete_path(app: AppHandle, path: String, options: Connection, operation_id: Option<String>) -> Result<(), BackendError> {
+    run_remote(app, options, operation_id, move |fs, _| remove_recursive(fs, &path)).await
+}
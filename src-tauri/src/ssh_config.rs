@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::connection_manager::AuthMethod;
+use crate::error::BackendError;
+use crate::importer::{self, ImportCandidate, ImportPreview};
+
+/// How deep `Include` directives are followed, guards against files including each other.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A `Host` block and the options set in it, in file order.
+struct HostBlock {
+	patterns: Vec<String>,
+	options: Vec<(String, String)>,
+}
+
+/// Returns the `~/.ssh` directory of the user.
+fn ssh_directory() -> Result<PathBuf, BackendError> {
+	tauri::api::path::home_dir().map(|home| home.join(".ssh")).ok_or_else(|| BackendError::not_found("Could not determine the home directory"))
+}
+
+/// Expands a leading `~` to the home directory of the user.
+fn expand_home(path: &str) -> String {
+	match (path.strip_prefix('~'), tauri::api::path::home_dir()) {
+		(Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') || rest.starts_with('\\') => {
+			format!("{}{}", home.to_string_lossy(), rest)
+		}
+		_ => path.to_string(),
+	}
+}
+
+/// Matches a host name against an OpenSSH pattern, `*` matches any sequence and `?` a single character.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+	let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+	let text: Vec<char> = text.to_lowercase().chars().collect();
+	let (mut p, mut t) = (0, 0);
+	// Position of the last `*` and the text position it was tried at, to backtrack to
+	let mut star: Option<(usize, usize)> = None;
+	while t < text.len() {
+		if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+			p += 1;
+			t += 1;
+		} else if p < pattern.len() && pattern[p] == '*' {
+			star = Some((p, t));
+			p += 1;
+		} else if let Some((star_p, star_t)) = star {
+			p = star_p + 1;
+			t = star_t + 1;
+			star = Some((star_p, star_t + 1));
+		} else {
+			return false;
+		}
+	}
+	pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Checks whether a host matches the patterns of a `Host` line, a matching negated pattern excludes it.
+fn host_matches(patterns: &[String], host: &str) -> bool {
+	let mut matched = false;
+	for pattern in patterns {
+		match pattern.strip_prefix('!') {
+			Some(negated) if wildcard_match(negated, host) => return false,
+			Some(_) => (),
+			None => matched |= wildcard_match(pattern, host),
+		}
+	}
+	matched
+}
+
+/// Splits a config line into its keyword and arguments, supporting `Key Value`, `Key=Value` and quoted arguments.
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+	let line = line.trim();
+	if line.is_empty() || line.starts_with('#') {
+		return None;
+	}
+	let split = line.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(line.len());
+	let keyword = line[..split].to_lowercase();
+	let rest = line[split..].trim_start().strip_prefix('=').unwrap_or(line[split..].trim_start());
+
+	let mut arguments = Vec::new();
+	let mut current = String::new();
+	let mut quoted = false;
+	for c in rest.chars() {
+		match c {
+			'"' => quoted = !quoted,
+			c if c.is_whitespace() && !quoted => {
+				if !current.is_empty() {
+					arguments.push(std::mem::take(&mut current));
+				}
+			}
+			c => current.push(c),
+		}
+	}
+	if !current.is_empty() {
+		arguments.push(current);
+	}
+	Some((keyword, arguments))
+}
+
+/// Resolves the files an `Include` argument refers to, a `*` or `?` in the file name is expanded.
+fn include_paths(argument: &str) -> Result<Vec<PathBuf>, BackendError> {
+	let expanded = expand_home(argument);
+	// Relative paths are relative to ~/.ssh, like for the user configuration of OpenSSH
+	let path = if Path::new(&expanded).is_absolute() { PathBuf::from(expanded) } else { ssh_directory()?.join(expanded) };
+
+	let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+	if !file_name.contains(['*', '?']) {
+		return Ok(vec![path]);
+	}
+	let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+	let mut paths: Vec<PathBuf> = match fs::read_dir(&directory) {
+		Ok(entries) => entries
+			.filter_map(|entry| entry.ok())
+			.filter(|entry| wildcard_match(&file_name, &entry.file_name().to_string_lossy()))
+			.map(|entry| entry.path())
+			.collect(),
+		Err(_) => Vec::new(),
+	};
+	// OpenSSH includes glob matches in lexical order
+	paths.sort();
+	Ok(paths)
+}
+
+/// Reads a config file and appends its `Host` blocks, following `Include` directives in place.
+///
+/// Options before the first `Host` line apply to every host, they are stored as a block with the pattern `*`.
+fn parse_file(path: &Path, blocks: &mut Vec<HostBlock>, depth: usize) -> Result<(), BackendError> {
+	if depth > MAX_INCLUDE_DEPTH {
+		return Err(BackendError::invalid_input(format!("Too many nested includes at {}", path.display())));
+	}
+	let content = fs::read_to_string(path).map_err(|e| BackendError::io(&format!("Error reading {}", path.display()), &e))?;
+	if blocks.is_empty() {
+		blocks.push(HostBlock { patterns: vec!["*".to_string()], options: Vec::new() });
+	}
+
+	for line in content.lines() {
+		let (keyword, arguments) = match split_line(line) {
+			Some(parsed) => parsed,
+			None => continue,
+		};
+		match keyword.as_str() {
+			"host" => blocks.push(HostBlock { patterns: arguments, options: Vec::new() }),
+			// Match blocks depend on the runtime environment, their options are skipped
+			"match" => blocks.push(HostBlock { patterns: Vec::new(), options: Vec::new() }),
+			"include" => {
+				for argument in &arguments {
+					for included in include_paths(argument)? {
+						// Missing includes are ignored, like OpenSSH does
+						if included.is_file() {
+							parse_file(&included, blocks, depth + 1)?;
+						}
+					}
+				}
+			}
+			_ => {
+				if let Some(block) = blocks.last_mut() {
+					block.options.push((keyword, arguments.join(" ")));
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Resolves the options of a host, the first value found for an option wins like in OpenSSH.
+fn resolve(blocks: &[HostBlock], host: &str) -> HashMap<String, String> {
+	let mut options = HashMap::new();
+	for block in blocks.iter().filter(|block| host_matches(&block.patterns, host)) {
+		for (keyword, value) in &block.options {
+			options.entry(keyword.clone()).or_insert_with(|| value.clone());
+		}
+	}
+	options
+}
+
+/// Splits a ProxyJump hop of the form `[user@]host[:port]`.
+fn parse_hop(hop: &str) -> (Option<String>, String, Option<i32>) {
+	let (user, rest) = match hop.rsplit_once('@') {
+		Some((user, rest)) => (Some(user.to_string()), rest),
+		None => (None, hop),
+	};
+	match rest.rsplit_once(':') {
+		Some((host, port)) if port.parse::<i32>().is_ok() => (user, host.to_string(), port.parse().ok()),
+		_ => (user, rest.to_string(), None),
+	}
+}
+
+/// The user name OpenSSH falls back to if a host sets no `User`.
+fn default_username() -> String {
+	std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default()
+}
+
+/// Builds the connection for a host alias from the resolved options.
+///
+/// # Arguments
+///
+/// * `blocks` - All parsed `Host` blocks.
+/// * `alias` - The alias to build the connection for, also used as its name.
+/// * `user` - A user that overrides the configured one, from a `user@host` ProxyJump hop.
+/// * `port` - A port that overrides the configured one, from a `host:port` ProxyJump hop.
+fn candidate(blocks: &[HostBlock], alias: &str, user: Option<String>, port: Option<i32>) -> ImportCandidate {
+	let options = resolve(blocks, alias);
+	let host = options.get("hostname").map(|host| host.replace("%h", alias)).unwrap_or_else(|| alias.to_string());
+	let port = port.or_else(|| options.get("port").and_then(|port| port.parse().ok())).unwrap_or(22);
+	let username = user.or_else(|| options.get("user").cloned()).unwrap_or_else(default_username);
+
+	let mut connection = importer::new_connection(alias, &host, port, &username);
+	match options.get("identityfile") {
+		Some(identity_file) => {
+			connection.auth_method = AuthMethod::KeyFile as i8;
+			connection.private_key_path = expand_home(identity_file);
+		}
+		// Without an identity file OpenSSH offers the keys of the agent
+		None => connection.auth_method = AuthMethod::Agent as i8,
+	}
+
+	// Only the last hop is the direct jump host, the hops before it are chained through each other
+	let jump_host = options.get("proxyjump")
+		.filter(|proxy_jump| !proxy_jump.eq_ignore_ascii_case("none"))
+		.and_then(|proxy_jump| proxy_jump.rsplit(',').next().map(|hop| hop.trim().to_string()));
+	ImportCandidate { connection, jump_host, warning: None }
+}
+
+/// Reads an OpenSSH config file and turns every concrete `Host` alias into an import candidate.
+///
+/// Jump hosts that are not defined as a `Host` of their own are added as connections as well.
+fn read_candidates(path: &Path) -> Result<Vec<ImportCandidate>, BackendError> {
+	let mut blocks = Vec::new();
+	parse_file(path, &mut blocks, 0)?;
+
+	// Wildcard and negated patterns only provide defaults, they are not hosts of their own
+	let mut aliases: Vec<String> = Vec::new();
+	for block in &blocks {
+		for pattern in &block.patterns {
+			if !pattern.contains(['*', '?', '!']) && !aliases.contains(pattern) {
+				aliases.push(pattern.clone());
+			}
+		}
+	}
+	let mut candidates: Vec<ImportCandidate> = aliases.iter().map(|alias| candidate(&blocks, alias, None, None)).collect();
+
+	// Follow the ProxyJump chains, hops of the form user@host:port become connections named after the hop
+	let mut index = 0;
+	while index < candidates.len() {
+		let proxy_jump = resolve(&blocks, &candidates[index].connection.name).get("proxyjump").cloned();
+		if let Some(proxy_jump) = proxy_jump.filter(|proxy_jump| !proxy_jump.eq_ignore_ascii_case("none")) {
+			let hops: Vec<&str> = proxy_jump.split(',').map(str::trim).collect();
+			for (position, hop) in hops.iter().enumerate() {
+				if !candidates.iter().any(|candidate| candidate.connection.name == *hop) {
+					let (user, host, port) = parse_hop(hop);
+					let mut jump = candidate(&blocks, &host, user, port);
+					jump.connection.name = hop.to_string();
+					candidates.push(jump);
+				}
+				// A hop is reached through the hop before it, unless it has a ProxyJump of its own
+				if position > 0 {
+					if let Some(jump) = candidates.iter_mut().find(|candidate| candidate.connection.name == *hop) {
+						jump.jump_host = jump.jump_host.take().or_else(|| Some(hops[position - 1].to_string()));
+					}
+				}
+			}
+		}
+		index += 1;
+	}
+	Ok(candidates)
+}
+
+/// Imports the hosts of an OpenSSH config file as connections.
+///
+/// `Host`, `HostName`, `Port`, `User`, `IdentityFile` and `ProxyJump` are read, including defaults from
+/// wildcard hosts and files pulled in with `Include`. Existing connections are matched by name and updated.
+///
+/// # Arguments
+///
+/// * `path` - The config file to read, defaults to `~/.ssh/config`.
+/// * `commit` - `false` only returns the preview, `true` adds and updates the connections.
+/// * `hosts` - The aliases to import, all hosts are imported if not given.
+///
+/// # Returns
+///
+/// What is, or would be, added, updated or left unchanged for every host.
+#[tauri::command]
+pub fn import_ssh_config(path: Option<String>, commit: bool, hosts: Option<Vec<String>>) -> Result<Vec<ImportPreview>, BackendError> {
+	let path = match path {
+		Some(path) => PathBuf::from(expand_home(&path)),
+		None => ssh_directory()?.join("config"),
+	};
+	let mut candidates = read_candidates(&path)?;
+	if let Some(mut hosts) = hosts {
+		// Jump hosts of the selected hosts are needed as well
+		let mut index = 0;
+		while index < hosts.len() {
+			let jump_host = candidates.iter().find(|candidate| candidate.connection.name == hosts[index]).and_then(|candidate| candidate.jump_host.clone());
+			if let Some(jump_host) = jump_host.filter(|jump_host| !hosts.contains(jump_host)) {
+				hosts.push(jump_host);
+			}
+			index += 1;
+		}
+		candidates.retain(|candidate| hosts.contains(&candidate.connection.name));
+	}
+
+	if commit {
+		importer::apply(candidates)
+	} else {
+		importer::preview(&candidates)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A directory for the config files of a test, removed again when the test ends.
+	struct ConfigDirectory(PathBuf);
+
+	impl ConfigDirectory {
+		fn new(name: &str) -> Self {
+			let directory = std::env::temp_dir().join(format!("ssh-config-test-{}-{}", name, std::process::id()));
+			fs::create_dir_all(&directory).unwrap();
+			ConfigDirectory(directory)
+		}
+
+		fn write(&self, name: &str, content: &str) -> PathBuf {
+			let path = self.0.join(name);
+			fs::write(&path, content).unwrap();
+			path
+		}
+	}
+
+	impl Drop for ConfigDirectory {
+		fn drop(&mut self) {
+			let _ = fs::remove_dir_all(&self.0);
+		}
+	}
+
+	fn find<'a>(candidates: &'a [ImportCandidate], name: &str) -> &'a ImportCandidate {
+		candidates.iter().find(|candidate| candidate.connection.name == name).unwrap()
+	}
+
+	#[test]
+	fn wildcards_backtrack() {
+		assert!(wildcard_match("*.example.com", "www.example.com"));
+		assert!(wildcard_match("*.example.com", "a.b.example.com"));
+		assert!(wildcard_match("w?w*", "WWW.example.com"));
+		assert!(wildcard_match("*a*b", "xaxxab"));
+		assert!(!wildcard_match("*.example.com", "example.com"));
+		assert!(!wildcard_match("a?", "a"));
+	}
+
+	#[test]
+	fn negated_patterns_exclude_hosts() {
+		let patterns = vec!["*.example.com".to_string(), "!secret.example.com".to_string()];
+		assert!(host_matches(&patterns, "www.example.com"));
+		assert!(!host_matches(&patterns, "secret.example.com"));
+		assert!(!host_matches(&["!secret".to_string()], "other"));
+	}
+
+	#[test]
+	fn lines_support_equals_signs_and_quotes() {
+		assert_eq!(split_line("Port=2222"), Some(("port".to_string(), vec!["2222".to_string()])));
+		assert_eq!(split_line("  HostName = example.com"), Some(("hostname".to_string(), vec!["example.com".to_string()])));
+		assert_eq!(split_line("IdentityFile \"/keys/my key\""), Some(("identityfile".to_string(), vec!["/keys/my key".to_string()])));
+		assert_eq!(split_line("# comment"), None);
+		assert_eq!(split_line("   "), None);
+	}
+
+	#[test]
+	fn the_first_value_wins() {
+		let directory = ConfigDirectory::new("first-value");
+		let path = directory.write("config", concat!(
+			"User global\n",
+			"Host web\n",
+			"  HostName web.example.com\n",
+			"  Port 2222\n",
+			"Host *\n",
+			"  Port 22\n",
+			"  User fallback\n",
+			"  IdentityFile /keys/id_ed25519\n",
+		));
+
+		let candidates = read_candidates(&path).unwrap();
+		assert_eq!(candidates.len(), 1);
+		let web = &find(&candidates, "web").connection;
+		assert_eq!(web.host, "web.example.com");
+		assert_eq!(web.port, 2222);
+		assert_eq!(web.username, "global");
+		assert_eq!(web.auth_method, AuthMethod::KeyFile as i8);
+		assert_eq!(web.private_key_path, "/keys/id_ed25519");
+	}
+
+	#[test]
+	fn includes_are_globbed_in_lexical_order() {
+		let directory = ConfigDirectory::new("include");
+		directory.write("b.conf", "Host b\n  Port 2002\nHost shared\n  Port 2222\n");
+		directory.write("a.conf", "Host a\n  Port 2001\nHost shared\n  Port 1111\n");
+		directory.write("ignored.txt", "Host ignored\n");
+		let path = directory.write("config", &format!("Include {}/*.conf\nHost after\n", directory.0.display()));
+
+		let candidates = read_candidates(&path).unwrap();
+		let names: Vec<&str> = candidates.iter().map(|candidate| candidate.connection.name.as_str()).collect();
+		assert_eq!(names, ["a", "shared", "b", "after"]);
+		assert_eq!(find(&candidates, "shared").connection.port, 1111);
+	}
+
+	#[test]
+	fn includes_are_limited_in_depth() {
+		let directory = ConfigDirectory::new("include-loop");
+		let path = directory.0.join("config");
+		directory.write("config", &format!("Include {}\n", path.display()));
+
+		assert!(matches!(read_candidates(&path), Err(BackendError::InvalidInput { .. })));
+	}
+
+	#[test]
+	fn proxy_jump_chains_are_linked() {
+		let directory = ConfigDirectory::new("proxy-jump");
+		let path = directory.write("config", concat!(
+			"Host bastion\n",
+			"  HostName bastion.example.com\n",
+			"Host internal\n",
+			"  ProxyJump bastion,admin@gateway:2200\n",
+			"Host direct\n",
+			"  ProxyJump none\n",
+		));
+
+		let candidates = read_candidates(&path).unwrap();
+		assert_eq!(find(&candidates, "internal").jump_host.as_deref(), Some("admin@gateway:2200"));
+		assert_eq!(find(&candidates, "bastion").jump_host, None);
+		assert_eq!(find(&candidates, "direct").jump_host, None);
+
+		let gateway = find(&candidates, "admin@gateway:2200");
+		assert_eq!(gateway.jump_host.as_deref(), Some("bastion"));
+		assert_eq!(gateway.connection.host, "gateway");
+		assert_eq!(gateway.connection.port, 2200);
+		assert_eq!(gateway.connection.username, "admin");
+	}
+}
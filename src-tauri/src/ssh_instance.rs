@@ -1,14 +1,46 @@
 use std::env;
-use std::io::{Read, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::net::TcpStream;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::connection_manager::Connection;
+use crate::app_settings;
+use crate::auth_prompt::{self, AuthPromptField};
+use crate::connection_manager::{AuthMethod, Connection};
+use crate::credential_store;
+use crate::error::BackendError;
+use crate::jump_host;
+use crate::known_hosts;
+use crate::logger;
+use crate::remote_fs::{copy_recursive, join_remote, remote_filename, RemoteFs};
 use crate::sftp_manager::File;
 
+/// The output stream a chunk of command output was read from.
+#[derive(Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// The outcome of a command executed with `SSHInstance::send_ssh_command`.
+#[derive(Clone, serde::Serialize)]
+pub struct CommandResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_status: i32,
+    /// The signal that terminated the command, without the `SIG` prefix.
+    pub exit_signal: Option<String>,
+    /// How long the command ran, in milliseconds.
+    pub duration: u64,
+}
+
 pub struct SSHInstance {
     pub session: ssh2::Session,
-    pub channel: ssh2::Channel,
+    sftp: Option<ssh2::Sftp>,
+	/// A handle to the socket the session runs over, shutting it down interrupts a blocked call.
+    socket: Option<TcpStream>,
 }
 
 
@@ -21,129 +53,141 @@ impl SSHInstance {
     ///
     /// # Returns
     ///
-    /// * `Result<SSHInstance, String>` - The `SSHInstance` if the connection was successful, otherwise an error message.
-    pub fn connect(options: Connection) -> Result<SSHInstance, String> {
-		// Log the attempt to establish a connection
-        println!("Attempting to create connection using {:?}", options);
+    /// * `Result<SSHInstance, BackendError>` - The `SSHInstance` if the connection was successful, otherwise an error message.
+    pub fn connect(options: Connection) -> Result<SSHInstance, BackendError> {
+        SSHInstance::connect_through(options, &[])
+    }
+
+    /// Connects to an SSH server, tunneling through the jump hosts of the connection if it has any.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The connection options specifying the host, port, username, password, and private key.
+    /// * `chain` - The ids of the connections this one is a jump host for, to detect loops in the chain.
+    pub(crate) fn connect_through(mut options: Connection, chain: &[i32]) -> Result<SSHInstance, BackendError> {
+		// Log the attempt to establish a connection, the options are not logged as they may hold secrets
+        let _ = logger::log(&format!("Connecting to {}@{}:{}", options.username, options.host, options.port), "", logger::LOG_INFO);
+
+		// Saved connections come without their secrets, look them up
+        credential_store::fill_secrets(&mut options)?;
 
-		// Attempt to establish a TCP stream to the given host and port
-        return match TcpStream::connect(format!("{}:{}", options.host, options.port)) {
+		// Attempt to establish a TCP stream to the given host and port, directly or through the jump hosts
+        match jump_host::open_stream(&options, chain) {
             Ok(tcp) => {
 				// Try creating a new SSH session
                 let mut session = match ssh2::Session::new() {
                     Ok(session) => session,
-					Err(e) => return Err(format!("Error creating session: {:?}", e)), // Error in creating the session
+					Err(e) => return Err(BackendError::ssh("Error creating session", &e)), // Error in creating the session
                 };
 
-				// Set the TCP stream in the SSH session
+				// Set the TCP stream in the SSH session, keeping a handle so a blocked operation can be cancelled
+                let socket = tcp.try_clone().ok();
                 session.set_tcp_stream(tcp);
 
+				// Fail blocking calls with a timeout instead of hanging on a server that stopped responding, 0 disables it
+                let read_timeout = app_settings::network_settings().read_timeout();
+                session.set_timeout(read_timeout.map(|timeout| timeout.as_millis() as u32).unwrap_or(0));
+
 				// Try performing the SSH handshake
                 if let Err(e) = session.handshake() {
 					// Error in performing the SSH handshake
-                    return Err(format!("Error handshaking: {:?}", e));
+                    return Err(BackendError::ssh("Error handshaking", &e));
                 }
 
-				// Extracts the private key from `options` and creates a file
-                let private_key = SSHInstance::extract_private_key_to_file(&options.private_key);
-                let private_key = private_key.as_path();
-
-				// Check if there is a private key provided
-                if !options.private_key.is_empty() {
-					// If there's also a password provided, use that. If not, set the password to None
-                    let password: Option<&str> = if !options.password.is_empty() {
-                        Some(&options.password)
-                    } else {
-                        None
-                    };
-
-					// Try authenticating using a public key
-                    if session.userauth_pubkey_file(&options.username, None, private_key, password).is_err() {
-                        SSHInstance::cleanup_private_key_file(private_key.to_path_buf());
-                        return Err("Error authenticating with public key".to_string());
-                    }
+				// Refuse to authenticate against a server whose key is unknown or changed
+                known_hosts::verify(&session, &options.host, options.port as u16)?;
 
-					// Clean up the temporary private key file, regardless of success
-                    SSHInstance::cleanup_private_key_file(private_key.to_path_buf());
-                } else if session.userauth_password(&options.username, &options.password).is_err() {
-					// If no private key is provided, try authenticating using a password
-                    return Err("Error authenticating with password".to_string());
-                }
+				// Authenticate, including any further methods the server requires
+                SSHInstance::authenticate(&session, &options)?;
 
-				// Try creating a new channel for the session
-                let channel = match session.channel_session() {
-                    Ok(channel) => channel,
-					Err(e) => return Err(format!("Error creating channel: {:?}", e)), // Error creating the channel
-                };
+				// Send keepalive messages so that idle pooled sessions are not dropped by the server
+                session.set_keepalive(true, 30);
 
-				// Return a new SSHInstance
+				// Return a new SSHInstance, the SFTP subsystem is opened lazily on first use
                 Ok(SSHInstance {
                     session,
-                    channel,
+                    sftp: None,
+                    socket,
                 })
             }
             Err(e) => {
-				// Error establishing the TCP connection
-                return Err(format!("Error connecting: {:?}", e));
+				// Error establishing the TCP connection, already described by `open_stream`
+                Err(e)
             }
-        };
+        }
     }
 
-    /// Sends an SSH command to a server and returns the output as a string.
+    /// Checks whether the underlying SSH session is still usable.
     ///
-    /// # Arguments
+    /// A keepalive message is sent to the server, if the transport has been dropped this fails
+    /// and the instance should be discarded and reconnected.
     ///
-    /// * `command` - The command to execute on the server.
-    /// * `options` - The SSH connection options.
+    /// # Returns
     ///
-    /// # Examples
+    /// `true` if the session is authenticated and the server is still reachable, otherwise `false`.
+    pub fn is_alive(&self) -> bool {
+        self.session.authenticated() && self.session.keepalive_send().is_ok()
+    }
+
+    /// Returns the SFTP handle of this session, opening the SFTP subsystem on first use.
     ///
-    /// ```
-    /// use my_ssh_library::send_ssh_command;
+    /// # Returns
+    ///
+    /// * `Ok(&ssh2::Sftp)` - The cached SFTP handle.
+    /// * `Err(error)` - An error description if the SFTP subsystem could not be started.
+    pub fn sftp(&mut self) -> Result<&ssh2::Sftp, BackendError> {
+		// Only start the SFTP subsystem once per session
+        let sftp = match self.sftp.take() {
+            Some(sftp) => sftp,
+            None => self.session.sftp().map_err(|e| BackendError::ssh("Error creating SFTP session", &e))?,
+        };
+        Ok(self.sftp.insert(sftp))
+    }
+
+    /// Sends an SSH command to the server and collects its output and exit status.
     ///
-    /// let command = "ls";
-    /// let options = Connection {
-    ///     hostname: "example.com",
-    ///     username: "sshuser",
-    ///     password: "sshpassword",
-    /// };
+    /// A new channel is opened on the existing session for every command, so the same
+    /// `SSHInstance` can be used to run any number of commands. Stdout and stderr are read
+    /// alternately so a command writing a lot to one of them can never stall the other.
     ///
-    /// let result = send_ssh_command(command, options);
-    /// match result {
-    ///     Ok(output) => println!("Command output: {}", output),
-    ///     Err(error) => println!("Error: {}", error),
-    /// }
-    /// ```
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute on the server.
+    /// * `on_output` - Called with every chunk of output as it arrives, used to stream output to the frontend.
     ///
     /// # Returns
     ///
-    /// * `Ok(output)` - The output of the executed command as a string.
-    /// * `Err(error)` - An error description if there was an error connecting or executing the command.
-    pub fn send_ssh_command(command: &str, options: Connection) -> Result<String, String> {
-		// Attempt to connect the SSH instance with the given options
-        match SSHInstance::connect(options) {
-			// If successful, do the following:
-            Ok(connection) => {
-                let mut channel = connection.channel;
-				let mut bytes: Vec<u8> = vec![]; // Create a mutable byte vector
+    /// * `Ok(result)` - The output, exit status and duration of the command. Output is decoded lossily, so
+    ///   binary output or invalid UTF-8 never fails the command.
+    /// * `Err(error)` - An error description if there was an error opening the channel or executing the command.
+    pub fn send_ssh_command<F>(&self, command: &str, mut on_output: F) -> Result<CommandResult, BackendError>
+    where
+        F: FnMut(OutputStream, &[u8]),
+    {
+        let started = Instant::now();
 
-				// Execute the command on the server. Panic if there is an error
-                channel.exec(command).unwrap();
+		// Open a new channel on the session for this command and execute it
+        let mut channel = self.session.channel_session().map_err(|e| BackendError::ssh("Error creating channel", &e))?;
+        channel.exec(command).map_err(|e| BackendError::ssh("Error executing command", &e))?;
 
-				// Read the output of the command to the end and store it in the byte vector. Panic if there is an error
-                channel.read_to_end(&mut bytes).unwrap();
+		// Read both streams without blocking until the command has finished
+        self.session.set_blocking(false);
+        let result = read_command_output(&mut channel, &mut on_output);
+        self.session.set_blocking(true);
+        let (stdout, stderr) = result?;
 
-				// Convert the byte vector to a string. Panic if there is an error
-                let output = String::from_utf8(bytes).unwrap();
+		// Close the channel so the server can release it and report the exit status
+        channel.wait_close().map_err(|e| BackendError::ssh("Error closing channel", &e))?;
+        let exit_status = channel.exit_status().map_err(|e| BackendError::ssh("Error reading exit status", &e))?;
+        let exit_signal = channel.exit_signal().ok().and_then(|signal| signal.exit_signal);
 
-				// Return the output as a successful Result
-                Ok(output)
-            }
-			// If there was an error connecting, return an error Result with a description of the error
-            Err(e) => {
-                Err(format!("Error: {:?}", e))
-            }
-        }
+        Ok(CommandResult {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            exit_status,
+            exit_signal,
+            duration: started.elapsed().as_millis() as u64,
+        })
     }
 
     /// Lists the files in a directory.
@@ -152,7 +196,6 @@ impl SSHInstance {
     ///
     /// * `path` - The path of the directory to list.
     /// * `show_hidden` - Whether or not to show hidden files.
-    /// * `options` - The connection options.
     ///
     /// # Returns
     ///
@@ -161,215 +204,555 @@ impl SSHInstance {
     /// # Errors
     ///
     /// Returns an error string if any of the following occur:
-    /// - Connection attempt fails.
     /// - SFTP session creation fails.
     /// - Directory opening fails.
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use my_library::{list_dir, Connection};
-    ///
-    /// let options = Connection::new("host", "username", "password");
-    /// let result = list_dir("/path/to/directory", true, options);
-    /// match result {
-    ///     Ok(files) => {
-    ///         for file in files {
-    ///             println!("{}", file.filename);
-    ///         }
-    ///     },
-    ///     Err(e) => {
-    ///         println!("Error: {}", e);
-    ///     }
-    /// }
-    /// ```
-    pub fn list_dir(path: &str, show_hidden: bool, options: Connection) -> Result<Vec<File>, String>
+    pub fn list_dir(&mut self, path: &str, show_hidden: bool) -> Result<Vec<File>, BackendError>
     {
-		// Connection attempt
-        match SSHInstance::connect(options) {
-			// If successful connection...
-            Ok(connection) => {
-                let session = connection.session;
-				// ... attempt to create a new SFTP session
-                match session.sftp() {
-					// If successful SFTP session creation...
-                    Ok(sftp) => {
-						// ... attempt to open the provided directory
-                        match sftp.opendir(Path::new(path)) {
-							// If successful directory opening...
-                            Ok(mut dir) => {
-								// Create a new, empty file vector
-                                let mut files: Vec<File> = Vec::new();
-								// Read through directory
-                                loop {
-									// Ignore '.' and '..' directories
-                                    if path.eq(".") || path.eq("..") {
-                                        continue;
-                                    }
-									// Read directory
-                                    match dir.readdir() {
-										// If reading directory is successful...
-                                        Ok((buf, stat)) => {
-                                            let filename = buf.to_str().unwrap().to_string();
-                                            let absolute_path = Path::join(Path::new(path), Path::new(&filename)).to_str().unwrap().to_string();
-											// If we are not showing hidden files, ignore files starting with '.'
-                                            if !show_hidden && filename.starts_with('.') {
-                                                continue;
-                                            }
-											// Add files to the vector
-                                            files.push(File {
-		                                        path: absolute_path, // Path of the file
-		                                        filename, // Name of the file
-		                                        is_dir: stat.is_dir(), // Bool indicating if the file is a directory
-		                                        size: stat.size.unwrap(), // Size of the file
-		                                        modified: stat.mtime.unwrap(), // Modification time of the file
-		                                        access: stat.atime.unwrap(), // Access time of the file
-		                                        permissions: stat.perm.unwrap(), // Permissions of the file
-		                                        owner: stat.uid.unwrap(), // User ID of the owner
-		                                        group: stat.gid.unwrap(), // Group ID of the owner
-                                            });
-                                        }
-										// If there is an error reading the directory, print the error and break
-                                        Err(e) => {
-                                            println!("Error: {:?}", e);
-                                            break;
-                                        }
-                                    }
-                                }
-								// Return the file vector if all operations are successful
-                                Ok(files)
-                            }
-							// If there is an error opening the directory, return the error
-                            Err(e) => {
-                                Err(format!("Error opening directory: {:?}", e))
-                            }
-                        }
+		// Get the cached SFTP session
+        let sftp = self.sftp()?;
+
+		// Attempt to open the provided directory
+        match sftp.opendir(Path::new(path)) {
+			// If successful directory opening...
+            Ok(mut dir) => {
+				// Create a new, empty file vector
+                let mut files: Vec<File> = Vec::new();
+				// Read through directory, the end of the directory is reported as an error
+                while let Ok((buf, stat)) = dir.readdir() {
+                    let filename = buf.to_string_lossy().to_string();
+					// Ignore '.' and '..' directories
+                    if filename.eq(".") || filename.eq("..") {
+                        continue;
                     }
-					// If there is an error creating the SFTP session, return the error
-                    Err(e) => {
-                        Err(format!("Error creating SFTP session: {:?}", e))
+                    let absolute_path = join_remote(path, &filename);
+					// If we are not showing hidden files, ignore files starting with '.'
+                    if !show_hidden && filename.starts_with('.') {
+                        continue;
                     }
+					// Add files to the vector
+                    files.push(file_from_stat(absolute_path, filename, &stat));
                 }
+				// Return the file vector if all operations are successful
+                Ok(files)
             }
-			// If there is an error connecting, return the error
+			// If there is an error opening the directory, return the error
             Err(e) => {
-                Err(format!("Error: {:?}", e))
+                Err(BackendError::ssh(&format!("Error opening directory {}", path), &e))
             }
         }
     }
 
-	/// This function downloads a file from a remote path to a local path.
+	/// Uploads a local file to a remote path, streaming it through SFTP in chunks.
+    ///
+    /// After the upload the modification and access time of the local file are applied to the
+    /// remote file, and optionally its permissions as well.
     ///
     /// # Arguments
     ///
-    /// * `remote_path` - The path of the file on the remote server.
-    /// * `local_path` - The path of the local file to be created.
-    /// * `options` - The connection options for establishing an SSH connection.
+    /// * `local_path` - The path of the local file to upload.
+    /// * `remote_path` - The path of the remote file to create or overwrite.
+    /// * `preserve_permissions` - Whether to copy the permissions of the local file.
+    /// * `offset` - How many bytes of the remote file an interrupted upload already wrote, `0` to upload the whole file.
     ///
     /// # Returns
     ///
-    /// `Result<(), String>` - Returns `Ok` if the download is successful, otherwise returns an error message as `Err`.
-    pub fn download_file(remote_path: &str, local_path: &str, options: Connection) -> Result<(), String> {
-		// Try to establish connection with the given options
-        match SSHInstance::connect(options) {
-			// If connection is successful...
-            Ok(connection) => {
-				let session = connection.session; // Get the session from the connection
-
-				// Try to retrieve the file from the remote path
-                match session.scp_recv(Path::new(remote_path)) {
-					// If successful retrieval...
-                    Ok((mut channel, _)) => {
-						// Create a local file at the given local path
-                        let mut local_file = std::fs::File::create(local_path).unwrap();
-
-						let mut buffer = [0; 4096]; // Buffer to hold the file data in 4KB chunks
-
-						// Loop to read from the source (remote file) to the local file
-                        loop {
-                            match channel.read(&mut buffer) {
-								// End of file, so break from the loop
-                                Ok(0) => {
-                                    break;
-                                }
-								// Read 'n' bytes from source and write to the local file
-                                Ok(n) => {
-                                    local_file.write_all(&buffer[..n]).unwrap();
-                                }
-								// If there is an error reading from source, return an error message
-                                Err(e) => {
-                                    return Err(format!("Error reading remote file: {:?}", e));
-                                }
-                            }
-                        }
-						// Return success if no errors encountered
-                        Ok(())
-                    }
-					// If there is an error retrieving the file, return an error message
-                    Err(e) => {
-                        Err(format!("Error opening remote file: {:?}", e))
-                    }
-                }
-            }
-			// If there is an error establishing the connection, return an error message
-            Err(e) => {
-                Err(format!("Error: {:?}", e))
-            }
-        }
+    /// `Result<(), BackendError>` - Returns `Ok` if the upload is successful, otherwise returns an error message as `Err`.
+    pub fn upload_file(&mut self, local_path: &str, remote_path: &str, preserve_permissions: bool, offset: u64) -> Result<(), BackendError> {
+		// Open the local file and read its metadata for the timestamps and permissions
+        let mut local_file = std::fs::File::open(local_path).map_err(|e| BackendError::io("Error opening local file", &e))?;
+        let metadata = local_file.metadata().map_err(|e| BackendError::io("Error reading local file metadata", &e))?;
+
+		// Skip what an interrupted upload already sent, then create (or truncate) the remote file or continue it
+        local_file.seek(SeekFrom::Start(offset)).map_err(|e| BackendError::io("Error seeking local file", &e))?;
+        self.write_at(remote_path, offset, &mut local_file)?;
+
+		// Apply the local timestamps and, if requested, the local permissions
+        let sftp = self.sftp()?;
+        let stat = ssh2::FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm: if preserve_permissions { local_permissions(&metadata) } else { None },
+            atime: metadata.accessed().ok().and_then(unix_time),
+            mtime: metadata.modified().ok().and_then(unix_time),
+        };
+        sftp.setstat(Path::new(remote_path), stat).map_err(|e| BackendError::ssh("Error setting remote file attributes", &e))
     }
 
 
-	/// Extracts the private key to a temporary file.
+	/// Copies a remote file or directory to another remote path.
     ///
-    /// # Arguments
+    /// The copy runs on the server with `cp -a` when commands can be executed, which keeps ownership,
+    /// permissions and timestamps and avoids sending the data over the network twice. If that fails,
+    /// e.g. on SFTP-only accounts, the files are streamed through the SFTP session instead.
     ///
-    /// * `private_key` - A string containing the private key.
+    /// # Arguments
     ///
-    /// # Returns
+    /// * `from` - The remote path to copy.
+    /// * `to` - The remote path to create.
+    pub fn copy_path(&mut self, from: &str, to: &str) -> Result<(), BackendError> {
+        let command = format!("cp -a -- {} {}", shell_quote(from), shell_quote(to));
+        match self.send_ssh_command(&command, |_, _| ()) {
+            Ok(result) if result.exit_status == 0 => Ok(()),
+			// The exec channel is not available or cp failed, fall back to a stream copy
+            _ => copy_recursive(self, from, to),
+        }
+    }
+
+	/// Authenticates a session, first with the configured method and then with whatever else the server asks for.
     ///
-    /// Returns a `PathBuf` object representing the path of the private key file.
+    /// Servers can require several methods one after another, e.g. a public key followed by a one-time password,
+    /// or only offer keyboard-interactive instead of password authentication. After every attempt the remaining
+    /// methods are queried with `auth_methods` until the session is authenticated or nothing is left to try.
+    /// Questions the stored credentials can not answer are relayed to the user through `auth_prompt`.
     ///
-    /// # Panics
+    /// # Arguments
     ///
-    /// Panics if there's an error creating the file or writing to it.
-    fn extract_private_key_to_file(private_key: &str) -> PathBuf {
-        println!("Extracting private key to file");
+    /// * `session` - The session to authenticate, the handshake must already be done.
+    /// * `options` - The connection holding the username, the configured method and the secrets.
+    fn authenticate(session: &ssh2::Session, options: &Connection) -> Result<(), BackendError> {
+        let auth_method = AuthMethod::try_from(options.auth_method)?;
+
+		// With a private key the password is its passphrase, if there is one
+        let passphrase: Option<&str> = if !options.password.is_empty() {
+            Some(&options.password)
+        } else {
+            None
+        };
 
-		// Get the path for the temporary file
-        let binding = env::current_exe().unwrap().parent().unwrap().join(".tmp");
-        let filepath = binding.to_str().unwrap();
+		// Authenticate the way the connection is configured to
+        let (first_method, result) = match auth_method {
+            AuthMethod::Password => ("password", session.userauth_password(&options.username, &options.password)
+                .map_err(|_| BackendError::auth_failed("Error authenticating with password"))),
+            AuthMethod::PrivateKey => ("publickey", SSHInstance::userauth_private_key(session, &options.username, &options.private_key, passphrase)),
+            AuthMethod::Agent => ("publickey", SSHInstance::userauth_agent(session, &options.username)),
+            AuthMethod::KeyFile => {
+                let key_path = expand_home(&options.private_key_path);
+                if !key_path.is_file() {
+                    return Err(BackendError::not_found(format!("Private key file not found: {}", key_path.display())));
+                }
+                ("publickey", session.userauth_pubkey_file(&options.username, None, &key_path, passphrase)
+                    .map_err(|e| BackendError::auth_failed(format!("Error authenticating with key file: {}", e.message()))))
+            }
+        };
+        let mut error = result.err();
+        let mut tried = vec![first_method];
 
-		// Create a new file at the temporary file path. Panic if there's an error
-        let mut file = std::fs::File::create(filepath).unwrap();
+		// Continue with the methods the server still accepts, a partial success keeps the earlier ones
+        for _ in 0..MAX_AUTH_ROUNDS {
+            if session.authenticated() {
+                return Ok(());
+            }
+            let methods = match session.auth_methods(&options.username) {
+                Ok(methods) => methods.to_string(),
+                Err(_) => break,
+            };
+            let next = ["keyboard-interactive", "password"].into_iter()
+                .find(|method| !tried.contains(method) && methods.split(',').any(|offered| offered == *method));
+            let method = match next {
+                Some(method) => method,
+                None => {
+                    error = error.or_else(|| Some(BackendError::auth_failed(format!("Authentication failed, the server accepts: {}", methods))));
+                    break;
+                }
+            };
+            tried.push(method);
 
-		// Write the private key to the file. Panic if there's an error
-        file.write_all(private_key.as_bytes()).unwrap();
+            let result = if method == "keyboard-interactive" {
+                let mut prompter = InteractivePrompter { options, auth_method, password_used: false, error: None };
+                session.userauth_keyboard_interactive(&options.username, &mut prompter)
+                    .map_err(|e| prompter.error.take().unwrap_or_else(|| BackendError::auth_failed(format!("Error authenticating with keyboard-interactive: {}", e.message()))))
+            } else {
+				// The stored password is a key passphrase here, so ask the user for the account password
+                auth_prompt::ask(options, "", vec![AuthPromptField { text: "Password:".to_string(), echo: false }])
+                    .and_then(|answers| {
+                        let password = answers.into_iter().next().unwrap_or_default();
+                        session.userauth_password(&options.username, &password).map_err(|_| BackendError::auth_failed("Error authenticating with password"))
+                    })
+            };
+            if let Err(e) = result {
+                error = Some(e);
+            }
+        }
 
-		// Return the path of the private key file
-        PathBuf::from(&filepath)
+        if session.authenticated() {
+            Ok(())
+        } else {
+            Err(error.unwrap_or_else(|| BackendError::auth_failed("Authentication failed")))
+        }
     }
 
-	/// Deletes a file at the specified path.
+	/// Authenticates with the identities of a running SSH agent, trying each until one is accepted.
     ///
-    /// # Arguments
+    /// On Unix the agent is found through the `SSH_AUTH_SOCK` environment variable, on Windows Pageant
+    /// and the OpenSSH agent are used.
     ///
-    /// * `private_key` - The path to the private key file to be deleted.
+    /// # Arguments
     ///
-    /// # Panics
+    /// * `session` - The session to authenticate, the handshake must already be done.
+    /// * `username` - The user to authenticate as.
+    fn userauth_agent(session: &ssh2::Session, username: &str) -> Result<(), BackendError> {
+        #[cfg(unix)]
+        if env::var_os("SSH_AUTH_SOCK").is_none() {
+            return Err(BackendError::auth_failed("No SSH agent found, SSH_AUTH_SOCK is not set"));
+        }
+
+        let mut agent = session.agent().map_err(|e| BackendError::ssh("Error creating agent", &e))?;
+        agent.connect().map_err(|e| BackendError::ssh("Error connecting to SSH agent", &e))?;
+        agent.list_identities().map_err(|e| BackendError::ssh("Error listing agent identities", &e))?;
+        let identities = agent.identities().map_err(|e| BackendError::ssh("Error listing agent identities", &e))?;
+        if identities.is_empty() {
+            return Err(BackendError::auth_failed("The SSH agent has no identities"));
+        }
+
+		// The server rejects keys it does not know, so offer one after another
+        let result = identities.iter().any(|identity| agent.userauth(username, identity).is_ok());
+        let _ = agent.disconnect();
+        if result {
+            Ok(())
+        } else {
+            Err(BackendError::auth_failed("Error authenticating with SSH agent: no identity was accepted"))
+        }
+    }
+
+	/// Authenticates with a private key, reading it from memory where the crypto backend supports it.
     ///
-    /// This function will panic if there is an error while attempting to remove the file.
+    /// If the key can not be read from memory it is written to a temporary file that only the current
+    /// user can read, which is removed again as soon as authentication is done.
     ///
-    /// # Examples
+    /// # Arguments
     ///
-    /// ```no_run
-    /// # use std::path::PathBuf;
-    /// # fn main() {
-    /// #     let private_key = PathBuf::from("path/to/private_key");
-    /// cleanup_private_key_file(private_key);
-    /// # }
-    /// ```
-    fn cleanup_private_key_file(private_key: PathBuf) {
-		// Attempt to remove the file. Panic if there's an error
-        std::fs::remove_file(private_key).unwrap();
+    /// * `session` - The session to authenticate, the handshake must already be done.
+    /// * `username` - The user to authenticate as.
+    /// * `private_key` - The contents of the private key.
+    /// * `passphrase` - The passphrase of the private key, if it is encrypted.
+    fn userauth_private_key(session: &ssh2::Session, username: &str, private_key: &str, passphrase: Option<&str>) -> Result<(), BackendError> {
+        match userauth_pubkey_memory(session, username, private_key, passphrase) {
+            Ok(()) => return Ok(()),
+			// Only fall back to a file if the backend can not read keys from memory, a wrong key stays an error
+            Err(e) if e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_METHOD_NOT_SUPPORTED) => (),
+            Err(e) => return Err(BackendError::auth_failed(format!("Error authenticating with public key: {}", e.message()))),
+        }
+
+		// The file is removed when `key_file` goes out of scope, whether authentication succeeded or not
+        let key_file = TempKeyFile::create(private_key)?;
+        session.userauth_pubkey_file(username, None, &key_file.path, passphrase)
+            .map_err(|e| BackendError::auth_failed(format!("Error authenticating with public key: {}", e.message())))
+    }
+}
+
+/// How many additional methods are tried after the configured one before giving up.
+const MAX_AUTH_ROUNDS: usize = 4;
+
+/// Answers keyboard-interactive questions of the server, e.g. from PAM or a 2FA module.
+///
+/// A single password question is answered with the stored password once, everything else is asked to the user.
+struct InteractivePrompter<'a> {
+    options: &'a Connection,
+    auth_method: AuthMethod,
+    password_used: bool,
+    /// Why asking the user failed, reported instead of the generic libssh2 error.
+    error: Option<BackendError>,
+}
+
+impl ssh2::KeyboardInteractivePrompt for InteractivePrompter<'_> {
+    fn prompt<'b>(&mut self, _username: &str, instructions: &str, prompts: &[ssh2::Prompt<'b>]) -> Vec<String> {
+		// Some servers send an empty round before the actual questions
+        if prompts.is_empty() {
+            return Vec::new();
+        }
+
+        let asks_password = prompts.len() == 1 && !prompts[0].echo && prompts[0].text.to_lowercase().contains("password");
+        if asks_password && !self.password_used && self.auth_method == AuthMethod::Password && !self.options.password.is_empty() {
+            self.password_used = true;
+            return vec![self.options.password.clone()];
+        }
+
+        let fields = prompts.iter().map(|prompt| AuthPromptField { text: prompt.text.to_string(), echo: prompt.echo }).collect();
+        match auth_prompt::ask(self.options, instructions, fields) {
+            Ok(answers) => answers,
+            Err(e) => {
+				// Empty answers make the server reject the attempt
+                self.error = Some(e);
+                vec![String::new(); prompts.len()]
+            }
+        }
+    }
+}
+
+/// Expands a leading `~` in a local path to the home directory of the user.
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")) {
+        Some(rest) => tauri::api::path::home_dir().map(|home| home.join(rest)).unwrap_or_else(|| PathBuf::from(path)),
+        None => PathBuf::from(path),
+    }
+}
+
+/// libssh2 reports this error if the crypto backend can not read private keys from memory, e.g. WinCNG.
+const LIBSSH2_ERROR_METHOD_NOT_SUPPORTED: i32 = -33;
+
+/// Authenticates with a private key held in memory.
+#[cfg(unix)]
+fn userauth_pubkey_memory(session: &ssh2::Session, username: &str, private_key: &str, passphrase: Option<&str>) -> Result<(), ssh2::Error> {
+    session.userauth_pubkey_memory(username, None, private_key, passphrase)
+}
+
+/// Reading private keys from memory needs the OpenSSL backend, which is not used on Windows.
+#[cfg(not(unix))]
+fn userauth_pubkey_memory(_session: &ssh2::Session, _username: &str, _private_key: &str, _passphrase: Option<&str>) -> Result<(), ssh2::Error> {
+    Err(ssh2::Error::new(ssh2::ErrorCode::Session(LIBSSH2_ERROR_METHOD_NOT_SUPPORTED), "Private keys can not be read from memory"))
+}
+
+/// A private key written to a uniquely named temporary file, removed again when dropped.
+struct TempKeyFile {
+    path: PathBuf,
+}
+
+impl TempKeyFile {
+    /// Writes a private key to a new file in the temporary directory that only the current user can read.
+    ///
+    /// Every call gets its own file, so concurrent connections with different keys do not interfere.
+    fn create(private_key: &str) -> Result<TempKeyFile, BackendError> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        loop {
+            let name = format!(
+                "sftp-editor-key-{}-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst),
+                unix_time(std::time::SystemTime::now()).unwrap_or(0)
+            );
+            let path = env::temp_dir().join(name);
+
+			// Never reuse an existing file, someone else could have created it with other permissions
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            let mut file = match options.open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(BackendError::io("Error creating private key file", &e)),
+            };
+
+			// Create the guard before writing, so a failed write still removes the file
+            let key_file = TempKeyFile { path };
+            file.write_all(private_key.as_bytes()).map_err(|e| BackendError::io("Error writing private key file", &e))?;
+            return Ok(key_file);
+        }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for TempKeyFile {
+    fn drop(&mut self) {
+		// Removal can only fail if the file is already gone, there is nothing left to do then
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reads stdout and stderr of a channel in non-blocking mode until the remote command closes them.
+///
+/// # Returns
+///
+/// The raw bytes written to stdout and stderr.
+fn read_command_output<F>(channel: &mut ssh2::Channel, on_output: &mut F) -> Result<(Vec<u8>, Vec<u8>), BackendError>
+where
+    F: FnMut(OutputStream, &[u8]),
+{
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let mut idle = true;
+        for output_stream in [OutputStream::Stdout, OutputStream::Stderr] {
+            let read = match output_stream {
+                OutputStream::Stdout => channel.read(&mut buffer),
+                OutputStream::Stderr => channel.stderr().read(&mut buffer),
+            };
+            match read {
+                Ok(0) => (),
+                Ok(n) => {
+                    idle = false;
+                    on_output(output_stream, &buffer[..n]);
+                    match output_stream {
+                        OutputStream::Stdout => stdout.extend_from_slice(&buffer[..n]),
+                        OutputStream::Stderr => stderr.extend_from_slice(&buffer[..n]),
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                Err(e) => return Err(BackendError::io("Error reading command output", &e)),
+            }
+        }
+
+		// The command is done once the server closed both streams and nothing is left to read
+        if idle {
+            if channel.eof() {
+                return Ok((stdout, stderr));
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
+
+/// Quotes a string for use as a single argument in a POSIX shell command.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Retries a non-blocking libssh2 call until it no longer reports that it would block.
+pub(crate) fn retry<T, F>(mut operation: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    loop {
+        match operation() {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(1)),
+            result => return result,
+        }
+    }
+}
+
+/// Converts a system time to seconds since the unix epoch.
+fn unix_time(time: std::time::SystemTime) -> Option<u64> {
+    time.duration_since(std::time::UNIX_EPOCH).ok().map(|duration| duration.as_secs())
+}
+
+/// Returns the unix permission bits of a local file.
+#[cfg(unix)]
+fn local_permissions(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+/// Returns the permission bits of a local file, Windows only knows about the read-only flag.
+#[cfg(not(unix))]
+fn local_permissions(metadata: &std::fs::Metadata) -> Option<u32> {
+    Some(if metadata.permissions().readonly() { 0o444 } else { 0o644 })
+}
+
+/// Builds a `File` from the attributes returned by the SFTP subsystem.
+///
+/// # Arguments
+///
+/// * `path` - The absolute path of the file.
+/// * `filename` - The name of the file.
+/// * `stat` - The attributes of the file.
+fn file_from_stat(path: String, filename: String, stat: &ssh2::FileStat) -> File {
+    File {
+        path, // Path of the file
+        filename, // Name of the file
+        is_dir: stat.is_dir(), // Bool indicating if the file is a directory
+        is_symlink: stat.file_type().is_symlink(), // Bool indicating if the file is a symbolic link
+        size: stat.size.unwrap_or(0), // Size of the file
+        modified: stat.mtime.unwrap_or(0), // Modification time of the file
+        access: stat.atime.unwrap_or(0), // Access time of the file
+        permissions: stat.perm.unwrap_or(0), // Permissions of the file
+        owner: stat.uid.unwrap_or(0), // User ID of the owner
+        group: stat.gid.unwrap_or(0), // Group ID of the owner
+    }
+}
+
+/// The SFTP backend of the `RemoteFs` trait, every operation runs on the cached SFTP handle of the session.
+impl RemoteFs for SSHInstance {
+    fn list(&mut self, path: &str, show_hidden: bool) -> Result<Vec<File>, BackendError> {
+        self.list_dir(path, show_hidden)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<File, BackendError> {
+        let stat = self.sftp()?.stat(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error reading file attributes of {}", path), &e))?;
+		// Use the last path component as the filename, falling back to the path itself for '/'
+        let filename = match remote_filename(path) { "" => path, name => name }.to_string();
+        Ok(file_from_stat(path.to_string(), filename, &stat))
+    }
+
+    fn lstat(&mut self, path: &str) -> Result<File, BackendError> {
+        let stat = self.sftp()?.lstat(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error reading file attributes of {}", path), &e))?;
+        let filename = match remote_filename(path) { "" => path, name => name }.to_string();
+        Ok(file_from_stat(path.to_string(), filename, &stat))
+    }
+
+    fn read(&mut self, path: &str, writer: &mut dyn Write) -> Result<u64, BackendError> {
+        let mut remote_file = self.sftp()?.open(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error opening remote file {}", path), &e))?;
+        io::copy(&mut remote_file, writer).map_err(|e| BackendError::io("Error reading remote file", &e))
+    }
+
+    fn write(&mut self, path: &str, reader: &mut dyn Read) -> Result<u64, BackendError> {
+        let mut remote_file = self.sftp()?.create(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error creating remote file {}", path), &e))?;
+        io::copy(reader, &mut remote_file).map_err(|e| BackendError::io("Error writing remote file", &e))
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), BackendError> {
+        self.sftp()?.rename(Path::new(from), Path::new(to), None).map_err(|e| BackendError::ssh(&format!("Error renaming {} to {}", from, to), &e))
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), BackendError> {
+        let sftp = self.sftp()?;
+		// Directories and files are removed through different SFTP requests
+        let stat = sftp.lstat(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error reading file attributes of {}", path), &e))?;
+        if stat.is_dir() {
+            sftp.rmdir(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error removing remote directory {}", path), &e))
+        } else {
+            sftp.unlink(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error removing remote file {}", path), &e))
+        }
+    }
+
+    fn mkdir(&mut self, path: &str) -> Result<(), BackendError> {
+        self.sftp()?.mkdir(Path::new(path), 0o755).map_err(|e| BackendError::ssh(&format!("Error creating remote directory {}", path), &e))
+    }
+
+    fn socket(&self) -> Option<TcpStream> {
+        self.socket.as_ref().and_then(|socket| socket.try_clone().ok())
+    }
+
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    fn read_range(&mut self, path: &str, offset: u64, length: Option<u64>, writer: &mut dyn Write) -> Result<u64, BackendError> {
+        let mut remote_file = self.sftp()?.open(Path::new(path)).map_err(|e| BackendError::ssh(&format!("Error opening remote file {}", path), &e))?;
+        remote_file.seek(SeekFrom::Start(offset)).map_err(|e| BackendError::io("Error seeking remote file", &e))?;
+        match length {
+            Some(length) => io::copy(&mut remote_file.take(length), writer),
+            None => io::copy(&mut remote_file, writer),
+        }.map_err(|e| BackendError::io("Error reading remote file", &e))
+    }
+
+    fn write_at(&mut self, path: &str, offset: u64, reader: &mut dyn Read) -> Result<u64, BackendError> {
+        if offset == 0 {
+            return self.write(path, reader);
+        }
+		// Open without truncating, so the part that was already written is kept
+        let mut remote_file = self.sftp()?.open_mode(Path::new(path), ssh2::OpenFlags::WRITE, 0o644, ssh2::OpenType::File)
+            .map_err(|e| BackendError::ssh(&format!("Error opening remote file {}", path), &e))?;
+        remote_file.seek(SeekFrom::Start(offset)).map_err(|e| BackendError::io("Error seeking remote file", &e))?;
+        io::copy(reader, &mut remote_file).map_err(|e| BackendError::io("Error writing remote file", &e))
+    }
+
+    fn checksum(&mut self, path: &str, length: u64) -> Option<String> {
+        let command = format!("head -c {} -- {} | sha256sum", length, shell_quote(path));
+        match self.send_ssh_command(&command, |_, _| ()) {
+			// sha256sum prints the checksum followed by the name of the file, `-` for its input
+            Ok(result) if result.exit_status == 0 => result.stdout.split_whitespace().next()
+                .filter(|checksum| checksum.len() == 64)
+                .map(|checksum| checksum.to_lowercase()),
+			// The exec channel is not available or the tools are missing
+            _ => None,
+        }
+    }
+
+    fn copy_file(&mut self, from: &str, to: &str) -> Result<(), BackendError> {
+		// Both files can be open on the same SFTP session, so stream directly from one into the other
+        let sftp = self.sftp()?;
+        let mut source = sftp.open(Path::new(from)).map_err(|e| BackendError::ssh(&format!("Error opening remote file {}", from), &e))?;
+        let mut target = sftp.create(Path::new(to)).map_err(|e| BackendError::ssh(&format!("Error creating remote file {}", to), &e))?;
+        io::copy(&mut source, &mut target).map(|_| ()).map_err(|e| BackendError::io("Error copying remote file", &e))
+    }
+
+    fn copy_symlink(&mut self, from: &str, to: &str) -> Result<(), BackendError> {
+        let sftp = self.sftp()?;
+        let target = sftp.readlink(Path::new(from)).map_err(|e| BackendError::ssh(&format!("Error reading symbolic link {}", from), &e))?;
+        sftp.symlink(&target, Path::new(to)).map_err(|e| BackendError::ssh(&format!("Error creating symbolic link {}", to), &e))
+    }
+}
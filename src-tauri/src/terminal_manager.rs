@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::connection_manager::{Connection, Protocol};
+use crate::error::BackendError;
+use crate::operations::{run_blocking, Operation};
+use crate::remote_fs::RemoteFs;
+use crate::ssh_instance::{retry, SSHInstance};
+
+/// How long the terminal thread sleeps when there is neither output nor input.
+const IDLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Input sent from the commands to the thread of a terminal.
+enum TerminalInput {
+	Data(Vec<u8>),
+	Resize(u32, u32),
+	Close,
+}
+
+/// Output of a terminal, emitted as the `terminal-output` event.
+#[derive(Clone, serde::Serialize)]
+pub struct TerminalOutput {
+	id: u64,
+	data: String,
+}
+
+/// Emitted as `terminal-closed` once the shell of a terminal has ended.
+#[derive(Clone, serde::Serialize)]
+pub struct TerminalClosed {
+	id: u64,
+	error: Option<BackendError>,
+}
+
+/// Keeps track of the open terminals, every terminal tab has its own id and SSH session.
+#[derive(Default)]
+pub struct TerminalManager {
+	next_id: AtomicU64,
+	terminals: Mutex<HashMap<u64, Sender<TerminalInput>>>,
+}
+
+impl TerminalManager {
+	/// Creates a terminal manager without any open terminals.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sends input to the thread of a terminal.
+	fn send(&self, id: u64, input: TerminalInput) -> Result<(), BackendError> {
+		let terminals = self.terminals.lock().map_err(|e| BackendError::other(format!("Terminal state is poisoned: {:?}", e)))?;
+		match terminals.get(&id) {
+			Some(sender) => sender.send(input).map_err(|_| BackendError::other(format!("Terminal {} is closed", id))),
+			None => Err(BackendError::not_found(format!("No terminal with id: {}", id))),
+		}
+	}
+}
+
+/// Decodes a chunk of terminal output, keeping an incomplete UTF-8 sequence at its end for the next chunk.
+///
+/// # Arguments
+///
+/// * `pending` - Bytes left over from the previous chunk, the new chunk is appended to it.
+/// * `data` - The new chunk.
+fn decode_output(pending: &mut Vec<u8>, data: &[u8]) -> String {
+	pending.extend_from_slice(data);
+	let complete = match std::str::from_utf8(pending) {
+		Ok(_) => pending.len(),
+		// An error without length means the input ended in the middle of a character
+		Err(e) if e.error_len().is_none() => e.valid_up_to(),
+		Err(_) => pending.len(),
+	};
+	let output = String::from_utf8_lossy(&pending[..complete]).to_string();
+	pending.drain(..complete);
+	output
+}
+
+/// Pumps data between the shell channel and the frontend until the shell ends or the terminal is closed.
+///
+/// The session is in non-blocking mode, so reading output never blocks writing input.
+fn run_terminal(app: &AppHandle, id: u64, channel: &mut ssh2::Channel, input: Receiver<TerminalInput>) -> Result<(), BackendError> {
+	let mut buffer = [0; 8192];
+	let mut pending = Vec::new();
+	loop {
+		let mut idle = true;
+
+		// Forward everything the shell printed
+		match channel.read(&mut buffer) {
+			Ok(0) => {
+				if channel.eof() {
+					return Ok(());
+				}
+			}
+			Ok(n) => {
+				idle = false;
+				let _ = app.emit_all("terminal-output", TerminalOutput { id, data: decode_output(&mut pending, &buffer[..n]) });
+			}
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+			Err(e) => return Err(BackendError::io("Error reading terminal output", &e)),
+		}
+
+		// Handle input from the frontend
+		loop {
+			match input.try_recv() {
+				Ok(TerminalInput::Data(data)) => {
+					idle = false;
+					let mut written = 0;
+					while written < data.len() {
+						written += retry(|| channel.write(&data[written..])).map_err(|e| BackendError::io("Error writing terminal input", &e))?;
+					}
+					retry(|| channel.flush()).map_err(|e| BackendError::io("Error writing terminal input", &e))?;
+				}
+				Ok(TerminalInput::Resize(cols, rows)) => {
+					retry(|| channel.request_pty_size(cols, rows, None, None).map_err(io::Error::from))
+						.map_err(|e| BackendError::io("Error resizing terminal", &e))?;
+				}
+				Ok(TerminalInput::Close) | Err(TryRecvError::Disconnected) => {
+					let _ = retry(|| channel.close().map_err(io::Error::from));
+					return Ok(());
+				}
+				Err(TryRecvError::Empty) => break,
+			}
+		}
+
+		if idle {
+			std::thread::sleep(IDLE_INTERVAL);
+		}
+	}
+}
+
+/// Opens an interactive shell with a PTY on its own SSH session.
+///
+/// Output is streamed as `terminal-output` events, a `terminal-closed` event is emitted once the shell ends.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to connect on the blocking thread pool and to emit the events.
+/// * `options` - The connection to open the shell on.
+/// * `cols` - The width of the terminal in characters.
+/// * `rows` - The height of the terminal in characters.
+/// * `operation_id` - An id to cancel connecting with, see `cancel_operation`.
+///
+/// # Returns
+///
+/// The id of the terminal, used for input, resizing and in the events.
+#[tauri::command]
+pub async fn open_terminal(app: AppHandle, options: Connection, cols: u32, rows: u32, operation_id: Option<String>) -> Result<u64, BackendError> {
+	if Protocol::try_from(options.protocol)? != Protocol::Sftp {
+		return Err(BackendError::invalid_input("Terminals are only supported over SSH"));
+	}
+	run_blocking(app, operation_id, move |app, operation| start_terminal(app, options, cols, rows, operation)).await
+}
+
+/// Connects, starts the shell and spawns the thread of a terminal, see `open_terminal`.
+fn start_terminal(app: &AppHandle, options: Connection, cols: u32, rows: u32, operation: &Operation) -> Result<u64, BackendError> {
+	// Start the shell before spawning the thread so errors reach the caller
+	let instance = SSHInstance::connect(options)?;
+	operation.attach(instance.socket());
+	let mut channel = instance.session.channel_session().map_err(|e| BackendError::ssh("Error creating channel", &e))?;
+	channel.request_pty("xterm-256color", None, Some((cols, rows, 0, 0))).map_err(|e| BackendError::ssh("Error requesting PTY", &e))?;
+	channel.shell().map_err(|e| BackendError::ssh("Error starting shell", &e))?;
+	instance.session.set_blocking(false);
+
+	let terminals = app.state::<TerminalManager>();
+	let id = terminals.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+	let (sender, receiver) = mpsc::channel();
+	terminals.terminals.lock().map_err(|e| BackendError::other(format!("Terminal state is poisoned: {:?}", e)))?.insert(id, sender);
+
+	let app = app.clone();
+	std::thread::spawn(move || {
+		// The session has to stay alive as long as its channel is used
+		let _instance = instance;
+		let result = run_terminal(&app, id, &mut channel, receiver);
+
+		if let Ok(mut terminals) = app.state::<TerminalManager>().terminals.lock() {
+			terminals.remove(&id);
+		}
+		let _ = app.emit_all("terminal-closed", TerminalClosed { id, error: result.err() });
+	});
+	Ok(id)
+}
+
+/// Writes input, e.g. typed keys, to the shell of a terminal.
+#[tauri::command]
+pub fn write_terminal(id: u64, data: String, terminals: State<TerminalManager>) -> Result<(), BackendError> {
+	terminals.send(id, TerminalInput::Data(data.into_bytes()))
+}
+
+/// Changes the size of the PTY of a terminal.
+#[tauri::command]
+pub fn resize_terminal(id: u64, cols: u32, rows: u32, terminals: State<TerminalManager>) -> Result<(), BackendError> {
+	terminals.send(id, TerminalInput::Resize(cols, rows))
+}
+
+/// Closes the shell of a terminal, a `terminal-closed` event follows once it has ended.
+#[tauri::command]
+pub fn close_terminal(id: u64, terminals: State<TerminalManager>) -> Result<(), BackendError> {
+	terminals.send(id, TerminalInput::Close)
+}
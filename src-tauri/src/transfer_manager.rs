@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::auth_prompt;
+use crate::connection_manager::{Connection, Protocol};
+use crate::error::BackendError;
+use crate::operations::Operation;
+use crate::remote_fs::{join_remote, with_remote_fs, RemoteFs};
+use crate::session_manager::SessionManager;
+
+/// How often progress events are emitted while a file is being transferred.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress of a running transfer, emitted as the `transfer-progress` event.
+#[derive(Clone, serde::Serialize)]
+pub struct TransferProgress {
+	id: u64,
+	path: String,
+	file_bytes: u64,
+	file_size: u64,
+	transferred_bytes: u64,
+	total_bytes: u64,
+	files_done: usize,
+	files_total: usize,
+	/// The remote paths that are left out, links to directories are not followed as they may point back up the tree.
+	skipped: Vec<String>,
+}
+
+/// Outcome of a transfer, emitted as the `transfer-finished` event.
+#[derive(Clone, serde::Serialize)]
+pub struct TransferFinished {
+	id: u64,
+	cancelled: bool,
+	error: Option<BackendError>,
+}
+
+/// Keeps track of the running transfers so they can be cancelled.
+#[derive(Default)]
+pub struct TransferManager {
+	next_id: AtomicU64,
+	operations: Mutex<HashMap<u64, Arc<Operation>>>,
+}
+
+impl TransferManager {
+	/// Creates a transfer manager without any running transfers.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Registers a new transfer and returns its id and the operation it runs as.
+	fn start(&self) -> (u64, Arc<Operation>) {
+		let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+		let operation = Arc::new(Operation::default());
+		if let Ok(mut operations) = self.operations.lock() {
+			operations.insert(id, operation.clone());
+		}
+		(id, operation)
+	}
+
+	/// Removes a transfer once it has finished.
+	fn finish(&self, id: u64) {
+		if let Ok(mut operations) = self.operations.lock() {
+			operations.remove(&id);
+		}
+	}
+
+	/// Cancels a running transfer.
+	///
+	/// # Returns
+	///
+	/// `true` if the transfer was running, otherwise `false`.
+	pub fn cancel(&self, id: u64) -> bool {
+		let operation = match self.operations.lock() {
+			Ok(operations) => operations.get(&id).cloned(),
+			Err(_) => None,
+		};
+		match operation {
+			Some(operation) => {
+				operation.cancel();
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+/// A single file of a transfer.
+struct TransferItem {
+	source: String,
+	target: String,
+	size: u64,
+}
+
+/// Everything that has to be created and copied for a transfer, collected before the first byte is sent
+/// so the total size is known up front.
+#[derive(Default)]
+struct TransferPlan {
+	directories: Vec<String>,
+	files: Vec<TransferItem>,
+	skipped: Vec<String>,
+	total_bytes: u64,
+}
+
+/// Tracks the progress of a transfer and emits it to the frontend.
+struct Progress<'a> {
+	app: &'a AppHandle,
+	id: u64,
+	total_bytes: u64,
+	transferred_bytes: u64,
+	files_total: usize,
+	files_done: usize,
+	skipped: Vec<String>,
+	/// The file that is being transferred and how much of it is done.
+	path: String,
+	file_bytes: u64,
+	file_size: u64,
+	last_emit: Instant,
+}
+
+impl Progress<'_> {
+	/// Takes the totals of a plan, before the first file is transferred.
+	fn plan(&mut self, plan: &TransferPlan) {
+		self.total_bytes = plan.total_bytes;
+		self.files_total = plan.files.len();
+		self.skipped = plan.skipped.clone();
+	}
+
+	/// Emits the current progress, unless one was emitted recently and `force` is not set.
+	fn emit(&mut self, force: bool) {
+		if !force && self.last_emit.elapsed() < PROGRESS_INTERVAL {
+			return;
+		}
+		self.last_emit = Instant::now();
+		let _ = self.app.emit_all("transfer-progress", TransferProgress {
+			id: self.id,
+			path: self.path.clone(),
+			file_bytes: self.file_bytes,
+			file_size: self.file_size,
+			transferred_bytes: self.transferred_bytes,
+			total_bytes: self.total_bytes,
+			files_done: self.files_done,
+			files_total: self.files_total,
+			skipped: self.skipped.clone(),
+		});
+	}
+
+	/// Starts reporting the progress of the next file.
+	fn start_file(&mut self, file: &TransferItem) {
+		self.path = file.source.clone();
+		self.file_bytes = 0;
+		self.file_size = file.size;
+		self.emit(true);
+	}
+
+	/// Counts bytes of the current file.
+	fn advance(&mut self, bytes: usize) {
+		self.file_bytes += bytes as u64;
+		self.transferred_bytes += bytes as u64;
+		self.emit(false);
+	}
+
+	/// Marks the current file as done.
+	fn finish_file(&mut self) {
+		self.files_done += 1;
+		self.emit(true);
+	}
+}
+
+/// A reader or writer that reports every chunk passing through it as progress of the current file.
+struct Tracked<'p, 'a, T> {
+	progress: &'p mut Progress<'a>,
+	inner: T,
+}
+
+impl<T: Read> Read for Tracked<'_, '_, T> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.progress.advance(read);
+		Ok(read)
+	}
+}
+
+impl<T: Write> Write for Tracked<'_, '_, T> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.progress.advance(written);
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// Collects the directories and files below a remote path.
+///
+/// # Arguments
+///
+/// * `remote` - The backend to read the remote tree with.
+/// * `remote_path` - The remote file or directory to download.
+/// * `local_path` - The local path the remote path is mapped to.
+/// * `plan` - The plan to add the entries to.
+fn plan_download(remote: &mut dyn RemoteFs, remote_path: &str, local_path: &Path, plan: &mut TransferPlan) -> Result<(), BackendError> {
+	let file = remote.stat(remote_path)?;
+	if !file.is_dir {
+		plan.total_bytes += file.size;
+		plan.files.push(TransferItem {
+			source: remote_path.to_string(),
+			target: local_path.to_string_lossy().to_string(),
+			size: file.size,
+		});
+		return Ok(());
+	}
+
+	plan.directories.push(local_path.to_string_lossy().to_string());
+	for entry in remote.list(remote_path, true)? {
+		// Do not follow symlinks to directories, they may point back up the tree, and skip broken links
+		if entry.is_symlink && remote.stat(&entry.path).map(|target| target.is_dir).unwrap_or(true) {
+			plan.skipped.push(entry.path);
+			continue;
+		}
+		plan_download(remote, &entry.path, &local_path.join(&entry.filename), plan)?;
+	}
+	Ok(())
+}
+
+/// Collects the directories and files below a local path.
+///
+/// # Arguments
+///
+/// * `local_path` - The local file or directory to upload.
+/// * `remote_path` - The remote path the local path is mapped to.
+/// * `plan` - The plan to add the entries to.
+fn plan_upload(local_path: &Path, remote_path: &str, plan: &mut TransferPlan) -> Result<(), BackendError> {
+	let metadata = fs::metadata(local_path).map_err(|e| BackendError::io(&format!("Error reading {}", local_path.display()), &e))?;
+	if !metadata.is_dir() {
+		plan.total_bytes += metadata.len();
+		plan.files.push(TransferItem {
+			source: local_path.to_string_lossy().to_string(),
+			target: remote_path.to_string(),
+			size: metadata.len(),
+		});
+		return Ok(());
+	}
+
+	plan.directories.push(remote_path.to_string());
+	let entries = fs::read_dir(local_path).map_err(|e| BackendError::io(&format!("Error opening directory {}", local_path.display()), &e))?;
+	for entry in entries {
+		let entry = entry.map_err(|e| BackendError::io(&format!("Error reading directory {}", local_path.display()), &e))?;
+		let name = entry.file_name().to_string_lossy().to_string();
+		plan_upload(&entry.path(), &join_remote(remote_path, &name), plan)?;
+	}
+	Ok(())
+}
+
+/// Downloads a remote file or directory tree on a pooled session.
+fn run_download(progress: &mut Progress, operation: &Operation, options: Connection, remote_path: &str, local_path: &str) -> Result<(), BackendError> {
+	let sessions = progress.app.state::<SessionManager>();
+	with_remote_fs(&options, &sessions, |remote| {
+		operation.attach(remote.socket());
+
+		// Collect the tree first so the total size is known
+		let mut plan = TransferPlan::default();
+		plan_download(remote, remote_path, &PathBuf::from(local_path), &mut plan)?;
+		progress.plan(&plan);
+
+		// Recreate the directory structure and copy every file
+		for directory in &plan.directories {
+			fs::create_dir_all(directory).map_err(|e| BackendError::io(&format!("Error creating directory {}", directory), &e))?;
+		}
+		for file in &plan.files {
+			let local_file = fs::File::create(&file.target).map_err(|e| BackendError::io(&format!("Error creating local file {}", file.target), &e))?;
+			progress.start_file(file);
+			remote.read(&file.source, &mut Tracked { progress, inner: operation.guard(local_file) })?;
+			progress.finish_file();
+		}
+		Ok(())
+	})
+}
+
+/// Uploads a local file or directory tree on a pooled session.
+fn run_upload(progress: &mut Progress, operation: &Operation, options: Connection, local_path: &str, remote_path: &str) -> Result<(), BackendError> {
+	let sessions = progress.app.state::<SessionManager>();
+	with_remote_fs(&options, &sessions, |remote| {
+		operation.attach(remote.socket());
+
+		// Collect the tree first so the total size is known
+		let mut plan = TransferPlan::default();
+		plan_upload(Path::new(local_path), remote_path, &mut plan)?;
+		progress.plan(&plan);
+
+		// Recreate the directory structure, directories that already exist are reused
+		for directory in &plan.directories {
+			if remote.stat(directory).is_err() {
+				remote.mkdir(directory)?;
+			}
+		}
+		for file in &plan.files {
+			let local_file = fs::File::open(&file.source).map_err(|e| BackendError::io(&format!("Error opening local file {}", file.source), &e))?;
+			progress.start_file(file);
+			remote.write(&file.target, &mut Tracked { progress, inner: operation.guard(local_file) })?;
+			progress.finish_file();
+		}
+		Ok(())
+	})
+}
+
+/// Runs a transfer on a background thread and emits `transfer-finished` once it is done.
+///
+/// # Returns
+///
+/// The id of the transfer, used in the progress events and to cancel it.
+fn spawn_transfer<F>(app: AppHandle, transfers: &TransferManager, transfer: F) -> u64
+where
+	F: FnOnce(&mut Progress, &Operation) -> Result<(), BackendError> + Send + 'static,
+{
+	let (id, operation) = transfers.start();
+	std::thread::spawn(move || {
+		let mut progress = Progress {
+			app: &app,
+			id,
+			total_bytes: 0,
+			transferred_bytes: 0,
+			files_total: 0,
+			files_done: 0,
+			skipped: Vec::new(),
+			path: String::new(),
+			file_bytes: 0,
+			file_size: 0,
+			last_emit: Instant::now(),
+		};
+		let result = auth_prompt::allow_prompts(|| transfer(&mut progress, &operation));
+
+		// Report the outcome, an interrupted transfer fails with whatever error the cancelled call ran into
+		app.state::<TransferManager>().finish(id);
+		let cancelled = operation.is_cancelled();
+		let _ = app.emit_all("transfer-finished", TransferFinished {
+			id,
+			cancelled,
+			error: if cancelled { None } else { result.err() },
+		});
+	});
+	id
+}
+
+/// Downloads a remote file or directory, recreating the directory structure locally.
+///
+/// Progress is reported through `transfer-progress` events and the outcome through a `transfer-finished` event.
+///
+/// # Arguments
+///
+/// * `remote_path` - The remote file or directory to download.
+/// * `local_path` - The local path to download to.
+/// * `options` - The connection to download from.
+///
+/// # Returns
+///
+/// The id of the started transfer.
+#[tauri::command]
+pub fn download_path(app: AppHandle, remote_path: String, local_path: String, options: Connection, transfers: State<TransferManager>) -> Result<u64, BackendError> {
+	// Fail right away for an unknown protocol instead of through the finished event
+	Protocol::try_from(options.protocol)?;
+	Ok(spawn_transfer(app, &transfers, move |progress, operation| run_download(progress, operation, options, &remote_path, &local_path)))
+}
+
+/// Uploads a local file or directory, recreating the directory structure on the server.
+///
+/// Progress is reported through `transfer-progress` events and the outcome through a `transfer-finished` event.
+///
+/// # Arguments
+///
+/// * `local_path` - The local file or directory to upload.
+/// * `remote_path` - The remote path to upload to.
+/// * `options` - The connection to upload to.
+///
+/// # Returns
+///
+/// The id of the started transfer.
+#[tauri::command]
+pub fn upload_path(app: AppHandle, local_path: String, remote_path: String, options: Connection, transfers: State<TransferManager>) -> Result<u64, BackendError> {
+	// Fail right away for an unknown protocol instead of through the finished event
+	Protocol::try_from(options.protocol)?;
+	Ok(spawn_transfer(app, &transfers, move |progress, operation| run_upload(progress, operation, options, &local_path, &remote_path)))
+}
+
+/// Cancels a running transfer, a call that is blocked on the server is interrupted.
+///
+/// # Returns
+///
+/// `true` if the transfer was running, otherwise `false`.
+#[tauri::command]
+pub fn cancel_transfer(id: u64, transfers: State<TransferManager>) -> bool {
+	transfers.cancel(id)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::remote_fs::tests::{Entry, MemoryFs};
+
+	#[test]
+	fn links_to_directories_are_skipped() {
+		let mut remote = MemoryFs::with(&[
+			("/tree", Entry::Directory),
+			("/tree/broken", Entry::Link("/missing".to_string())),
+			("/tree/file-link", Entry::Link("/tree/file.txt".to_string())),
+			("/tree/file.txt", Entry::File(b"contents".to_vec())),
+			("/tree/loop", Entry::Link("/tree".to_string())),
+		]);
+		let mut plan = TransferPlan::default();
+		plan_download(&mut remote, "/tree", Path::new("local"), &mut plan).unwrap();
+
+		let sources: Vec<&str> = plan.files.iter().map(|file| file.source.as_str()).collect();
+		assert_eq!(sources, ["/tree/file-link", "/tree/file.txt"]);
+		assert_eq!(plan.skipped, ["/tree/broken", "/tree/loop"]);
+		assert_eq!(plan.total_bytes, 16);
+	}
+}
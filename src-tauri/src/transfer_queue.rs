@@ -0,0 +1,713 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::app_settings;
+use crate::auth_prompt;
+use crate::connection_manager::{self, get_database_path, Connection, Protocol};
+use crate::error::BackendError;
+use crate::ftp_instance::FTPInstance;
+use crate::logger;
+use crate::operations::Operation;
+use crate::remote_fs::RemoteFs;
+use crate::resume::{self, ResumeCheck};
+use crate::session_manager::same_target;
+use crate::ssh_instance::SSHInstance;
+
+/// How often the progress of a running transfer is emitted.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long to wait before the first retry of a failed transfer, doubled for every further attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// The longest wait between two attempts of a failed transfer.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Whether a transfer copies a remote file to the local machine or the other way around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+	Download,
+	Upload,
+}
+
+impl TransferDirection {
+	/// The name the direction is stored with in the transfer history.
+	fn as_str(&self) -> &'static str {
+		match self {
+			TransferDirection::Download => "download",
+			TransferDirection::Upload => "upload",
+		}
+	}
+}
+
+/// Where a transfer is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferState {
+	/// Waiting for a free slot of its connection, or for the delay before the next retry.
+	Queued,
+	Running,
+	/// Stopped by the user, it only runs again once it is resumed.
+	Paused,
+	/// Failed more often than it is retried.
+	Failed,
+	Done,
+	Cancelled,
+}
+
+impl TransferState {
+	/// The name the state is stored with in the transfer history.
+	fn as_str(&self) -> &'static str {
+		match self {
+			TransferState::Queued => "queued",
+			TransferState::Running => "running",
+			TransferState::Paused => "paused",
+			TransferState::Failed => "failed",
+			TransferState::Done => "done",
+			TransferState::Cancelled => "cancelled",
+		}
+	}
+
+	/// Checks whether the transfer will not run again, such transfers are recorded in the history.
+	fn is_finished(&self) -> bool {
+		matches!(self, TransferState::Failed | TransferState::Done | TransferState::Cancelled)
+	}
+}
+
+/// A single file to transfer, as passed to `enqueue_transfers`.
+#[derive(serde::Deserialize)]
+pub struct TransferRequest {
+	direction: TransferDirection,
+	source: String,
+	target: String,
+	/// Whether to continue a partial target file left behind by an earlier transfer, and how to check it.
+	#[serde(default)]
+	resume: Option<ResumeCheck>,
+}
+
+/// A transfer in the queue, emitted as the `transfer-queue-changed` event whenever it changes.
+#[derive(Clone, serde::Serialize)]
+pub struct QueuedTransfer {
+	id: u64,
+	connection_id: i32,
+	direction: TransferDirection,
+	source: String,
+	target: String,
+	state: TransferState,
+	/// How often the transfer was started, retries included.
+	attempts: u32,
+	transferred_bytes: u64,
+	total_bytes: u64,
+	/// The error of the last attempt, kept while the transfer waits for its retry.
+	error: Option<BackendError>,
+}
+
+/// A queued transfer together with what is needed to run and control it.
+struct Entry {
+	transfer: QueuedTransfer,
+	options: Connection,
+	/// How to continue the partial target file, set once an attempt was interrupted as the partial file is ours then.
+	resume: Option<ResumeCheck>,
+	/// When a failed transfer may be retried, `None` if it may start right away.
+	retry_at: Option<Instant>,
+	/// The operation of the running attempt, cancelling it interrupts the transfer.
+	operation: Option<Arc<Operation>>,
+	/// Whether the running attempt was interrupted to pause or to cancel the transfer.
+	stop: Option<TransferState>,
+}
+
+impl Entry {
+	/// Checks whether the transfer may start now.
+	fn is_ready(&self, now: Instant) -> bool {
+		self.transfer.state == TransferState::Queued && self.retry_at.is_none_or(|retry_at| retry_at <= now)
+	}
+}
+
+/// The transfers of the queue in the order they run in, and the number of workers per connection.
+#[derive(Default)]
+struct QueueState {
+	entries: Vec<Entry>,
+	workers: HashMap<i32, usize>,
+}
+
+impl QueueState {
+	fn entry_mut(&mut self, id: u64) -> Option<&mut Entry> {
+		self.entries.iter_mut().find(|entry| entry.transfer.id == id)
+	}
+}
+
+/// A transfer a worker claimed from the queue.
+struct Job {
+	id: u64,
+	options: Connection,
+	direction: TransferDirection,
+	source: String,
+	target: String,
+	resume: Option<ResumeCheck>,
+	operation: Arc<Operation>,
+}
+
+/// Queue of single file transfers, run by a limited number of workers per connection.
+///
+/// Every worker opens its own session and keeps it for the transfers it runs one after another, so a
+/// queue of many small files does not connect for every file and does not block the pooled sessions
+/// the panes use.
+#[derive(Default)]
+pub struct TransferQueue {
+	next_id: AtomicU64,
+	state: Mutex<QueueState>,
+}
+
+impl TransferQueue {
+	/// Creates an empty transfer queue.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn lock(&self) -> Result<MutexGuard<'_, QueueState>, BackendError> {
+		self.state.lock().map_err(|e| BackendError::other(format!("Transfer queue is poisoned: {:?}", e)))
+	}
+
+	/// Takes the next transfer of a connection that may start, or stops the worker if there is none.
+	fn claim(&self, app: &AppHandle, connection_id: i32) -> Option<Job> {
+		let mut guard = self.state.lock().ok()?;
+		let state = &mut *guard;
+		let now = Instant::now();
+		match state.entries.iter_mut().find(|entry| entry.transfer.connection_id == connection_id && entry.is_ready(now)) {
+			Some(entry) => {
+				let operation = Arc::new(Operation::default());
+				entry.operation = Some(operation.clone());
+				entry.retry_at = None;
+				entry.transfer.state = TransferState::Running;
+				entry.transfer.attempts += 1;
+				entry.transfer.transferred_bytes = 0;
+				emit(app, &entry.transfer);
+				Some(Job {
+					id: entry.transfer.id,
+					options: entry.options.clone(),
+					direction: entry.transfer.direction,
+					source: entry.transfer.source.clone(),
+					target: entry.transfer.target.clone(),
+					resume: entry.resume,
+					operation,
+				})
+			}
+			None => {
+				// Deciding to stop happens under the same lock new transfers are added with, so none is missed
+				if let Some(workers) = state.workers.get_mut(&connection_id) {
+					*workers = workers.saturating_sub(1);
+				}
+				None
+			}
+		}
+	}
+
+	/// Updates the progress of a running transfer.
+	fn progress(&self, app: &AppHandle, id: u64, transferred_bytes: u64, total_bytes: u64) {
+		if let Ok(mut state) = self.state.lock() {
+			if let Some(entry) = state.entry_mut(id) {
+				entry.transfer.transferred_bytes = transferred_bytes;
+				entry.transfer.total_bytes = total_bytes;
+				emit(app, &entry.transfer);
+			}
+		}
+	}
+
+	/// Stores the outcome of an attempt, schedules a retry if it failed and records finished transfers.
+	///
+	/// # Arguments
+	///
+	/// * `app` - The application handle, used to emit the changed transfer.
+	/// * `job` - The attempt that ended.
+	/// * `result` - The number of transferred bytes, or why the attempt failed.
+	/// * `touched` - Whether the attempt started writing the target, so a retry has a partial file to continue.
+	fn finish(&self, app: &AppHandle, job: Job, result: Result<u64, BackendError>, touched: bool) {
+		let max_retries = app_settings::transfer_settings().max_retries;
+		let mut finished = None;
+		let mut retry = None;
+		if let Ok(mut state) = self.state.lock() {
+			let entry = match state.entry_mut(job.id) {
+				Some(entry) => entry,
+				None => return,
+			};
+			entry.operation = None;
+			let stop = entry.stop.take();
+			// The next attempt continues where this one stopped, a target that was never written is not checked
+			if touched {
+				entry.resume = Some(entry.resume.unwrap_or(ResumeCheck::Tail));
+			}
+			let transfer = &mut entry.transfer;
+			match result {
+				Ok(bytes) => {
+					transfer.state = TransferState::Done;
+					transfer.transferred_bytes = bytes;
+					transfer.error = None;
+				}
+				// An interrupted transfer fails with whatever error the closed socket caused
+				Err(_) if stop == Some(TransferState::Cancelled) => transfer.state = TransferState::Cancelled,
+				Err(_) if stop == Some(TransferState::Paused) => transfer.state = TransferState::Paused,
+				Err(e) if transfer.attempts <= max_retries => {
+					let delay = retry_delay(transfer.attempts);
+					transfer.state = TransferState::Queued;
+					transfer.error = Some(e);
+					entry.retry_at = Some(Instant::now() + delay);
+					retry = Some(delay);
+				}
+				Err(e) => {
+					transfer.state = TransferState::Failed;
+					transfer.error = Some(e);
+				}
+			}
+			emit(app, &entry.transfer);
+			if entry.transfer.state.is_finished() {
+				finished = Some(entry.transfer.clone());
+			}
+		}
+
+		if let Some(transfer) = finished {
+			if let Err(e) = record_history(&transfer) {
+				let _ = logger::log(&format!("Failed to record transfer {} in the history: {}", transfer.id, e), "", logger::LOG_WARN);
+			}
+		}
+		if let Some(delay) = retry {
+			schedule_after(app.clone(), delay);
+		}
+	}
+}
+
+/// Emits a changed transfer to the frontend.
+fn emit(app: &AppHandle, transfer: &QueuedTransfer) {
+	let _ = app.emit_all("transfer-queue-changed", transfer.clone());
+}
+
+/// How long to wait before the next attempt, doubling with every attempt up to `MAX_RETRY_DELAY`.
+fn retry_delay(attempts: u32) -> Duration {
+	RETRY_DELAY.saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1))).min(MAX_RETRY_DELAY)
+}
+
+/// Starts workers for every connection that has transfers that may start and free slots.
+fn schedule(app: &AppHandle) {
+	let limit = app_settings::transfer_settings().parallel_transfers.max(1);
+	let queue = app.state::<TransferQueue>();
+	let mut guard = match queue.state.lock() {
+		Ok(guard) => guard,
+		Err(_) => return,
+	};
+	let state = &mut *guard;
+
+	// Count the transfers that may start per connection, connections earlier in the queue come first
+	let now = Instant::now();
+	let mut ready: Vec<(i32, usize)> = Vec::new();
+	for entry in state.entries.iter().filter(|entry| entry.is_ready(now)) {
+		match ready.iter_mut().find(|(connection_id, _)| *connection_id == entry.transfer.connection_id) {
+			Some((_, count)) => *count += 1,
+			None => ready.push((entry.transfer.connection_id, 1)),
+		}
+	}
+
+	for (connection_id, count) in ready {
+		let workers = state.workers.entry(connection_id).or_default();
+		let spawn = limit.saturating_sub(*workers).min(count);
+		*workers += spawn;
+		for _ in 0..spawn {
+			spawn_worker(app.clone(), connection_id);
+		}
+	}
+}
+
+/// Schedules the queue again once a failed transfer may be retried.
+fn schedule_after(app: AppHandle, delay: Duration) {
+	std::thread::spawn(move || {
+		std::thread::sleep(delay);
+		schedule(&app);
+	});
+}
+
+/// Runs the transfers of a connection one after another on a background thread, until none is left.
+fn spawn_worker(app: AppHandle, connection_id: i32) {
+	std::thread::spawn(move || {
+		let queue = app.state::<TransferQueue>();
+		let mut session = None;
+		while let Some(job) = queue.claim(&app, connection_id) {
+			let mut touched = false;
+			let result = run_job(&app, &mut session, &job, &mut touched);
+			// The session may be broken after a failed or interrupted transfer, the next one reconnects
+			if result.is_err() {
+				session = None;
+			}
+			queue.finish(&app, job, result, touched);
+		}
+	});
+}
+
+/// Opens a dedicated session for a worker.
+fn connect(options: &Connection) -> Result<Box<dyn RemoteFs + Send>, BackendError> {
+	match Protocol::try_from(options.protocol)? {
+		Protocol::Sftp => Ok(Box::new(SSHInstance::connect(options.clone())?)),
+		Protocol::Ftp | Protocol::Ftps => Ok(Box::new(FTPInstance::connect(options)?)),
+	}
+}
+
+/// Runs a single attempt of a transfer.
+///
+/// # Arguments
+///
+/// * `app` - The application handle, used to report progress.
+/// * `session` - The session of the worker, opened or replaced if it does not match the connection of the job.
+/// * `job` - The transfer to run.
+/// * `touched` - Set once the target is opened for writing.
+///
+/// # Returns
+///
+/// The number of bytes that were transferred.
+fn run_job(app: &AppHandle, session: &mut Option<(Connection, Box<dyn RemoteFs + Send>)>, job: &Job, touched: &mut bool) -> Result<u64, BackendError> {
+	let remote = match session {
+		Some((options, remote)) if same_target(options, &job.options) => remote,
+		_ => {
+			// Workers run on their own thread, so the user can be asked to authenticate
+			let remote = auth_prompt::allow_prompts(|| connect(&job.options))?;
+			&mut session.insert((job.options.clone(), remote)).1
+		}
+	};
+	job.operation.attach(remote.socket());
+
+	match job.direction {
+		TransferDirection::Download => {
+			let file = remote.stat(&job.source)?;
+			if file.is_dir {
+				return Err(BackendError::invalid_input(format!("{} is a directory, the queue only transfers files", job.source)));
+			}
+			if let Some(parent) = Path::new(&job.target).parent() {
+				fs::create_dir_all(parent).map_err(|e| BackendError::io(&format!("Error creating directory {}", parent.display()), &e))?;
+			}
+			let offset = match job.resume {
+				Some(check) => resume::download_offset(remote.as_mut(), &job.source, &job.target, check)?,
+				None => 0,
+			};
+			*touched = true;
+			let local_file = resume::create_local_at(&job.target, offset)?;
+			let mut writer = Tracked::new(app, job.id, offset, file.size, job.operation.guard(local_file));
+			remote.read_range(&job.source, offset, None, &mut writer).map(|bytes| offset + bytes)
+		}
+		TransferDirection::Upload => {
+			let metadata = fs::metadata(&job.source).map_err(|e| BackendError::io(&format!("Error reading {}", job.source), &e))?;
+			if metadata.is_dir() {
+				return Err(BackendError::invalid_input(format!("{} is a directory, the queue only transfers files", job.source)));
+			}
+			let offset = match job.resume {
+				Some(check) => resume::upload_offset(remote.as_mut(), &job.source, &job.target, check)?,
+				None => 0,
+			};
+			let local_file = resume::open_local_at(&job.source, offset)?;
+			let mut reader = Tracked::new(app, job.id, offset, metadata.len(), job.operation.guard(local_file));
+			*touched = true;
+			remote.write_at(&job.target, offset, &mut reader).map(|bytes| offset + bytes)
+		}
+	}
+}
+
+/// A reader or writer that reports the progress of a transfer as data passes through it.
+struct Tracked<'a, T> {
+	app: &'a AppHandle,
+	id: u64,
+	transferred_bytes: u64,
+	total_bytes: u64,
+	last_emit: Instant,
+	inner: T,
+}
+
+impl<'a, T> Tracked<'a, T> {
+	fn new(app: &'a AppHandle, id: u64, transferred_bytes: u64, total_bytes: u64, inner: T) -> Self {
+		app.state::<TransferQueue>().progress(app, id, transferred_bytes, total_bytes);
+		Tracked {
+			app,
+			id,
+			transferred_bytes,
+			total_bytes,
+			last_emit: Instant::now(),
+			inner,
+		}
+	}
+
+	fn advance(&mut self, bytes: usize) {
+		self.transferred_bytes += bytes as u64;
+		if self.last_emit.elapsed() >= PROGRESS_INTERVAL {
+			self.last_emit = Instant::now();
+			self.app.state::<TransferQueue>().progress(self.app, self.id, self.transferred_bytes, self.total_bytes);
+		}
+	}
+}
+
+impl<T: Read> Read for Tracked<'_, T> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.advance(read);
+		Ok(read)
+	}
+}
+
+impl<T: Write> Write for Tracked<'_, T> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.advance(written);
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// A finished transfer as stored in the transfer history.
+#[derive(Clone, serde::Serialize)]
+pub struct TransferRecord {
+	id: i64,
+	connection_id: i32,
+	direction: String,
+	source: String,
+	target: String,
+	state: String,
+	bytes: u64,
+	attempts: u32,
+	error: Option<String>,
+	finished_at: String,
+}
+
+/// Stores a finished transfer in the transfer history.
+fn record_history(transfer: &QueuedTransfer) -> Result<(), BackendError> {
+	let lite = sqlite::open(get_database_path())?;
+	connection_manager::execute(
+		&lite,
+		"INSERT INTO `transfer_history` (`connection_id`, `direction`, `source`, `target`, `state`, `bytes`, `attempts`, `error`) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+		&[
+			(transfer.connection_id as i64).into(),
+			transfer.direction.as_str().into(),
+			transfer.source.as_str().into(),
+			transfer.target.as_str().into(),
+			transfer.state.as_str().into(),
+			(transfer.transferred_bytes as i64).into(),
+			(transfer.attempts as i64).into(),
+			transfer.error.as_ref().map(|e| e.to_string()).into(),
+		],
+	)
+}
+
+/// Adds files to the end of the transfer queue.
+///
+/// Changes of the transfers are reported through `transfer-queue-changed` events.
+///
+/// # Arguments
+///
+/// * `options` - The connection to transfer the files from or to.
+/// * `transfers` - The files to transfer, directories are not expanded.
+///
+/// # Returns
+///
+/// The ids of the queued transfers, in the order of `transfers`.
+#[tauri::command]
+pub fn enqueue_transfers(app: AppHandle, options: Connection, transfers: Vec<TransferRequest>, queue: State<TransferQueue>) -> Result<Vec<u64>, BackendError> {
+	// Reject unknown protocols now instead of retrying them later
+	Protocol::try_from(options.protocol)?;
+
+	let mut ids = Vec::with_capacity(transfers.len());
+	{
+		let mut state = queue.lock()?;
+		for request in transfers {
+			let id = queue.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+			let entry = Entry {
+				transfer: QueuedTransfer {
+					id,
+					connection_id: options.id,
+					direction: request.direction,
+					source: request.source,
+					target: request.target,
+					state: TransferState::Queued,
+					attempts: 0,
+					transferred_bytes: 0,
+					total_bytes: 0,
+					error: None,
+				},
+				options: options.clone(),
+				resume: request.resume,
+				retry_at: None,
+				operation: None,
+				stop: None,
+			};
+			emit(&app, &entry.transfer);
+			state.entries.push(entry);
+			ids.push(id);
+		}
+	}
+	schedule(&app);
+	Ok(ids)
+}
+
+/// Returns the transfers of the queue in the order they run in.
+#[tauri::command]
+pub fn get_transfer_queue(queue: State<TransferQueue>) -> Result<Vec<QueuedTransfer>, BackendError> {
+	Ok(queue.lock()?.entries.iter().map(|entry| entry.transfer.clone()).collect())
+}
+
+/// Pauses a queued or running transfer, a running transfer is interrupted and continues where it stopped once resumed.
+///
+/// # Returns
+///
+/// `true` if the transfer was paused, `false` if it is not queued or running.
+#[tauri::command]
+pub fn pause_transfer(app: AppHandle, id: u64, queue: State<TransferQueue>) -> Result<bool, BackendError> {
+	let mut state = queue.lock()?;
+	let entry = match state.entry_mut(id) {
+		Some(entry) => entry,
+		None => return Ok(false),
+	};
+	match entry.transfer.state {
+		TransferState::Queued => {
+			entry.transfer.state = TransferState::Paused;
+			emit(&app, &entry.transfer);
+			Ok(true)
+		}
+		// The worker reports the transfer as paused once the interrupted attempt returned
+		TransferState::Running if entry.stop.is_none() => {
+			entry.stop = Some(TransferState::Paused);
+			if let Some(operation) = &entry.operation {
+				operation.cancel();
+			}
+			Ok(true)
+		}
+		_ => Ok(false),
+	}
+}
+
+/// Queues a paused or failed transfer again, failed transfers get a fresh set of retries.
+///
+/// # Returns
+///
+/// `true` if the transfer was queued again, `false` if it is not paused or failed.
+#[tauri::command]
+pub fn resume_transfer(app: AppHandle, id: u64, queue: State<TransferQueue>) -> Result<bool, BackendError> {
+	{
+		let mut state = queue.lock()?;
+		let entry = match state.entry_mut(id) {
+			Some(entry) => entry,
+			None => return Ok(false),
+		};
+		match entry.transfer.state {
+			TransferState::Paused => (),
+			TransferState::Failed => entry.transfer.attempts = 0,
+			_ => return Ok(false),
+		}
+		entry.transfer.state = TransferState::Queued;
+		entry.retry_at = None;
+		emit(&app, &entry.transfer);
+	}
+	schedule(&app);
+	Ok(true)
+}
+
+/// Cancels a queued, paused or running transfer, a partially transferred file is left as it is.
+///
+/// # Returns
+///
+/// `true` if the transfer was cancelled, `false` if it already finished.
+#[tauri::command]
+pub fn cancel_queued_transfer(app: AppHandle, id: u64, queue: State<TransferQueue>) -> Result<bool, BackendError> {
+	let cancelled = {
+		let mut state = queue.lock()?;
+		let entry = match state.entry_mut(id) {
+			Some(entry) => entry,
+			None => return Ok(false),
+		};
+		match entry.transfer.state {
+			TransferState::Queued | TransferState::Paused => {
+				entry.transfer.state = TransferState::Cancelled;
+				emit(&app, &entry.transfer);
+				entry.transfer.clone()
+			}
+			// The worker reports and records the transfer once the interrupted attempt returned
+			TransferState::Running => {
+				entry.stop = Some(TransferState::Cancelled);
+				if let Some(operation) = &entry.operation {
+					operation.cancel();
+				}
+				return Ok(true);
+			}
+			_ => return Ok(false),
+		}
+	};
+	record_history(&cancelled)?;
+	Ok(true)
+}
+
+/// Moves a transfer to another position in the queue, transfers that are earlier in the queue start first.
+///
+/// # Arguments
+///
+/// * `id` - The id of the transfer to move.
+/// * `position` - The new index of the transfer, positions past the end move it to the end.
+///
+/// # Returns
+///
+/// `true` if the transfer was moved, `false` if there is no transfer with the id.
+#[tauri::command]
+pub fn move_transfer(id: u64, position: usize, queue: State<TransferQueue>) -> Result<bool, BackendError> {
+	let mut state = queue.lock()?;
+	let index = match state.entries.iter().position(|entry| entry.transfer.id == id) {
+		Some(index) => index,
+		None => return Ok(false),
+	};
+	let entry = state.entries.remove(index);
+	let position = position.min(state.entries.len());
+	state.entries.insert(position, entry);
+	Ok(true)
+}
+
+/// Removes the done, failed and cancelled transfers from the queue, they stay in the transfer history.
+#[tauri::command]
+pub fn clear_finished_transfers(queue: State<TransferQueue>) -> Result<(), BackendError> {
+	queue.lock()?.entries.retain(|entry| !entry.transfer.state.is_finished());
+	Ok(())
+}
+
+/// Returns the finished transfers, the most recent first.
+///
+/// # Arguments
+///
+/// * `limit` - How many transfers to return at most, all of them if `None`.
+#[tauri::command]
+pub fn get_transfer_history(limit: Option<u32>) -> Result<Vec<TransferRecord>, BackendError> {
+	let lite = sqlite::open(get_database_path())?;
+	let mut statement = lite.prepare("SELECT * FROM `transfer_history` ORDER BY `id` DESC LIMIT ?")?;
+	// A negative limit makes SQLite return every row
+	statement.bind((1, limit.map_or(-1, |limit| limit as i64)))?;
+
+	let mut records = Vec::new();
+	while let sqlite::State::Row = statement.next()? {
+		records.push(TransferRecord {
+			id: statement.read::<i64, &str>("id")?,
+			connection_id: statement.read::<i64, &str>("connection_id")? as i32,
+			direction: statement.read::<String, &str>("direction")?,
+			source: statement.read::<String, &str>("source")?,
+			target: statement.read::<String, &str>("target")?,
+			state: statement.read::<String, &str>("state")?,
+			bytes: statement.read::<i64, &str>("bytes")? as u64,
+			attempts: statement.read::<i64, &str>("attempts")? as u32,
+			error: statement.read::<Option<String>, &str>("error")?,
+			finished_at: statement.read::<String, &str>("finished_at")?,
+		});
+	}
+	Ok(records)
+}
+
+/// Removes every transfer from the transfer history.
+#[tauri::command]
+pub fn clear_transfer_history() -> Result<(), BackendError> {
+	let lite = sqlite::open(get_database_path())?;
+	connection_manager::execute(&lite, "DELETE FROM `transfer_history`", &[])
+}
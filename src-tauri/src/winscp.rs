@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::connection_manager::Protocol;
+use crate::error::BackendError;
+use crate::importer::{self, ImportCandidate, ImportPreview};
+
+/// The value every obfuscated password byte is XORed with.
+const PASSWORD_MAGIC: u8 = 0xA3;
+
+/// Marks obfuscated passwords that are prefixed with the user name and host.
+const PASSWORD_FLAG: u8 = 0xFF;
+
+/// The section WinSCP uses for the defaults of new sites, it is not a site itself.
+const DEFAULT_SESSION: &str = "Default Settings";
+
+/// Returns the default location of the WinSCP configuration, as used by an installed WinSCP on Windows.
+fn default_path() -> Result<PathBuf, BackendError> {
+	tauri::api::path::config_dir().map(|config| config.join("WinSCP.ini")).ok_or_else(|| BackendError::not_found("Could not determine the WinSCP settings directory"))
+}
+
+/// Decodes the `%XX` escapes WinSCP uses in section names and values.
+fn percent_decode(value: &str) -> String {
+	let bytes = value.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut index = 0;
+	while index < bytes.len() {
+		let escaped = match (bytes[index], bytes.get(index + 1..index + 3)) {
+			(b'%', Some(hex)) => std::str::from_utf8(hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+			_ => None,
+		};
+		match escaped {
+			Some(byte) => {
+				decoded.push(byte);
+				index += 3;
+			}
+			None => {
+				decoded.push(bytes[index]);
+				index += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Splits an ini file into its sections, keys keep their case and the first value of a key wins.
+fn read_sections(content: &str) -> Vec<(String, HashMap<String, String>)> {
+	let mut sections: Vec<(String, HashMap<String, String>)> = Vec::new();
+	for line in content.lines() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with(';') {
+			continue;
+		}
+		if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+			sections.push((name.to_string(), HashMap::new()));
+		} else if let (Some((key, value)), Some((_, values))) = (line.split_once('='), sections.last_mut()) {
+			values.entry(key.trim().to_string()).or_insert_with(|| value.trim().to_string());
+		}
+	}
+	sections
+}
+
+/// Reverses the obfuscation WinSCP applies to stored passwords.
+///
+/// Every byte is stored as two hex digits, XORed with a constant and inverted. The password is preceded by its
+/// length and some random padding, and newer versions also prefix it with the user name and host.
+///
+/// # Arguments
+///
+/// * `username` - The user name of the site, part of the obfuscation.
+/// * `host` - The host of the site, part of the obfuscation.
+/// * `value` - The `Password` value of the site.
+fn decrypt_password(username: &str, host: &str, value: &str) -> String {
+	let mut bytes = value.as_bytes().chunks(2).filter_map(|pair| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok())).map(|byte| !(byte ^ PASSWORD_MAGIC));
+
+	let flag = bytes.next().unwrap_or(0);
+	let length = if flag == PASSWORD_FLAG {
+		// The byte after the flag is unused
+		bytes.next();
+		bytes.next().unwrap_or(0)
+	} else {
+		flag
+	};
+	let padding = bytes.next().unwrap_or(0) as usize;
+	let password: Vec<u8> = bytes.skip(padding).take(length as usize).collect();
+
+	let key = format!("{}{}", username, host);
+	let password = match password.strip_prefix(key.as_bytes()) {
+		Some(password) if flag == PASSWORD_FLAG => password,
+		_ => &password[..],
+	};
+	String::from_utf8_lossy(password).to_string()
+}
+
+/// Converts a `Sessions\<name>` section into a candidate.
+///
+/// # Arguments
+///
+/// * `name` - The escaped site name, folders are separated by `/`.
+/// * `values` - The values of the section.
+/// * `passwords` - Whether passwords can be decoded, which is not the case with a WinSCP master password.
+///
+/// # Returns
+///
+/// `None` for sites without a host and for protocols that are not supported, e.g. WebDAV or implicit FTPS.
+fn candidate(name: &str, values: &HashMap<String, String>, passwords: bool) -> Option<ImportCandidate> {
+	let value = |key: &str| values.get(key).map(|value| percent_decode(value)).unwrap_or_default();
+	let host = value("HostName");
+	if host.is_empty() {
+		return None;
+	}
+
+	// WinSCP only stores values that differ from its defaults, which are SFTP and no TLS
+	let protocol = match (value("FSProtocol").as_str(), value("Ftps").as_str()) {
+		("" | "0" | "1" | "2", _) => Protocol::Sftp,
+		("5", "" | "0") => Protocol::Ftp,
+		("5", "2" | "3") => Protocol::Ftps,
+		_ => return None,
+	};
+	let port = value("PortNumber").parse().unwrap_or(match protocol {
+		Protocol::Sftp => 22,
+		Protocol::Ftp | Protocol::Ftps => 21,
+	});
+	let username = value("UserName");
+
+	let mut connection = importer::new_connection(&percent_decode(name), &host, port, &username);
+	connection.protocol = i32::from(protocol) as i8;
+	connection.remote_path = value("RemoteDirectory");
+	connection.local_path = value("LocalDirectory");
+	let key_file = value("PublicKeyFile");
+	let mut warning = None;
+	if protocol == Protocol::Sftp && !key_file.is_empty() {
+		warning = importer::use_key_file(&mut connection, &key_file);
+	} else if passwords {
+		connection.password = values.get("Password").map(|password| decrypt_password(&username, &host, password)).unwrap_or_default();
+	}
+	Some(ImportCandidate { connection, jump_host: None, warning })
+}
+
+/// Reads the sites of a WinSCP configuration file.
+fn read_candidates(content: &str) -> Vec<ImportCandidate> {
+	let sections = read_sections(content);
+	let passwords = !sections.iter().any(|(name, values)| name == "Configuration\\Security" && values.get("UseMasterPassword").map(String::as_str) == Some("1"));
+	sections
+		.iter()
+		.filter_map(|(section, values)| section.strip_prefix("Sessions\\").map(|name| (name, values)))
+		.filter(|(name, _)| percent_decode(name) != DEFAULT_SESSION)
+		.filter_map(|(name, values)| candidate(name, values, passwords))
+		.collect()
+}
+
+/// Imports the sites of a WinSCP configuration file (`WinSCP.ini`) as connections.
+///
+/// Folders are kept as part of the connection name, e.g. `Work/Web server`. Passwords are imported unless they are
+/// protected with a WinSCP master password. Existing connections are matched by name and updated.
+///
+/// # Arguments
+///
+/// * `path` - The `WinSCP.ini` to read, defaults to the one in the roaming profile of the current user.
+/// * `commit` - `false` only returns the preview, `true` adds and updates the connections.
+/// * `sites` - The names of the sites to import, including their folders, all sites are imported if not given.
+///
+/// # Returns
+///
+/// What is, or would be, added, updated or left unchanged for every site.
+#[tauri::command]
+pub fn import_winscp(path: Option<String>, commit: bool, sites: Option<Vec<String>>) -> Result<Vec<ImportPreview>, BackendError> {
+	let path = match path {
+		Some(path) => PathBuf::from(path),
+		None => default_path()?,
+	};
+	let content = fs::read_to_string(&path).map_err(|e| BackendError::io(&format!("Error reading {}", path.display()), &e))?;
+	let mut candidates = read_candidates(&content);
+	if let Some(sites) = sites {
+		candidates.retain(|candidate| sites.contains(&candidate.connection.name));
+	}
+
+	if commit {
+		importer::apply(candidates)
+	} else {
+		importer::preview(&candidates)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::connection_manager::AuthMethod;
+
+	/// `s3cret!` as WinSCP stores it for `admin@example.com`, prefixed with the user and host and padded to 50 bytes.
+	const PASSWORD: &str = "A35C4B5F0606063D3831353239243D312C3039723F33312F6F3F2E39287D4D7E6F184D7E6F184D7E6F184D7E6F184D7E6F18";
+
+	/// `legacy` in the format of older WinSCP versions, without the flag and the user and host prefix.
+	const LEGACY_PASSWORD: &str = "5A5E060630393B3D3F254D7E";
+
+	fn find<'a>(candidates: &'a [ImportCandidate], name: &str) -> &'a ImportCandidate {
+		candidates.iter().find(|candidate| candidate.connection.name == name).unwrap()
+	}
+
+	#[test]
+	fn passwords_are_decrypted() {
+		assert_eq!(decrypt_password("admin", "example.com", PASSWORD), "s3cret!");
+		assert_eq!(decrypt_password("admin", "example.com", LEGACY_PASSWORD), "legacy");
+	}
+
+	#[test]
+	fn sites_are_read() {
+		let content = format!(concat!(
+			"[Sessions\\Default%20Settings]\n",
+			"HostName=default.example.com\n",
+			"[Sessions\\Work/Web%20server]\n",
+			"HostName=example.com\n",
+			"UserName=admin\n",
+			"PortNumber=2222\n",
+			"Password={}\n",
+			"RemoteDirectory=/var/www\n",
+			"[Sessions\\Explicit]\n",
+			"HostName=ftp.example.com\n",
+			"FSProtocol=5\n",
+			"Ftps=3\n",
+			"[Sessions\\Plain]\n",
+			"HostName=ftp.example.com\n",
+			"FSProtocol=5\n",
+			"[Sessions\\Implicit]\n",
+			"HostName=ftp.example.com\n",
+			"FSProtocol=5\n",
+			"Ftps=1\n",
+			"[Sessions\\No%20host]\n",
+			"UserName=admin\n",
+		), PASSWORD);
+
+		let candidates = read_candidates(&content);
+		let names: Vec<&str> = candidates.iter().map(|candidate| candidate.connection.name.as_str()).collect();
+		assert_eq!(names, ["Work/Web server", "Explicit", "Plain"]);
+
+		let web = &find(&candidates, "Work/Web server").connection;
+		assert_eq!(web.protocol, i32::from(Protocol::Sftp) as i8);
+		assert_eq!(web.port, 2222);
+		assert_eq!(web.password, "s3cret!");
+		assert_eq!(web.remote_path, "/var/www");
+
+		let explicit = &find(&candidates, "Explicit").connection;
+		assert_eq!(explicit.protocol, i32::from(Protocol::Ftps) as i8);
+		assert_eq!(explicit.port, 21);
+		assert_eq!(find(&candidates, "Plain").connection.protocol, i32::from(Protocol::Ftp) as i8);
+	}
+
+	#[test]
+	fn passwords_protected_by_a_master_password_are_skipped() {
+		let content = format!("[Configuration\\Security]\nUseMasterPassword=1\n[Sessions\\Site]\nHostName=example.com\nUserName=admin\nPassword={}\n", PASSWORD);
+		assert_eq!(read_candidates(&content)[0].connection.password, "");
+	}
+
+	#[test]
+	fn putty_keys_fall_back_to_the_agent() {
+		let content = concat!(
+			"[Sessions\\PuTTY]\n",
+			"HostName=example.com\n",
+			"PublicKeyFile=C:%5Ckeys%5Cid.PPK\n",
+			"[Sessions\\OpenSSH]\n",
+			"HostName=example.com\n",
+			"PublicKeyFile=C:%5Ckeys%5Cid_ed25519\n",
+		);
+
+		let candidates = read_candidates(content);
+		let putty = find(&candidates, "PuTTY");
+		assert_eq!(putty.connection.auth_method, AuthMethod::Agent as i8);
+		assert!(putty.warning.is_some());
+
+		let openssh = find(&candidates, "OpenSSH");
+		assert_eq!(openssh.connection.auth_method, AuthMethod::KeyFile as i8);
+		assert_eq!(openssh.connection.private_key_path, "C:\\keys\\id_ed25519");
+		assert!(openssh.warning.is_none());
+	}
+}
@@ -5,6 +5,15 @@ export enum Protocol
 {
     SFTP = 0,
     FTP = 1,
+    FTPS = 2,
+}
+
+export enum AuthMethod
+{
+    Password = 0,
+    PrivateKey = 1,
+    Agent = 2,
+    KeyFile = 3,
 }
 
 export interface Connection
@@ -23,6 +32,9 @@ export interface Connection
     created_at: Date,
     updated_at: Date,
     last_connected_at: Date,
+    auth_method: AuthMethod,
+    private_key_path: string,
+    jump_host_id: number | null,
 }
 
 export interface File
@@ -30,6 +42,7 @@ export interface File
     path: string,
     filename: string,
     is_dir: boolean,
+    is_symlink: boolean,
     size: number,
     modified: number,
     permissions: number,
@@ -37,6 +50,40 @@ export interface File
     group: number,
 }
 
+export interface AuthPrompt
+{
+    id: number,
+    connection_id: number,
+    host: string,
+    username: string,
+    instructions: string,
+    prompts: { text: string, echo: boolean }[],
+}
+
+/**
+ * An error rejected by a backend command, `kind` tells what went wrong and `message` is meant for the user.
+ * `UnknownHostKey` and `HostKeyMismatch` also carry the key, so the user can be asked whether to trust it.
+ */
+export interface BackendError
+{
+    kind: "AuthFailed" | "UnknownHostKey" | "HostKeyMismatch" | "NotFound" | "PermissionDenied" | "Timeout" | "Cancelled"
+        | "Protocol" | "Database" | "CredentialsLocked" | "InvalidInput" | "Io" | "Other",
+    message: string,
+    host?: string,
+    port?: number,
+    key_type?: string,
+    fingerprint?: string,
+}
+
+export interface CommandResult
+{
+    stdout: string,
+    stderr: string,
+    exit_status: number,
+    exit_signal: string | null,
+    duration: number,
+}
+
 export const EmptyConnection: Connection = {
     id: -1,
     name: "",
@@ -51,7 +98,10 @@ export const EmptyConnection: Connection = {
     protocol: Protocol.SFTP,
     created_at: new Date(),
     updated_at: new Date(),
-    last_connected_at: new Date()
+    last_connected_at: new Date(),
+    auth_method: AuthMethod.Password,
+    private_key_path: "",
+    jump_host_id: null
 };
 
 export default class ConnectionManager
@@ -90,9 +140,11 @@ export default class ConnectionManager
 
     /**
      * Updates the provided connection in the backend.
+     * An empty password or private key keeps the stored one, unless it is cleared explicitly.
      * @param connection - The connection to update.
+     * @param clear - Which stored secrets to remove when the connection has them empty.
      */
-    static async updateConnection(connection: Connection): Promise<void>
+    static async updateConnection(connection: Connection, clear: { password?: boolean, privateKey?: boolean } = {}): Promise<void>
     {
         if (connection.id === EmptyConnection.id)
         {
@@ -100,7 +152,7 @@ export default class ConnectionManager
             return;
         }
         Log.info("Updating connection", connection);
-        await invoke("update_connection", {id: connection.id, connection: {...connection, protocol: connection.protocol}});
+        await invoke("update_connection", {id: connection.id, connection: {...connection, protocol: connection.protocol}, clearPassword: clear.password, clearPrivateKey: clear.privateKey});
         await this.instance.loadConnections();
     }
 
@@ -198,25 +250,44 @@ export default class ConnectionManager
     /**
      * Tests the provided connection to see if it is valid.
      * @param connection - The connection to test.
+     * @returns Whether the connection could be established, the reason it failed is logged.
      */
     static async testConnection(connection: Connection): Promise<boolean>
     {
-        Log.info("Testing connection", connection);
-        const response: boolean = await invoke("test_connection", {options: {...connection, protocol: connection.protocol}});
-        Log.debug("Test connection response", response);
-        return response;
+        Log.info("Testing connection {0}", connection.name);
+        try
+        {
+            const response: boolean = await invoke("test_connection", {options: {...connection, protocol: connection.protocol}});
+            Log.debug("Test connection response", response);
+            return response;
+        } catch (e)
+        {
+            Log.error("Unable to connect to {0}\nError: ", connection.name, e);
+            return false;
+        }
+    }
+
+    /**
+     * Cancels a running network operation, e.g. a listing on a server that stopped responding.
+     * @param operationId - The id that was passed to the operation.
+     * @returns Whether the operation was still running.
+     */
+    static async cancelOperation(operationId: string): Promise<boolean>
+    {
+        return await invoke("cancel_operation", {id: operationId});
     }
 
     /**
      * Retrieves a connection by its id.
      * @param id - The id of the connection to retrieve.
+     * @param includeSecrets - Whether to include the password and private key, e.g. for the edit form.
      */
-    static async getConnectionById(id: number): Promise<Connection>
+    static async getConnectionById(id: number, includeSecrets: boolean = false): Promise<Connection>
     {
         Log.info("Getting connection by id", id);
         try
         {
-            const connection: Connection = await invoke("get_connection_by_id", {id: id});
+            const connection: Connection = await invoke("get_connection_by_id", {id: id, includeSecrets: includeSecrets});
             Log.debug("Found Connection with id of '{0}'", id, connection);
             return connection;
         } catch (e)
@@ -226,6 +297,36 @@ export default class ConnectionManager
         }
     }
 
+    /**
+     * Checks whether a master password is set and whether the stored credentials are unlocked.
+     * Without a master password, `keyring` tells whether the key is kept in the keyring of the operating system,
+     * otherwise the stored secrets are only obfuscated.
+     */
+    static async getCredentialStatus(): Promise<{ protected: boolean, unlocked: boolean, keyring: boolean }>
+    {
+        return await invoke("get_credential_status");
+    }
+
+    /**
+     * Unlocks the stored passwords and private keys with the master password.
+     * @param masterPassword - The master password.
+     */
+    static async unlockCredentials(masterPassword: string): Promise<void>
+    {
+        await invoke("unlock_credentials", {masterPassword: masterPassword});
+        await this.instance.loadConnections();
+    }
+
+    /**
+     * Answers a keyboard-interactive prompt received through the `auth-prompt` event.
+     * @param prompt - The prompt to answer.
+     * @param answers - One answer per question, or null to cancel the authentication.
+     */
+    static async answerAuthPrompt(prompt: AuthPrompt, answers: string[] | null): Promise<void>
+    {
+        await invoke("answer_auth_prompt", {id: prompt.id, answers: answers});
+    }
+
     /**
      * Navigates to the Browser page with the provided connection.
      * @param connection - The connection to navigate to.
@@ -250,8 +351,9 @@ export default class ConnectionManager
      * If no connection is currently active, an error message will be logged and empty string will be returned.<br>
      * <u><i><b>NOTE:</b> This function will only work with SFTP connections.</i></u>
      * @param command
+     * @param operationId - An optional id to cancel the command with, see {@link cancelOperation}.
      */
-    async sendCommand(command: string): Promise<string>
+    async sendCommand(command: string, operationId?: string): Promise<string>
     {
         if (!this.isConnected())
         {
@@ -260,7 +362,8 @@ export default class ConnectionManager
         }
         try
         {
-            return await invoke("send_ssh_command", {command: command, options: {...this.current, protocol: this.current.protocol}});
+            const result: CommandResult = await invoke("send_ssh_command", {command: command, options: {...this.current, protocol: this.current.protocol}, operationId: operationId});
+            return result.stdout;
         } catch (e)
         {
             Log.error("Failed to send command:", e);
@@ -271,8 +374,9 @@ export default class ConnectionManager
     /**
      * Lists the contents of the provided directory for the current connection.
      * @param path
+     * @param operationId - An optional id to cancel the listing with, see {@link cancelOperation}.
      */
-    async listDirectory(path: string): Promise<File[]>
+    async listDirectory(path: string, operationId?: string): Promise<File[]>
     {
         if (!this.isConnected())
         {
@@ -281,7 +385,7 @@ export default class ConnectionManager
         }
         try
         {
-            let files: File[] = await invoke("list", {path: path, showHidden: true, options: {...this.current, protocol: this.current.protocol}});
+            let files: File[] = await invoke("list", {path: path, showHidden: true, options: {...this.current, protocol: this.current.protocol}, operationId: operationId});
             files =  files.filter(i => i.filename !== "." && i.filename !== ".."); // Filter out the current and parent directory.
             files.push(...files)
             files.push(...files)
@@ -41,9 +41,23 @@ export interface LogSettings
 
 }
 
+export interface NetworkSettings
+{
+    connect_timeout: number; // seconds, 0 waits forever
+    read_timeout: number; // seconds, 0 waits forever
+}
+
+export interface TransferSettings
+{
+    parallel_transfers: number; // transfers per connection that run at once
+    max_retries: number; // retries before a transfer is marked as failed
+}
+
 export interface AppSettings
 {
     general_settings: GeneralSettings;
+    network_settings?: NetworkSettings;
+    transfer_settings?: TransferSettings;
 }
 
 export let currentSettings: AppSettings = {
@@ -1,4 +1,4 @@
-import ConnectionManager, {Connection, EmptyConnection, Protocol} from "../../assets/ts/ConnectionManager.ts";
+import ConnectionManager, {Connection, EmptyConnection} from "../../assets/ts/ConnectionManager.ts";
 import {ReactNode, useState} from "react";
 import {Autocomplete, AutocompleteItem, Button, Divider, Input, Select, SelectItem, Spinner} from "@nextui-org/react";
 import SwitchOption from "../SwitchSetting.tsx";
@@ -110,7 +110,7 @@ export default function Details(props: { connection: Connection })
                 }}>
                     {uniquePorts.map(port => <AutocompleteItem key={port} value={port}>{port}</AutocompleteItem>)}
                 </Autocomplete>
-                <Select label={"Protocol"} description={"The connection protocol that will be used."} defaultSelectedKeys={[connection.protocol === Protocol.SFTP ? "0" : "1"]} className={"w-[30%] min-w-[100px]"} onSelectionChange={async keys =>
+                <Select label={"Protocol"} description={"The connection protocol that will be used."} defaultSelectedKeys={[connection.protocol.toString()]} className={"w-[30%] min-w-[100px]"} onSelectionChange={async keys =>
                 {
                     const temp: Connection = {...connection, protocol: Number.parseInt([...keys][0] as string)};
                     if (!isNewConnection)
@@ -119,6 +119,7 @@ export default function Details(props: { connection: Connection })
                 }}>
                     <SelectItem key={"0"}>SFTP</SelectItem>
                     <SelectItem key={"1"}>FTP</SelectItem>
+                    <SelectItem key={"2"}>FTPS</SelectItem>
                 </Select>
             </div>
             <h2 className={"text-lg font-bold"}>Authentication</h2>
@@ -145,7 +146,7 @@ export default function Details(props: { connection: Connection })
                 {
                     const temp: Connection = {...connection, password: e.target.value};
                     if (!isNewConnection)
-                        await ConnectionManager.updateConnection(temp);
+                        await ConnectionManager.updateConnection(temp, {password: e.target.value === ""});
                     setConnection(temp);
                 }}/>
             </div>
@@ -160,7 +161,7 @@ export default function Details(props: { connection: Connection })
                 {
                     const temp: Connection = {...connection, private_key: content};
                     if (!isNewConnection)
-                        await ConnectionManager.updateConnection(temp);
+                        await ConnectionManager.updateConnection(temp, {privateKey: content === ""});
                     setConnection(temp);
                 }}/>
 
@@ -36,11 +36,11 @@ export default function RemoteDirectoryTable({path, onPathChange, connection}: {
             }
             Log.debug("Loading path: {0}, Connection:", path, connection);
             // const files = await ConnectionManager.instance.listDirectory(path);
-            const files:File[] = [{path: "/test", filename: "test", is_dir: true, modified: 84641300, size: 846413, owner: 0x0077777, group: 0x0077777, permissions: 0x0077777},];
+            const files:File[] = [{path: "/test", filename: "test", is_dir: true, is_symlink: false, modified: 84641300, size: 846413, owner: 0x0077777, group: 0x0077777, permissions: 0x0077777},];
             for(let i = 0; i < 45; i++)
             {
                 const rand = Math.random();
-                files.push({path: `/test${rand}`, filename: `test${rand}`, is_dir: rand >= .25, modified: 84641300/rand, size: 846413/rand, owner: 0x0077777, group: 0x0077777, permissions: 0x0077777});
+                files.push({path: `/test${rand}`, filename: `test${rand}`, is_dir: rand >= .25, is_symlink: false, modified: 84641300/rand, size: 846413/rand, owner: 0x0077777, group: 0x0077777, permissions: 0x0077777});
             }
             Log.debug("Files:", files);
             onPathChange(path);
@@ -1,8 +1,32 @@
+import {useEffect, useState} from "react";
+import ConnectionManager from "../../assets/ts/ConnectionManager.ts";
+
+/**
+ * Describes how the stored passwords and private keys are protected.
+ */
+function describeCredentialStatus(status: { protected: boolean, unlocked: boolean, keyring: boolean }): string
+{
+    if (status.protected)
+        return "Stored passwords and private keys are encrypted with your master password.";
+    if (!status.unlocked)
+        return "The key of the stored passwords and private keys could not be read from the keyring of the operating system, they stay locked until it is available.";
+    if (status.keyring)
+        return "Stored passwords and private keys are encrypted with a key kept in the keyring of the operating system.";
+    return "No keyring of the operating system is available, stored passwords and private keys are only obfuscated. Anyone who can read the connection database can decrypt them.";
+}
+
 export default function Connections()
 {
+    const [description, setDescription] = useState<string>("");
+    useEffect(() =>
+    {
+        ConnectionManager.getCredentialStatus().then(status => setDescription(describeCredentialStatus(status)));
+    }, []);
+
     return (
-        <div>
-            <h1>Connections</h1>
+        <div className={"flex flex-col"}>
+            <h1 className={"text-4xl mb-4"}>Connections</h1>
+            <p>{description}</p>
         </div>
     );
-}
\ No newline at end of file
+}